@@ -1,14 +1,14 @@
 #[cfg(not(target_os = "windows"))]
-use alith::inference::{LlamaEngine, serve::run};
+use alith::inference::{LlamaEngine, serve::ServerBuilder};
 
 #[cfg(not(target_os = "windows"))]
 #[tokio::main]
 async fn main() -> Result<(), anyhow::Error> {
-    run(
-        None,
-        LlamaEngine::new("/root/models/qwen2.5-1.5b-instruct-q5_k_m.gguf").await?,
-    )
-    .await?;
+    let engine = LlamaEngine::new("/root/models/qwen2.5-1.5b-instruct-q5_k_m.gguf").await?;
+    // Serve requests in parallel on all the llama.cpp contexts.
+    ServerBuilder::with_replicas(engine.contexts())
+        .run()
+        .await?;
     // Run the server and run the following command to test the server
     /*
     curl http://localhost:8000/v1/chat/completions \
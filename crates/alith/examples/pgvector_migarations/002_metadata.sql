@@ -0,0 +1,2 @@
+-- store a metadata object with each document
+ALTER TABLE alith ADD COLUMN IF NOT EXISTS metadata jsonb NOT NULL DEFAULT '{}'::jsonb;
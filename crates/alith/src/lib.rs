@@ -18,9 +18,13 @@ pub use core::llm::{
 };
 pub use core::{
     agent::Agent,
+    approval::{ApprovalDecision, ApprovalRequest, Approver, ChannelApprover, PendingApproval},
+    budget::{Budget, BudgetError, BudgetUsage},
+    cancel::{Cancellation, CancellationToken, current_token},
     chat::{
-        Chat, Completion, CompletionError, Message as ChatMessage, Request, ResponseContent,
-        ResponseTokenUsage, ResponseToolCalls, ToolCall,
+        Chat, Completion, CompletionError, JsonSchemaFormat, Message as ChatMessage, Request,
+        ResponseContent, ResponseFormat, ResponseTokenUsage, ResponseToolCalls, TokenUsage,
+        ToolCall,
     },
     chunking::{
         ChunkError, Chunker, ChunkerConfig, ChunkerResult, DEFAULT_CHUNK_SIZE, TextChunker,
@@ -30,12 +34,14 @@ pub use core::{
         TextCleaner, normalize_whitespace, reduce_to_single_whitespace, strip_unwanted_chars,
     },
     concatenator::{TextConcatenator, TextConcatenatorTrait},
+    context::{ContextError, ContextPart, ContextPolicy, ContextReport, count_tokens},
     embeddings::{Embed, EmbedError, Embeddings, EmbeddingsBuilder, EmbeddingsData, TextEmbedder},
     extractor::{ExtractionError, Extractor},
     flow::{
         Action, Content, DefaultNode, EmptyAction, EnvVar, Graph, InChannels, Node, NodeId,
         NodeName, NodeTable, OutChannels, Output, RecvErr, SendErr, auto_node, dependencies,
     },
+    hook::{Hook, ToolCallDecision},
     json::{
         JsonParseError, parse_and_check_json_markdown, parse_json_markdown, parse_partial_json,
     },
@@ -51,9 +57,23 @@ pub use core::{
         Separator, SeparatorGroup, SplitError, TextSplit, TextSplitter, split_markdown, split_text,
         split_text_into_indices,
     },
-    store::{DocumentId, InMemoryStorage, Storage, TopNResults, VectorStoreError},
+    store::{
+        DocumentId, Filter as StoreFilter, Fusion, HybridSearch, HybridStorage, InMemoryStorage,
+        KeywordIndex, Metadata as DocumentMetadata, Range as StoreRange, Storage, StorageDocument,
+        TopNResults, VectorStoreError,
+    },
+    strategy::{
+        DEFAULT_MAX_REVISIONS, DEFAULT_MAX_STEPS, Direct, FINAL_ANSWER, PlanAndExecute, ReAct,
+        STEP_FAILED, Step, Strategy, Trace,
+    },
+    structured::{DEFAULT_MAX_REPAIRS, StructuredOutputError},
     task::{Task, TaskError, TaskMetadata},
-    tool::{StructureTool, Tool, ToolChoice, ToolDefinition, ToolError},
+    team::{
+        AgentRegistry, AgentTask, AgentTool, DEFAULT_MAX_HANDOFFS, HANDOFF_TOOL_NAME, Handoff,
+        TaskInput,
+    },
+    tool::{RetryPolicy, StructureTool, Tool, ToolChoice, ToolDefinition, ToolError},
+    usage::{AgentResponse, CallUsage, ModelPrice, PriceTable, UsageStats, UsageTracker},
 };
 
 pub use knowledge::{
@@ -1,7 +1,7 @@
 use alith_interface::{
     llms::LLMBackend,
     requests::{
-        completion::{CompletionRequest, CompletionResponse},
+        completion::{CompletionRequest, CompletionResponse, CompletionStream},
         logit_bias::{LogitBias, LogitBiasTrait},
         req_components::{RequestConfig, RequestConfigTrait},
     },
@@ -34,6 +34,12 @@ impl BasicCompletion {
         Ok(self.base_req.request().await?)
     }
 
+    /// Runs the request and streams the response chunks as they are generated.
+    #[inline]
+    pub async fn stream(&mut self) -> crate::Result<CompletionStream> {
+        Ok(self.base_req.stream().await?)
+    }
+
     pub fn parse_response(&self, content: &str) -> crate::Result<String> {
         if content.is_empty() {
             return Err(anyhow::format_err!(
@@ -1,17 +1,31 @@
-use crate::chat::{Chat, Completion, Document, Message, Request};
-use crate::executor::Executor;
+use crate::approval::Approver;
+use crate::budget::Budget;
+use crate::cancel::{self, Cancellation};
+use crate::chat::{
+    self, Chat, ChatStream, Completion, CompletionChunk, Document, Message, Request,
+};
+use crate::context::ContextPolicy;
+use crate::executor::{DEFAULT_MAX_ITERATIONS, DEFAULT_TOOL_PARALLELISM, Executor};
+use crate::hook::Hook;
 use crate::knowledge::Knowledge;
 use crate::mcp::{MCPClient, MCPError, setup_mcp_clients, sse_client, stdio_client};
 use crate::memory::Memory;
 use crate::store::{Storage, VectorStoreError};
+use crate::strategy::{Direct, Strategy, Trace};
+use crate::structured::{self, DEFAULT_MAX_REPAIRS, StructuredOutputError};
 use crate::task::TaskError;
+use crate::team::{AgentTool, TaskInput};
 use crate::tool::Tool;
+use crate::usage::{AgentResponse, PriceTable, UsageTracker};
 use crate::{Ref, make_ref};
 use async_trait::async_trait;
 use futures::{StreamExt, TryStreamExt, stream};
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
 use std::collections::HashMap;
 use std::path::Path;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::RwLock;
 use uuid::Uuid;
 
@@ -36,6 +50,30 @@ pub struct Agent<M: Completion> {
     pub temperature: Option<f32>,
     /// Maximum number of tokens for the completion.
     pub max_tokens: Option<usize>,
+    /// Maximum number of model calls in one tool calling loop.
+    pub max_iterations: usize,
+    /// The policy fitting the requests into the context window of the model.
+    pub context_policy: Option<ContextPolicy>,
+    /// Maximum number of tool calls of one model response run concurrently.
+    pub tool_parallelism: usize,
+    /// Maximum duration of a tool call, for the tools without their own timeout.
+    pub tool_timeout: Option<Duration>,
+    /// Hooks invoked around model requests and tool calls, in order.
+    pub hooks: Vec<Arc<dyn Hook>>,
+    /// Approver of the calls to the tools requiring approval.
+    pub approver: Option<Arc<dyn Approver>>,
+    /// Maximum number of times the model is re-prompted with the errors of a structured output.
+    pub max_repairs: usize,
+    /// Reasoning strategy answering the prompts, a single turn when not set.
+    pub strategy: Option<Arc<dyn Strategy<M>>>,
+    /// Prices used to estimate the cost of the model calls.
+    pub price_table: Option<Arc<PriceTable>>,
+    /// Usage of the model calls made by the agent.
+    pub usage: UsageTracker,
+    /// Shared trackers also counting the usage of the agent, e.g. per session or user.
+    pub usage_trackers: Vec<UsageTracker>,
+    /// Limits on the tokens, tool calls and requests of the agent.
+    pub budget: Option<Budget>,
     /// The MCP client used to communicate with the MCP server
     mcp_clients: Ref<Vec<MCPClient>>,
 }
@@ -55,6 +93,18 @@ where
             preamble: String::new(),
             temperature: None,
             max_tokens: None,
+            max_iterations: DEFAULT_MAX_ITERATIONS,
+            context_policy: None,
+            tool_parallelism: DEFAULT_TOOL_PARALLELISM,
+            tool_timeout: None,
+            hooks: Vec::new(),
+            approver: None,
+            max_repairs: DEFAULT_MAX_REPAIRS,
+            strategy: None,
+            price_table: None,
+            usage: UsageTracker::new(),
+            usage_trackers: Vec::new(),
+            budget: None,
             knowledges: Arc::new(Vec::new()),
             memory: None,
             mcp_clients: make_ref(vec![]),
@@ -75,6 +125,18 @@ where
             preamble: String::new(),
             temperature: None,
             max_tokens: None,
+            max_iterations: DEFAULT_MAX_ITERATIONS,
+            context_policy: None,
+            tool_parallelism: DEFAULT_TOOL_PARALLELISM,
+            tool_timeout: None,
+            hooks: Vec::new(),
+            approver: None,
+            max_repairs: DEFAULT_MAX_REPAIRS,
+            strategy: None,
+            price_table: None,
+            usage: UsageTracker::new(),
+            usage_trackers: Vec::new(),
+            budget: None,
             knowledges: Arc::new(Vec::new()),
             memory: None,
             mcp_clients: make_ref(vec![]),
@@ -108,6 +170,12 @@ where
         self
     }
 
+    /// Adds a memory shared with other agents, e.g. a session memory of a [`crate::memory::MemoryStore`].
+    pub fn shared_memory(mut self, memory: Ref<dyn Memory>) -> Self {
+        self.memory = Some(memory);
+        self
+    }
+
     /// Adds a storage index to the agent.
     pub fn store_index(mut self, sample: usize, store: impl Storage + 'static) -> Self {
         self.store_indices.push((sample, Box::new(store)));
@@ -120,6 +188,77 @@ where
         self
     }
 
+    /// Set the maximum number of model calls in one tool calling loop.
+    pub fn max_iterations(mut self, max_iterations: usize) -> Self {
+        self.max_iterations = max_iterations;
+        self
+    }
+
+    /// Set the maximum number of tool calls of one model response run concurrently.
+    pub fn tool_parallelism(mut self, tool_parallelism: usize) -> Self {
+        self.tool_parallelism = tool_parallelism;
+        self
+    }
+
+    /// Set the maximum duration of a tool call, for the tools without their own timeout.
+    pub fn tool_timeout(mut self, tool_timeout: Duration) -> Self {
+        self.tool_timeout = Some(tool_timeout);
+        self
+    }
+
+    /// Adds a hook invoked around model requests and tool calls, after the hooks already added.
+    pub fn hook(mut self, hook: impl Hook + 'static) -> Self {
+        self.hooks.push(Arc::new(hook));
+        self
+    }
+
+    /// Set the approver of the calls to the tools requiring approval, which are rejected
+    /// without an approver.
+    pub fn approver(mut self, approver: impl Approver + 'static) -> Self {
+        self.approver = Some(Arc::new(approver));
+        self
+    }
+
+    /// Set the maximum number of times the model is re-prompted with the errors of a
+    /// structured output, see [`Agent::prompt_typed`].
+    pub fn max_repairs(mut self, max_repairs: usize) -> Self {
+        self.max_repairs = max_repairs;
+        self
+    }
+
+    /// Set the reasoning strategy answering the prompts, e.g. [`crate::strategy::ReAct`].
+    pub fn strategy(mut self, strategy: impl Strategy<M> + 'static) -> Self {
+        self.strategy = Some(Arc::new(strategy));
+        self
+    }
+
+    /// Set the prices used to estimate the cost of the model calls.
+    pub fn price_table(mut self, price_table: PriceTable) -> Self {
+        self.price_table = Some(Arc::new(price_table));
+        self
+    }
+
+    /// Add a shared tracker counting the usage of the agent, e.g. to count the usage of
+    /// a session across several agents.
+    pub fn usage_tracker(mut self, usage_tracker: UsageTracker) -> Self {
+        self.usage_trackers.push(usage_tracker);
+        self
+    }
+
+    /// Set the limits on the tokens, tool calls and requests of the agent. Exceeding
+    /// them fails the turn with [`TaskError::BudgetExceeded`].
+    pub fn budget(mut self, budget: Budget) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Set the policy trimming the history, documents and knowledges of a request that
+    /// doesn't fit the context window of the model.
+    pub fn context_policy(mut self, context_policy: ContextPolicy) -> Self {
+        self.context_policy = Some(context_policy);
+        self
+    }
+
     /// Set the MCP client.
     pub async fn mcp_client(self, mcp_client: MCPClient) -> Self {
         let mut mcp_clients = self.mcp_clients.write().await;
@@ -177,36 +316,181 @@ where
     }
 }
 
-#[async_trait]
-impl<M: Completion + Send + Sync> Chat for Agent<M> {
-    /// Processes a prompt using the agent.
-    async fn prompt(&self, prompt: &str) -> Result<String, TaskError> {
-        // Add chat conversion history.
-        let history = if let Some(memory) = &self.memory {
-            let memory = memory.read().await;
-            memory
-                .messages()
-                .iter()
-                .map(|m| Message {
-                    role: m.message_type.type_string(),
-                    content: m.content.clone(),
-                })
-                .collect()
+impl<M: Completion + Send + Sync + 'static> Agent<M> {
+    /// Exposes the agent as a tool named after it, so a supervisor agent can delegate
+    /// tasks to it. The agent name must be a valid tool name, e.g. `billing_agent`.
+    pub fn into_tool<I: TaskInput>(self, description: impl ToString) -> AgentTool<I> {
+        AgentTool::new(self.name.clone(), description, self)
+    }
+}
+
+impl<M: Completion + Send + Sync> Agent<M> {
+    /// Returns the chat conversion history stored in the memory, with the tool calls
+    /// and their results.
+    pub(crate) async fn memory_history(&self) -> Vec<Message> {
+        if let Some(memory) = &self.memory {
+            chat::memory_history(&memory.read().await.messages())
         } else {
             vec![]
+        }
+    }
+
+    /// Processes a prompt using the agent in a single turn and returns the answer with
+    /// the tool calls made, the token usage, the latency and the estimated cost.
+    pub async fn prompt_response(&self, prompt: &str) -> Result<AgentResponse, TaskError> {
+        let history = self.memory_history().await;
+        self.chat_response(prompt, history).await
+    }
+
+    /// Processes a prompt and history using the agent in a single turn and returns the
+    /// answer with the tool calls made, the token usage, the latency and the estimated cost.
+    pub async fn chat_response(
+        &self,
+        prompt: &str,
+        history: Vec<Message>,
+    ) -> Result<AgentResponse, TaskError> {
+        let (mut executor, req) = self.prepare_chat(prompt, history).await?;
+        executor.invoke_response(req).await.map_err(task_error)
+    }
+
+    /// Processes a prompt using the agent until the cancellation is cancelled or its
+    /// deadline passes, failing with [`TaskError::Cancelled`] or
+    /// [`TaskError::DeadlineExceeded`] when stopped.
+    ///
+    /// The agents prompted during the turn, e.g. the agents called as tools, are stopped
+    /// with it.
+    pub async fn prompt_with_cancellation(
+        &self,
+        prompt: &str,
+        cancellation: Cancellation,
+    ) -> Result<String, TaskError> {
+        let history = self.memory_history().await;
+        self.chat_with_cancellation(prompt, history, cancellation)
+            .await
+    }
+
+    /// Processes a prompt and history using the agent until the cancellation is cancelled
+    /// or its deadline passes, see [`Agent::prompt_with_cancellation`].
+    pub async fn chat_with_cancellation(
+        &self,
+        prompt: &str,
+        history: Vec<Message>,
+        cancellation: Cancellation,
+    ) -> Result<String, TaskError> {
+        cancellation.scope(self.chat(prompt, history)).await
+    }
+
+    /// Processes a prompt using the agent and returns the recorded steps of its strategy,
+    /// ending with the answer.
+    pub async fn prompt_traced(&self, prompt: &str) -> Result<Trace, TaskError> {
+        let history = self.memory_history().await;
+        self.chat_traced(prompt, history).await
+    }
+
+    /// Processes a prompt and history using the agent and returns the recorded steps of
+    /// its strategy, ending with the answer.
+    pub async fn chat_traced(
+        &self,
+        prompt: &str,
+        history: Vec<Message>,
+    ) -> Result<Trace, TaskError> {
+        match &self.strategy {
+            Some(strategy) => strategy.run(self, prompt, history).await,
+            None => Direct.run(self, prompt, history).await,
+        }
+    }
+
+    /// Processes a prompt using the agent and parses the answer as a `T`.
+    ///
+    /// The model is asked to answer with JSON matching the schema of `T`, natively when it
+    /// supports JSON schemas. The answer is parsed leniently and validated against the schema,
+    /// and the model is re-prompted with the errors up to [`Agent::max_repairs`] times.
+    ///
+    /// Only the prompt and the valid answer are stored into the memory, not the invalid
+    /// answers and the repair prompts.
+    pub async fn prompt_typed<T>(&self, prompt: &str) -> Result<T, StructuredOutputError>
+    where
+        T: JsonSchema + DeserializeOwned,
+    {
+        let schema = structured::schema_of::<T>();
+        let response_format = match self.model.read().await.supports_json_schema() {
+            true => structured::response_format(&schema),
+            false => None,
         };
-        self.chat(prompt, history).await
+        let mut history = self.memory_history().await;
+        let mut attempt = prompt.to_string();
+        let mut errors = String::new();
+        for _ in 0..=self.max_repairs {
+            let (executor, mut req) = self.prepare_chat(&attempt, history.clone()).await?;
+            req.preamble = [req.preamble.as_str(), &structured::instructions(&schema)]
+                .join("\n\n")
+                .trim()
+                .to_string();
+            req.response_format = response_format.clone();
+            let output = executor
+                .without_memory()
+                .invoke(req)
+                .await
+                .map_err(task_error)?;
+            match structured::parse_typed(&output, &schema) {
+                Ok(value) => {
+                    if let Some(memory) = &self.memory {
+                        let mut memory = memory.write().await;
+                        memory.add_user_message(prompt);
+                        memory.add_ai_message(&output);
+                    }
+                    return Ok(value);
+                }
+                Err(err) => errors = err,
+            }
+            history.push(Message::new("user", &attempt));
+            history.push(Message::new("assistant", &output));
+            attempt = structured::repair_prompt(&errors);
+        }
+        Err(StructuredOutputError::InvalidOutput {
+            attempts: self.max_repairs + 1,
+            errors,
+        })
     }
 
-    /// Processes a prompt using the agent.
-    async fn chat(&self, prompt: &str, history: Vec<Message>) -> Result<String, TaskError> {
+    /// Builds the executor and the request of a chat turn.
+    pub(crate) async fn prepare_chat(
+        &self,
+        prompt: &str,
+        history: Vec<Message>,
+    ) -> Result<(Executor<M>, Request), TaskError> {
         let mut executor = Executor::new(
             self.model.clone(),
             self.knowledges.clone(),
             self.tools.clone(),
             self.memory.clone(),
             self.mcp_clients.clone(),
-        );
+        )
+        .with_max_iterations(self.max_iterations)
+        .with_tool_parallelism(self.tool_parallelism)
+        .with_hooks(self.hooks.clone())
+        .with_usage_tracker(self.usage.clone());
+        for usage_tracker in &self.usage_trackers {
+            executor = executor.with_usage_tracker(usage_tracker.clone());
+        }
+        if let Some(price_table) = &self.price_table {
+            executor = executor.with_price_table(price_table.clone());
+        }
+        if let Some(budget) = &self.budget {
+            executor = executor.with_budget(budget.clone());
+        }
+        if let Some(cancellation) = cancel::current() {
+            executor = executor.with_cancellation(cancellation);
+        }
+        if let Some(approver) = &self.approver {
+            executor = executor.with_approver(approver.clone());
+        }
+        if let Some(tool_timeout) = self.tool_timeout {
+            executor = executor.with_tool_timeout(tool_timeout);
+        }
+        if let Some(context_policy) = &self.context_policy {
+            executor = executor.with_context_policy(context_policy.clone());
+        }
         let mut req = Request::new(prompt.to_string(), self.preamble.clone());
         req.history = history;
         req.max_tokens = self.max_tokens;
@@ -244,11 +528,62 @@ impl<M: Completion + Send + Sync> Chat for Agent<M> {
             .await
             .map_err(|err| TaskError::ExecutionError(err.to_string()))?;
 
-        let response = executor
-            .invoke(req)
-            .await
-            .map_err(|err| TaskError::ExecutionError(err.to_string()))?;
+        Ok((executor, req))
+    }
+}
+
+#[async_trait]
+impl<M: Completion + Send + Sync> Chat for Agent<M> {
+    /// Processes a prompt using the agent.
+    async fn prompt(&self, prompt: &str) -> Result<String, TaskError> {
+        // Add chat conversion history.
+        let history = self.memory_history().await;
+        self.chat(prompt, history).await
+    }
+
+    /// Processes a prompt using the agent.
+    ///
+    /// The turn is only cancelled through [`Agent::chat_with_cancellation`] and
+    /// [`Agent::prompt_with_cancellation`], or with the turn calling the agent as a tool,
+    /// see [`AgentTool`].
+    async fn chat(&self, prompt: &str, history: Vec<Message>) -> Result<String, TaskError> {
+        if let Some(strategy) = &self.strategy {
+            let trace = strategy.run(self, prompt, history).await?;
+            return Ok(trace.answer().to_string());
+        }
+        let (mut executor, req) = self.prepare_chat(prompt, history).await?;
+        executor.invoke(req).await.map_err(task_error)
+    }
+
+    /// Processes a prompt using the agent and streams the response.
+    async fn prompt_stream<'a>(&'a self, prompt: &'a str) -> Result<ChatStream<'a>, TaskError> {
+        // Add chat conversion history.
+        let history = self.memory_history().await;
+        self.chat_stream(prompt, history).await
+    }
+
+    /// Processes a prompt using the agent and streams the response.
+    ///
+    /// With a strategy, the answer is yielded as a single chunk once the strategy ends.
+    async fn chat_stream<'a>(
+        &'a self,
+        prompt: &'a str,
+        history: Vec<Message>,
+    ) -> Result<ChatStream<'a>, TaskError> {
+        if self.strategy.is_some() {
+            let answer = self.chat(prompt, history).await?;
+            return Ok(Box::pin(stream::iter([Ok(CompletionChunk::Text(answer))])));
+        }
+        let (executor, req) = self.prepare_chat(prompt, history).await?;
+        let stream = executor.invoke_stream(req).await.map_err(task_error)?;
+        Ok(Box::pin(stream.map(|chunk| chunk.map_err(task_error))))
+    }
+}
 
-        Ok(response)
+/// Converts an executor error into a task error, keeping typed task errors.
+pub(crate) fn task_error(err: anyhow::Error) -> TaskError {
+    match err.downcast::<TaskError>() {
+        Ok(err) => err,
+        Err(err) => TaskError::ExecutionError(err.to_string()),
     }
 }
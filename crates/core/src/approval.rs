@@ -0,0 +1,100 @@
+use async_trait::async_trait;
+use std::future::Future;
+use tokio::sync::{mpsc, oneshot};
+
+/// A call to a tool requiring approval, see [`crate::tool::Tool::requires_approval`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApprovalRequest {
+    /// The ID of the tool call.
+    pub tool_call_id: String,
+    /// The name of the called tool.
+    pub tool_name: String,
+    /// The JSON arguments of the call.
+    pub arguments: String,
+}
+
+/// The answer to an [`ApprovalRequest`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ApprovalDecision {
+    /// Run the tool call.
+    Approve,
+    /// Don't run the tool call and send the reason back to the model.
+    Reject(String),
+}
+
+/// Approves or rejects the calls to the tools requiring approval.
+///
+/// The executor waits for the answer before running the call. Closures returning a
+/// future are approvers, and [`ChannelApprover`] forwards the requests to a channel.
+#[async_trait]
+pub trait Approver: Send + Sync {
+    async fn approve(&self, request: ApprovalRequest) -> ApprovalDecision;
+}
+
+#[async_trait]
+impl<F, Fut> Approver for F
+where
+    F: Fn(ApprovalRequest) -> Fut + Send + Sync,
+    Fut: Future<Output = ApprovalDecision> + Send,
+{
+    async fn approve(&self, request: ApprovalRequest) -> ApprovalDecision {
+        self(request).await
+    }
+}
+
+/// An approval request waiting for an answer, received from a [`ChannelApprover`].
+///
+/// Dropping it without answering rejects the call.
+#[derive(Debug)]
+pub struct PendingApproval {
+    pub request: ApprovalRequest,
+    responder: oneshot::Sender<ApprovalDecision>,
+}
+
+impl PendingApproval {
+    /// Approves the tool call.
+    pub fn approve(self) {
+        self.answer(ApprovalDecision::Approve);
+    }
+
+    /// Rejects the tool call, the reason is sent back to the model.
+    pub fn reject(self, reason: impl ToString) {
+        self.answer(ApprovalDecision::Reject(reason.to_string()));
+    }
+
+    /// Answers the approval request.
+    pub fn answer(self, decision: ApprovalDecision) {
+        // The agent may have stopped waiting, e.g. when the turn was dropped.
+        let _ = self.responder.send(decision);
+    }
+}
+
+/// An approver sending the approval requests as [`PendingApproval`]s to a channel, e.g.
+/// to show them to a user in another task.
+#[derive(Debug, Clone)]
+pub struct ChannelApprover {
+    sender: mpsc::Sender<PendingApproval>,
+}
+
+impl ChannelApprover {
+    /// Creates an approver and the receiver of its requests, holding at most `buffer`
+    /// requests not received yet.
+    pub fn new(buffer: usize) -> (Self, mpsc::Receiver<PendingApproval>) {
+        let (sender, receiver) = mpsc::channel(buffer);
+        (Self { sender }, receiver)
+    }
+}
+
+#[async_trait]
+impl Approver for ChannelApprover {
+    async fn approve(&self, request: ApprovalRequest) -> ApprovalDecision {
+        let (responder, answer) = oneshot::channel();
+        let pending = PendingApproval { request, responder };
+        if self.sender.send(pending).await.is_err() {
+            return ApprovalDecision::Reject("nobody is answering approval requests".to_string());
+        }
+        answer.await.unwrap_or_else(|_| {
+            ApprovalDecision::Reject("the approval request was dropped".to_string())
+        })
+    }
+}
@@ -0,0 +1,389 @@
+use crate::chat::Request;
+use crate::context;
+use alith_client::prelude::PromptTokenizer;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const MINUTE: Duration = Duration::from_secs(60);
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// The default time after which the tokens of an unused session are forgotten.
+pub const DEFAULT_SESSION_IDLE_TIMEOUT: Duration = Duration::from_secs(SECONDS_PER_DAY);
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum BudgetError {
+    #[error("The request needs about {tokens} tokens but a request may use {max_tokens}")]
+    RequestTokens { tokens: usize, max_tokens: usize },
+    #[error(
+        "The session used {used} tokens and the request needs about {tokens} more, the limit is {max_tokens}"
+    )]
+    SessionTokens {
+        used: u64,
+        tokens: usize,
+        max_tokens: u64,
+    },
+    #[error(
+        "{used} tokens were used today and the request needs about {tokens} more, the limit is {max_tokens}"
+    )]
+    DailyTokens {
+        used: u64,
+        tokens: usize,
+        max_tokens: u64,
+    },
+    #[error("The turn requested {calls} tool calls but a turn may make {max_calls}")]
+    ToolCalls { calls: usize, max_calls: usize },
+    #[error("A minute allows {max_requests} requests, retry after {retry_after:?}")]
+    RateLimited {
+        max_requests: usize,
+        retry_after: Duration,
+    },
+}
+
+/// The tokens counted by a [`Budget`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BudgetUsage {
+    /// The tokens used by the session of the budget since it started or was reset.
+    pub session_tokens: u64,
+    /// The tokens used since midnight UTC.
+    pub daily_tokens: u64,
+}
+
+/// The tokens used by a session.
+#[derive(Debug, Clone, Copy)]
+struct SessionUsage {
+    tokens: u64,
+    /// When the session last used tokens.
+    used: Instant,
+}
+
+#[derive(Debug, Default)]
+struct BudgetState {
+    /// The tokens used by each session.
+    sessions: HashMap<String, SessionUsage>,
+    daily_tokens: u64,
+    /// The days since the UNIX epoch of the daily tokens.
+    day: u64,
+    /// When the requests of the last minute were sent.
+    requests: VecDeque<Instant>,
+}
+
+/// Limits on the tokens, tool calls and requests of agents, checked before calling the
+/// model or the tools.
+///
+/// Clones share their counters, so a budget given to several agents limits them together,
+/// e.g. all the agents serving a user. The session tokens are counted per session ID, see
+/// [`Budget::session`], and forgotten when the session is unused for the
+/// [`Budget::session_idle_timeout`]. Exceeding a limit fails the turn with
+/// [`crate::task::TaskError::BudgetExceeded`].
+#[derive(Debug, Clone, Default)]
+pub struct Budget {
+    max_request_tokens: Option<usize>,
+    max_session_tokens: Option<u64>,
+    max_daily_tokens: Option<u64>,
+    max_tool_calls_per_turn: Option<usize>,
+    max_requests_per_minute: Option<usize>,
+    session_idle_timeout: Option<Duration>,
+    /// The ID of the session whose tokens are counted, empty for the default session.
+    session_id: String,
+    state: Arc<Mutex<BudgetState>>,
+}
+
+impl Budget {
+    /// Creates a budget without limits.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum number of tokens of a model request, the prompt tokens and the
+    /// completion `max_tokens` of the request.
+    pub fn max_request_tokens(mut self, max_tokens: usize) -> Self {
+        self.max_request_tokens = Some(max_tokens);
+        self
+    }
+
+    /// Sets the maximum number of tokens used until the session is reset.
+    pub fn max_session_tokens(mut self, max_tokens: u64) -> Self {
+        self.max_session_tokens = Some(max_tokens);
+        self
+    }
+
+    /// Sets the maximum number of tokens used per day, from midnight UTC.
+    pub fn max_daily_tokens(mut self, max_tokens: u64) -> Self {
+        self.max_daily_tokens = Some(max_tokens);
+        self
+    }
+
+    /// Sets the maximum number of tool calls in a turn.
+    pub fn max_tool_calls_per_turn(mut self, max_calls: usize) -> Self {
+        self.max_tool_calls_per_turn = Some(max_calls);
+        self
+    }
+
+    /// Sets the maximum number of model requests in a sliding minute.
+    pub fn max_requests_per_minute(mut self, max_requests: usize) -> Self {
+        self.max_requests_per_minute = Some(max_requests);
+        self
+    }
+
+    /// Sets the time after which the tokens of an unused session are forgotten, as if the
+    /// session was reset, [`DEFAULT_SESSION_IDLE_TIMEOUT`] by default.
+    pub fn session_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.session_idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Returns a budget sharing the counters and limits of this one, counting the session
+    /// tokens of the session `session_id`.
+    ///
+    /// Each session, e.g. the session of a [`crate::memory::MemoryStore`], has its own
+    /// session tokens, while the daily tokens and the request rate are shared.
+    pub fn session(&self, session_id: impl Into<String>) -> Self {
+        Self {
+            session_id: session_id.into(),
+            ..self.clone()
+        }
+    }
+
+    /// Returns the ID of the session whose tokens are counted, empty for the default session.
+    #[inline]
+    pub fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    /// Returns the tokens counted by the budget.
+    pub fn usage(&self) -> BudgetUsage {
+        let state = self.lock();
+        state.usage(&self.session_id)
+    }
+
+    /// Starts a new session, resetting the session tokens of the session.
+    pub fn reset_session(&self) {
+        self.lock().sessions.remove(&self.session_id);
+    }
+
+    /// Checks a model request against the limits and counts it in the request rate.
+    pub(crate) fn check_request(
+        &self,
+        request: &Request,
+        tokenizer: Option<&dyn PromptTokenizer>,
+    ) -> Result<(), BudgetError> {
+        let tokens = match tokenizer {
+            Some(tokenizer) => context::count_tokens(request, tokenizer),
+            None => context::count_tokens(request, &Approximate),
+        } + request.max_tokens.unwrap_or(0);
+        if let Some(max_tokens) = self.max_request_tokens.filter(|max| tokens > *max) {
+            return Err(BudgetError::RequestTokens { tokens, max_tokens });
+        }
+        let mut state = self.lock();
+        let usage = state.usage(&self.session_id);
+        if let Some(max_tokens) = self
+            .max_session_tokens
+            .filter(|max| usage.session_tokens + tokens as u64 > *max)
+        {
+            return Err(BudgetError::SessionTokens {
+                used: usage.session_tokens,
+                tokens,
+                max_tokens,
+            });
+        }
+        if let Some(max_tokens) = self
+            .max_daily_tokens
+            .filter(|max| usage.daily_tokens + tokens as u64 > *max)
+        {
+            return Err(BudgetError::DailyTokens {
+                used: usage.daily_tokens,
+                tokens,
+                max_tokens,
+            });
+        }
+        let now = Instant::now();
+        while state
+            .requests
+            .front()
+            .is_some_and(|sent| now.duration_since(*sent) >= MINUTE)
+        {
+            state.requests.pop_front();
+        }
+        if let Some(max_requests) = self.max_requests_per_minute {
+            if state.requests.len() >= max_requests {
+                let retry_after = state
+                    .requests
+                    .front()
+                    .map(|sent| MINUTE.saturating_sub(now.duration_since(*sent)))
+                    .unwrap_or(MINUTE);
+                return Err(BudgetError::RateLimited {
+                    max_requests,
+                    retry_after,
+                });
+            }
+            state.requests.push_back(now);
+        }
+        Ok(())
+    }
+
+    /// Checks the number of tool calls requested in a turn so far.
+    pub(crate) fn check_tool_calls(&self, calls: usize) -> Result<(), BudgetError> {
+        match self.max_tool_calls_per_turn {
+            Some(max_calls) if calls > max_calls => {
+                Err(BudgetError::ToolCalls { calls, max_calls })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Counts the tokens used by a model request.
+    pub(crate) fn record_tokens(&self, tokens: u64) {
+        let mut state = self.lock();
+        let used = Instant::now();
+        state
+            .sessions
+            .entry(self.session_id.clone())
+            .and_modify(|session| {
+                session.tokens += tokens;
+                session.used = used;
+            })
+            .or_insert(SessionUsage { tokens, used });
+        state.daily_tokens += tokens;
+    }
+
+    /// Locks the state, after resetting the daily tokens and forgetting the idle sessions.
+    fn lock(&self) -> std::sync::MutexGuard<'_, BudgetState> {
+        let mut state = self.state.lock().unwrap_or_else(|err| err.into_inner());
+        state.roll_day(today());
+        let idle_timeout = self
+            .session_idle_timeout
+            .unwrap_or(DEFAULT_SESSION_IDLE_TIMEOUT);
+        let now = Instant::now();
+        state
+            .sessions
+            .retain(|_, session| now.duration_since(session.used) < idle_timeout);
+        state
+    }
+}
+
+impl BudgetState {
+    /// Resets the daily tokens when the day changed.
+    fn roll_day(&mut self, day: u64) {
+        if self.day != day {
+            self.day = day;
+            self.daily_tokens = 0;
+        }
+    }
+
+    fn usage(&self, session_id: &str) -> BudgetUsage {
+        BudgetUsage {
+            session_tokens: self
+                .sessions
+                .get(session_id)
+                .map_or(0, |session| session.tokens),
+            daily_tokens: self.daily_tokens,
+        }
+    }
+}
+
+/// Returns the days since the UNIX epoch.
+fn today() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+        / SECONDS_PER_DAY
+}
+
+/// Estimates four characters per token, for the models without a tokenizer.
+struct Approximate;
+
+impl PromptTokenizer for Approximate {
+    fn tokenize(&self, input: &str) -> Vec<u32> {
+        vec![0; self.count_tokens(input) as usize]
+    }
+
+    fn count_tokens(&self, input: &str) -> u32 {
+        input.chars().count().div_ceil(4) as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_budget_limits() {
+        let request = Request::new("a".repeat(400), String::new());
+        let budget = Budget::new().max_request_tokens(50);
+        assert!(matches!(
+            budget.check_request(&request, None),
+            Err(BudgetError::RequestTokens { .. })
+        ));
+
+        let budget = Budget::new()
+            .max_session_tokens(1000)
+            .max_requests_per_minute(2);
+        budget.check_request(&request, None).unwrap();
+        budget.record_tokens(900);
+        assert!(matches!(
+            budget.check_request(&request, None),
+            Err(BudgetError::SessionTokens { used: 900, .. })
+        ));
+        budget.reset_session();
+        assert_eq!(budget.usage().daily_tokens, 900);
+        budget.check_request(&request, None).unwrap();
+        assert!(matches!(
+            budget.check_request(&request, None),
+            Err(BudgetError::RateLimited {
+                max_requests: 2,
+                ..
+            })
+        ));
+
+        let budget = Budget::new().max_tool_calls_per_turn(2);
+        assert!(budget.check_tool_calls(2).is_ok());
+        assert!(budget.check_tool_calls(3).is_err());
+    }
+
+    #[test]
+    fn test_session_tokens_are_counted_per_session() {
+        let request = Request::new("a".repeat(400), String::new());
+        let budget = Budget::new().max_session_tokens(1000);
+        let alice = budget.session("alice");
+        let bob = budget.session("bob");
+        alice.record_tokens(900);
+        assert!(matches!(
+            alice.check_request(&request, None),
+            Err(BudgetError::SessionTokens { used: 900, .. })
+        ));
+        bob.check_request(&request, None).unwrap();
+        bob.record_tokens(200);
+        assert_eq!(bob.usage().session_tokens, 200);
+        assert_eq!(bob.usage().daily_tokens, 1100);
+
+        // Clones of a session share its tokens.
+        assert_eq!(alice.clone().usage().session_tokens, 900);
+        bob.reset_session();
+        assert_eq!(alice.usage().session_tokens, 900);
+        assert_eq!(bob.usage().session_tokens, 0);
+    }
+
+    #[test]
+    fn test_idle_sessions_are_forgotten() {
+        let budget = Budget::new();
+        budget.session("alice").record_tokens(900);
+        budget.session("bob").record_tokens(200);
+        assert_eq!(budget.lock().sessions.len(), 2);
+
+        let budget = Budget::new().session_idle_timeout(Duration::ZERO);
+        budget.session("alice").record_tokens(900);
+        budget.session("bob").record_tokens(200);
+        let sessions: Vec<String> = budget.lock().sessions.keys().cloned().collect();
+        assert!(sessions.is_empty(), "{sessions:?}");
+        assert_eq!(
+            budget.session("alice").usage(),
+            BudgetUsage {
+                session_tokens: 0,
+                daily_tokens: 1100,
+            }
+        );
+    }
+}
@@ -0,0 +1,107 @@
+use crate::task::TaskError;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+pub use tokio_util::sync::CancellationToken;
+
+tokio::task_local! {
+    static CURRENT: Cancellation;
+}
+
+/// Returns the cancellation token of the agent turn running the current tool call.
+///
+/// Tools doing long work can watch it to stop early, their call is dropped anyway
+/// when the turn is cancelled.
+pub fn current_token() -> Option<CancellationToken> {
+    CURRENT
+        .try_with(|cancellation| cancellation.token.clone())
+        .ok()
+}
+
+/// Returns the cancellation of the current task, inherited by the agents it prompts,
+/// e.g. the agents called as tools.
+pub(crate) fn current() -> Option<Cancellation> {
+    CURRENT.try_with(Cancellation::clone).ok()
+}
+
+/// Stops an agent turn when its token is cancelled or when its deadline passes.
+///
+/// The token is passed down to the HTTP requests of the model and to the running tools,
+/// see [`current_token`]. A cancelled turn fails with [`TaskError::Cancelled`] and a turn
+/// past its deadline with [`TaskError::DeadlineExceeded`].
+#[derive(Debug, Clone, Default)]
+pub struct Cancellation {
+    token: CancellationToken,
+    deadline: Option<Instant>,
+}
+
+impl Cancellation {
+    /// Creates a cancellation without deadline, cancelled with [`Cancellation::cancel`].
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a cancellation cancelled with the token.
+    pub fn with_token(token: CancellationToken) -> Self {
+        Self {
+            token,
+            deadline: None,
+        }
+    }
+
+    /// Sets the instant after which the turn is stopped.
+    pub fn deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Sets the deadline to the given duration from now.
+    pub fn timeout(self, timeout: Duration) -> Self {
+        self.deadline(Instant::now() + timeout)
+    }
+
+    /// Returns the cancellation token.
+    pub fn token(&self) -> &CancellationToken {
+        &self.token
+    }
+
+    /// Cancels the turns using this cancellation.
+    pub fn cancel(&self) {
+        self.token.cancel();
+    }
+
+    /// Returns an error when the token is cancelled or the deadline passed.
+    pub fn check(&self) -> Result<(), TaskError> {
+        if self.token.is_cancelled() {
+            return Err(TaskError::Cancelled);
+        }
+        match self.deadline {
+            Some(deadline) if Instant::now() >= deadline => Err(TaskError::DeadlineExceeded),
+            _ => Ok(()),
+        }
+    }
+
+    /// Runs the future with this cancellation as the current one, without stopping it.
+    pub(crate) async fn scope<F: Future>(&self, future: F) -> F::Output {
+        CURRENT.scope(self.clone(), future).await
+    }
+
+    /// Runs the future until it completes, the token is cancelled or the deadline passes,
+    /// dropping it when stopped. [`current_token`] returns the token inside the future.
+    pub async fn run<F: Future>(&self, future: F) -> Result<F::Output, TaskError> {
+        self.check()?;
+        let deadline = async {
+            match self.deadline {
+                Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            biased;
+            _ = self.token.cancelled() => Err(TaskError::Cancelled),
+            _ = deadline => Err(TaskError::DeadlineExceeded),
+            output = self.scope(future) => Ok(output),
+        }
+    }
+}
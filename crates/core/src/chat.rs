@@ -1,12 +1,28 @@
+use crate::cancel::CancellationToken;
 pub use crate::client::{
     CompletionRequest as BackendCompletionRequest, CompletionResponse as Response,
 };
 use crate::store::DocumentId;
 use crate::task::TaskError;
-pub use alith_interface::requests::completion::{TokenUsage, ToolDefinition};
+use alith_client::prelude::PromptTokenizer;
+pub use alith_interface::requests::completion::{
+    CompletionChunk, JsonSchemaFormat, ResponseFormat, StreamedCompletion, TokenUsage,
+    ToolCallChunk, ToolDefinition,
+};
 use async_trait::async_trait;
+use futures::{Stream, stream};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// A stream of response chunks produced by a [`Completion`] model.
+pub type ResponseStream =
+    Pin<Box<dyn Stream<Item = Result<CompletionChunk, CompletionError>> + Send>>;
+
+/// A stream of response chunks produced by a [`Chat`] implementation.
+pub type ChatStream<'a> =
+    Pin<Box<dyn Stream<Item = Result<CompletionChunk, TaskError>> + Send + 'a>>;
 
 /// A trait representing a prompt-based interaction mechanism.
 ///
@@ -33,6 +49,33 @@ pub trait Chat: Send + Sync {
     async fn prompt(&self, prompt: &str) -> Result<String, TaskError>;
     /// Processes the given prompt and history and returns a response asynchronously.
     async fn chat(&self, prompt: &str, history: Vec<Message>) -> Result<String, TaskError>;
+
+    /// Processes the given prompt and streams the response chunks as they are generated.
+    ///
+    /// The default implementation waits for [`Chat::prompt`] and yields the whole
+    /// response as a single text chunk.
+    async fn prompt_stream<'a>(&'a self, prompt: &'a str) -> Result<ChatStream<'a>, TaskError> {
+        let response = self.prompt(prompt).await?;
+        Ok(Box::pin(stream::iter([Ok(CompletionChunk::Text(
+            response,
+        ))])))
+    }
+
+    /// Processes the given prompt and history and streams the response chunks as they
+    /// are generated.
+    ///
+    /// The default implementation waits for [`Chat::chat`] and yields the whole
+    /// response as a single text chunk.
+    async fn chat_stream<'a>(
+        &'a self,
+        prompt: &'a str,
+        history: Vec<Message>,
+    ) -> Result<ChatStream<'a>, TaskError> {
+        let response = self.chat(prompt, history).await?;
+        Ok(Box::pin(stream::iter([Ok(CompletionChunk::Text(
+            response,
+        ))])))
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -40,6 +83,64 @@ pub struct Message {
     /// "system", "user", "tool", or "assistant"
     pub role: String,
     pub content: String,
+    /// The tool calls requested by the model, only for "assistant" messages.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    /// The ID of the tool call this message answers, only for "tool" messages.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+}
+
+impl Message {
+    /// Creates a new message with the given role and content.
+    pub fn new(role: impl ToString, content: impl ToString) -> Self {
+        Self {
+            role: role.to_string(),
+            content: content.to_string(),
+            tool_calls: Vec::new(),
+            tool_call_id: None,
+        }
+    }
+
+    /// Creates a new assistant message carrying the tool calls requested by the model.
+    pub fn assistant_with_tool_calls(content: impl ToString, tool_calls: Vec<ToolCall>) -> Self {
+        Self {
+            tool_calls,
+            ..Self::new("assistant", content)
+        }
+    }
+
+    /// Creates a new tool message holding the result of the tool call `tool_call_id`.
+    pub fn tool_result(content: impl ToString, tool_call_id: impl ToString) -> Self {
+        Self {
+            tool_call_id: Some(tool_call_id.to_string()),
+            ..Self::new("tool", content)
+        }
+    }
+
+    /// Returns whether the message is the result of a tool call.
+    #[inline]
+    pub fn is_tool_result(&self) -> bool {
+        self.role == "tool"
+    }
+
+    /// Returns the message as a map, with the tool calls encoded as a JSON string.
+    fn to_map(&self) -> HashMap<String, String> {
+        let mut map = HashMap::from([
+            ("role".to_string(), self.role.clone()),
+            ("content".to_string(), self.content.clone()),
+        ]);
+        if !self.tool_calls.is_empty() {
+            map.insert(
+                "tool_calls".to_string(),
+                serde_json::to_string(&self.tool_calls).unwrap_or_default(),
+            );
+        }
+        if let Some(tool_call_id) = &self.tool_call_id {
+            map.insert("tool_call_id".to_string(), tool_call_id.clone());
+        }
+        map
+    }
 }
 
 /// Represents a document with an ID, text, and additional properties.
@@ -129,6 +230,12 @@ pub struct Request {
     /// Top-K sampling described in academic paper "The Curious Case of Neural Text Degeneration" https://arxiv.org/abs/1904.09751
     pub top_k: Option<usize>,
 
+    /// Messages that follow the prompt within the current turn.
+    ///
+    /// These are the assistant tool calls and the tool results produced while the
+    /// executor loops over tool calls, in the order they happened.
+    pub tool_messages: Vec<Message>,
+
     /// A collection of tools available for tool-based interactions with the model.
     ///
     /// Tools are external systems, APIs, or utilities that the model can invoke to perform
@@ -140,6 +247,14 @@ pub struct Request {
     /// These documents can be used by the model to generate more accurate and informed responses.
     /// Examples include research papers, policy documents, or reference materials.
     pub documents: Vec<Document>,
+
+    /// Optional: The format of the output, e.g. a JSON schema for structured output.
+    ///
+    /// Only sent to the models supporting it, see [`Completion::supports_json_schema`].
+    pub response_format: Option<ResponseFormat>,
+
+    /// Optional: A token cancelling the HTTP request of the model when it is cancelled.
+    pub cancellation: Option<CancellationToken>,
 }
 
 impl Request {
@@ -157,12 +272,15 @@ impl Request {
             preamble,
             knowledges: Vec::new(),
             history: Vec::new(),
+            tool_messages: Vec::new(),
             max_tokens: None,
             top_p: None,
             top_k: None,
             temperature: None,
             tools: Vec::new(),
             documents: Vec::new(),
+            response_format: None,
+            cancellation: None,
         }
     }
 
@@ -216,15 +334,15 @@ impl Request {
             ("content".to_string(), self.preamble.clone()),
         ]));
         for m in &self.history {
-            messages.push(HashMap::from([
-                ("role".to_string(), m.role.clone()),
-                ("content".to_string(), m.content.clone()),
-            ]));
+            messages.push(m.to_map());
         }
         messages.push(HashMap::from([
             ("role".to_string(), "user".to_string()),
             ("content".to_string(), self.effective_prompt()),
         ]));
+        for m in &self.tool_messages {
+            messages.push(m.to_map());
+        }
         messages
     }
 }
@@ -253,6 +371,7 @@ pub trait ResponseTokenUsage {
 }
 
 /// Represents a call to a specific tool in a response.
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct ToolCall {
     /// The unique identifier for the tool call.
     pub id: String,
@@ -263,6 +382,7 @@ pub struct ToolCall {
 }
 
 /// Represents a callable function within a tool interaction.
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct CallFunction {
     /// The name of the function being invoked.
     pub name: String,
@@ -270,6 +390,62 @@ pub struct CallFunction {
     pub arguments: String,
 }
 
+impl From<&crate::memory::Message> for Message {
+    /// Converts a memory message, restoring the tool calls of AI messages and the tool
+    /// call ID of tool messages.
+    fn from(message: &crate::memory::Message) -> Self {
+        let mut result = Message::new(message.message_type.type_string(), &message.content);
+        if let Some(tool_calls) = &message.tool_calls {
+            match serde_json::from_value(tool_calls.clone()) {
+                Ok(tool_calls) => result.tool_calls = tool_calls,
+                Err(err) => tracing::error!("Failed to read the tool calls of a message: {err}"),
+            }
+        }
+        if message.message_type == crate::memory::MessageType::Tool {
+            result.tool_call_id = message.id.clone();
+        }
+        result
+    }
+}
+
+/// Converts the messages of a memory into a chat history, dropping the tool results
+/// which don't answer a tool call of the previous AI message, e.g. because a window
+/// removed it or its tool calls couldn't be read.
+pub(crate) fn memory_history(messages: &[crate::memory::Message]) -> Vec<Message> {
+    let mut call_ids = HashSet::new();
+    messages
+        .iter()
+        .map(Message::from)
+        .filter(|message| {
+            if message.is_tool_result() {
+                return message
+                    .tool_call_id
+                    .as_ref()
+                    .is_some_and(|id| call_ids.contains(id));
+            }
+            call_ids = message
+                .tool_calls
+                .iter()
+                .map(|call| call.id.clone())
+                .collect();
+            true
+        })
+        .collect()
+}
+
+impl From<&alith_interface::requests::completion::tool::ToolCall> for ToolCall {
+    fn from(call: &alith_interface::requests::completion::tool::ToolCall) -> Self {
+        ToolCall {
+            id: call.id.clone(),
+            r#type: call.r#type.clone(),
+            function: CallFunction {
+                name: call.function.name.clone(),
+                arguments: call.function.arguments.clone(),
+            },
+        }
+    }
+}
+
 /// A trait defining the behavior of a completion engine.
 ///
 /// This trait is used by components that handle requests for text generation
@@ -294,6 +470,63 @@ pub trait Completion {
         &mut self,
         request: Request,
     ) -> impl std::future::Future<Output = Result<Self::Response, CompletionError>> + Send;
+
+    /// Returns the tokenizer of the model, used to fit requests into its context window.
+    fn prompt_tokenizer(&self) -> Option<Arc<dyn PromptTokenizer>> {
+        None
+    }
+
+    /// Returns the size of the context window of the model in tokens.
+    fn context_size(&self) -> Option<usize> {
+        None
+    }
+
+    /// Returns the name of the model, used to look up its price.
+    fn model_name(&self) -> Option<String> {
+        None
+    }
+
+    /// Returns whether the model constrains its output to the JSON schema of
+    /// [`Request::response_format`].
+    fn supports_json_schema(&self) -> bool {
+        false
+    }
+
+    /// Processes a `Request` and streams the response chunks as they are generated.
+    ///
+    /// The default implementation waits for [`Completion::completion`] and yields the
+    /// whole response as a few chunks, so models without streaming support still work.
+    ///
+    /// # Returns
+    /// A future that resolves to either:
+    /// - `Ok(ResponseStream)`: The stream of text, tool call and token usage chunks.
+    /// - `Err(CompletionError)`: An error encountered before the stream started.
+    fn completion_stream(
+        &mut self,
+        request: Request,
+    ) -> impl std::future::Future<Output = Result<ResponseStream, CompletionError>> + Send
+    where
+        Self: Send,
+    {
+        async move {
+            let response = self.completion(request).await?;
+            let mut chunks = Vec::new();
+            let content = response.content();
+            if !content.is_empty() {
+                chunks.push(CompletionChunk::Text(content));
+            }
+            for (index, call) in response.toolcalls().into_iter().enumerate() {
+                chunks.push(CompletionChunk::ToolCall(ToolCallChunk {
+                    index,
+                    id: Some(call.id),
+                    name: Some(call.function.name),
+                    arguments: call.function.arguments,
+                }));
+            }
+            chunks.push(CompletionChunk::Usage(response.token_usage()));
+            Ok(Box::pin(stream::iter(chunks.into_iter().map(Ok))) as ResponseStream)
+        }
+    }
 }
 
 /// An enumeration of possible errors that may occur during completion operations.
@@ -311,3 +544,36 @@ pub enum CompletionError {
     #[error("JSON error: {0}")]
     JsonError(#[from] serde_json::Error),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::Message as MemoryMessage;
+
+    #[test]
+    fn test_memory_history_drops_orphan_tool_results() {
+        let call = serde_json::json!([{
+            "id": "call-2",
+            "type": "function",
+            "function": {"name": "add", "arguments": "{}"}
+        }]);
+        let messages = [
+            MemoryMessage::new_tool_message("1", "call-1"),
+            MemoryMessage::new_human_message("question"),
+            MemoryMessage::new_ai_message("").with_tool_calls(serde_json::json!("corrupt")),
+            MemoryMessage::new_tool_message("2", "call-3"),
+            MemoryMessage::new_ai_message("").with_tool_calls(call),
+            MemoryMessage::new_tool_message("3", "call-2"),
+            MemoryMessage::new_ai_message("3"),
+        ];
+        let history = memory_history(&messages);
+        let roles: Vec<&str> = history.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(
+            roles,
+            ["user", "assistant", "assistant", "tool", "assistant"]
+        );
+        assert!(history[1].tool_calls.is_empty());
+        assert_eq!(history[2].tool_calls[0].id, "call-2");
+        assert_eq!(history[3].tool_call_id.as_deref(), Some("call-2"));
+    }
+}
@@ -0,0 +1,383 @@
+use crate::chat::{Message, Request};
+use crate::store::DocumentId;
+use alith_client::prelude::PromptTokenizer;
+use std::sync::Arc;
+
+/// The default number of tokens kept free for the completion when the request
+/// doesn't set `max_tokens`.
+pub const DEFAULT_RESERVED_TOKENS: usize = 1024;
+/// The tokens added by the chat format around each message.
+const TOKENS_PER_MESSAGE: usize = 4;
+/// The tokens priming the reply of the assistant.
+const TOKENS_PER_REPLY: usize = 3;
+
+/// The wrapper of the documents in the prompt.
+const ATTACHMENTS: &str = "<attachments>\n</attachments>\n\n";
+
+/// A callback called with the report of a trimmed request.
+pub type TrimCallback = Arc<dyn Fn(&ContextReport) + Send + Sync>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ContextError {
+    #[error("The request needs {tokens} tokens but the context window allows {max_tokens}")]
+    Overflow { tokens: usize, max_tokens: usize },
+}
+
+/// A part of a request that can be trimmed to fit the context window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ContextPart {
+    /// The retrieved documents, the least relevant ones are dropped first.
+    Documents,
+    /// The knowledge enrichments, the last ones are truncated or dropped first.
+    Knowledges,
+    /// The conversation history, the oldest messages are dropped first.
+    /// System messages, e.g. memory summaries, are kept.
+    History,
+}
+
+/// What was trimmed from a request to fit the context window.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ContextReport {
+    /// The number of prompt tokens after trimming.
+    pub tokens: usize,
+    /// The number of prompt tokens allowed.
+    pub max_tokens: usize,
+    /// The number of dropped history messages.
+    pub dropped_messages: usize,
+    /// The IDs of the dropped documents.
+    pub dropped_documents: Vec<DocumentId>,
+    /// The number of dropped knowledge enrichments.
+    pub dropped_knowledges: usize,
+    /// Whether a knowledge enrichment was truncated.
+    pub truncated_knowledge: bool,
+}
+
+impl ContextReport {
+    /// Returns whether anything was trimmed from the request.
+    pub fn is_trimmed(&self) -> bool {
+        self.dropped_messages > 0
+            || !self.dropped_documents.is_empty()
+            || self.dropped_knowledges > 0
+            || self.truncated_knowledge
+    }
+}
+
+/// Fits requests into the context window of a model by trimming their lower priority parts.
+///
+/// The preamble, the prompt, the tools and the messages of the current turn are always sent,
+/// the other parts are trimmed in the [`ContextPolicy::trim_order`] until the request fits.
+#[derive(Clone)]
+pub struct ContextPolicy {
+    max_tokens: Option<usize>,
+    reserved_tokens: usize,
+    trim_order: Vec<ContextPart>,
+    tokenizer: Option<Arc<dyn PromptTokenizer>>,
+    on_trim: Option<TrimCallback>,
+}
+
+impl Default for ContextPolicy {
+    fn default() -> Self {
+        Self {
+            max_tokens: None,
+            reserved_tokens: DEFAULT_RESERVED_TOKENS,
+            trim_order: vec![
+                ContextPart::Documents,
+                ContextPart::Knowledges,
+                ContextPart::History,
+            ],
+            tokenizer: None,
+            on_trim: None,
+        }
+    }
+}
+
+impl ContextPolicy {
+    /// Creates a policy trimming documents, then knowledges, then history to fit the
+    /// context window of the model.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum number of prompt tokens, instead of deriving it from the context
+    /// size of the model.
+    pub fn max_tokens(mut self, max_tokens: usize) -> Self {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+
+    /// Sets the number of tokens kept free for the completion when the request doesn't
+    /// set `max_tokens`.
+    pub fn reserved_tokens(mut self, reserved_tokens: usize) -> Self {
+        self.reserved_tokens = reserved_tokens;
+        self
+    }
+
+    /// Sets the parts to trim, in order. Parts not listed are never trimmed.
+    pub fn trim_order(mut self, trim_order: impl IntoIterator<Item = ContextPart>) -> Self {
+        self.trim_order = trim_order.into_iter().collect();
+        self
+    }
+
+    /// Sets the tokenizer counting the tokens, instead of the tokenizer of the model.
+    pub fn tokenizer(mut self, tokenizer: Arc<dyn PromptTokenizer>) -> Self {
+        self.tokenizer = Some(tokenizer);
+        self
+    }
+
+    /// Sets a callback called with the report of each request that was trimmed.
+    pub fn on_trim(mut self, on_trim: impl Fn(&ContextReport) + Send + Sync + 'static) -> Self {
+        self.on_trim = Some(Arc::new(on_trim));
+        self
+    }
+
+    /// Fits the request into the context window of a model, using the tokenizer of the
+    /// policy or else the one of the model.
+    ///
+    /// Returns `None` when there is no tokenizer or no known context size.
+    pub(crate) fn apply(
+        &self,
+        request: &mut Request,
+        tokenizer: Option<Arc<dyn PromptTokenizer>>,
+        context_size: Option<usize>,
+    ) -> Result<Option<ContextReport>, ContextError> {
+        let Some(tokenizer) = self.tokenizer.clone().or(tokenizer) else {
+            return Ok(None);
+        };
+        let reserved_tokens = request.max_tokens.unwrap_or(self.reserved_tokens);
+        let Some(max_tokens) = self
+            .max_tokens
+            .or_else(|| context_size.map(|size| size.saturating_sub(reserved_tokens)))
+        else {
+            return Ok(None);
+        };
+        self.fit(request, tokenizer.as_ref(), max_tokens).map(Some)
+    }
+
+    /// Trims the request until its prompt has at most `max_tokens` tokens.
+    pub fn fit(
+        &self,
+        request: &mut Request,
+        tokenizer: &dyn PromptTokenizer,
+        max_tokens: usize,
+    ) -> Result<ContextReport, ContextError> {
+        let count = |text: &str| tokenizer.count_tokens(text) as usize;
+        let message_tokens = |message: &Message| message_tokens(message, tokenizer);
+        let history_tokens: Vec<usize> = request.history.iter().map(message_tokens).collect();
+        let document_tokens: Vec<usize> = request
+            .documents
+            .iter()
+            .map(|document| count(&document.to_string()))
+            .collect();
+        let knowledge_tokens: Vec<usize> = request
+            .knowledges
+            .iter()
+            .map(|knowledge| count(knowledge) + 1)
+            .collect();
+        let mut tokens = count_tokens(request, tokenizer);
+        let attachments_tokens = count(ATTACHMENTS);
+
+        let mut report = ContextReport {
+            max_tokens,
+            ..Default::default()
+        };
+        for part in &self.trim_order {
+            if tokens <= max_tokens {
+                break;
+            }
+            match part {
+                ContextPart::Documents => {
+                    while tokens > max_tokens {
+                        let Some(document) = request.documents.pop() else {
+                            break;
+                        };
+                        tokens -= document_tokens[request.documents.len()];
+                        if request.documents.is_empty() {
+                            tokens -= attachments_tokens;
+                        }
+                        report.dropped_documents.push(document.id);
+                    }
+                }
+                ContextPart::Knowledges => {
+                    while tokens > max_tokens {
+                        let Some(knowledge) = request.knowledges.pop() else {
+                            break;
+                        };
+                        let knowledge_tokens = knowledge_tokens[request.knowledges.len()];
+                        let excess = tokens - max_tokens;
+                        if knowledge_tokens > excess + 1 {
+                            let truncated =
+                                truncate(&knowledge, knowledge_tokens - excess - 1, count);
+                            tokens = tokens - knowledge_tokens + count(truncated) + 1;
+                            request.knowledges.push(truncated.to_string());
+                            report.truncated_knowledge = true;
+                            break;
+                        }
+                        tokens -= knowledge_tokens;
+                        report.dropped_knowledges += 1;
+                    }
+                }
+                ContextPart::History => {
+                    let mut keep = vec![true; request.history.len()];
+                    for (index, message) in request.history.iter().enumerate() {
+                        // Tool results are dropped with the message calling the tool.
+                        if tokens <= max_tokens && message.role != "tool" {
+                            break;
+                        }
+                        if message.role == "system" {
+                            continue;
+                        }
+                        keep[index] = false;
+                        tokens -= history_tokens[index];
+                        report.dropped_messages += 1;
+                    }
+                    let mut keep = keep.into_iter();
+                    request.history.retain(|_| keep.next().unwrap_or(true));
+                }
+            }
+        }
+
+        report.tokens = tokens;
+        if tokens > max_tokens {
+            return Err(ContextError::Overflow { tokens, max_tokens });
+        }
+        if let Some(on_trim) = self.on_trim.as_ref().filter(|_| report.is_trimmed()) {
+            on_trim(&report);
+        }
+        Ok(report)
+    }
+}
+
+/// Returns the number of prompt tokens of the request, as counted to fit it into the
+/// context window.
+pub fn count_tokens(request: &Request, tokenizer: &dyn PromptTokenizer) -> usize {
+    let count = |text: &str| tokenizer.count_tokens(text) as usize;
+    let mut tokens = TOKENS_PER_REPLY
+        + 2 * TOKENS_PER_MESSAGE
+        + count(&request.preamble)
+        + count(&request.prompt)
+        + count(&serde_json::to_string(&request.tools).unwrap_or_default())
+        + request
+            .tool_messages
+            .iter()
+            .chain(&request.history)
+            .map(|message| message_tokens(message, tokenizer))
+            .sum::<usize>()
+        + request
+            .documents
+            .iter()
+            .map(|document| count(&document.to_string()))
+            .sum::<usize>()
+        + request
+            .knowledges
+            .iter()
+            .map(|knowledge| count(knowledge) + 1)
+            .sum::<usize>();
+    if !request.documents.is_empty() {
+        tokens += count(ATTACHMENTS);
+    }
+    tokens
+}
+
+fn message_tokens(message: &Message, tokenizer: &dyn PromptTokenizer) -> usize {
+    let count = |text: &str| tokenizer.count_tokens(text) as usize;
+    let mut tokens = TOKENS_PER_MESSAGE + count(&message.content);
+    if !message.tool_calls.is_empty() {
+        tokens += count(&serde_json::to_string(&message.tool_calls).unwrap_or_default());
+    }
+    tokens
+}
+
+/// Returns the longest prefix of the text with at most `max_tokens` tokens.
+fn truncate(text: &str, max_tokens: usize, count: impl Fn(&str) -> usize) -> &str {
+    let boundaries: Vec<usize> = text
+        .char_indices()
+        .map(|(index, _)| index)
+        .chain(std::iter::once(text.len()))
+        .collect();
+    // Binary search the last boundary whose prefix fits.
+    let (mut low, mut high) = (0, boundaries.len() - 1);
+    while low < high {
+        let mid = (low + high).div_ceil(2);
+        if count(&text[..boundaries[mid]]) <= max_tokens {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+    &text[..boundaries[low]]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chat::Document;
+    use std::collections::HashMap;
+
+    /// Counts one token per word.
+    struct Words;
+
+    impl PromptTokenizer for Words {
+        fn tokenize(&self, input: &str) -> Vec<u32> {
+            input.split_whitespace().map(|_| 0).collect()
+        }
+
+        fn count_tokens(&self, input: &str) -> u32 {
+            input.split_whitespace().count() as u32
+        }
+    }
+
+    fn request() -> Request {
+        let mut request = Request::new("question".to_string(), "be brief".to_string());
+        request.history = vec![
+            Message::new("system", "summary"),
+            Message::new("user", "one two three four"),
+            Message::new("assistant", "five six"),
+            Message::new("user", "seven"),
+        ];
+        request.documents = ["a b c", "d e f g h i"]
+            .into_iter()
+            .enumerate()
+            .map(|(id, text)| Document {
+                id: DocumentId(id.to_string()),
+                text: text.to_string(),
+                additional_props: HashMap::new(),
+            })
+            .collect();
+        request.knowledges = vec!["k1 k2 k3 k4 k5 k6 k7 k8".to_string()];
+        request
+    }
+
+    #[test]
+    fn test_fit_trims_in_order() {
+        let policy = ContextPolicy::new();
+        let untouched = policy.fit(&mut request(), &Words, 1000).unwrap();
+        assert!(!untouched.is_trimmed());
+        assert_eq!(untouched.tokens, count_tokens(&request(), &Words));
+
+        let mut trimmed = request();
+        let report = policy
+            .fit(&mut trimmed, &Words, untouched.tokens - 22)
+            .unwrap();
+        assert_eq!(report.dropped_documents.len(), 2);
+        assert!(report.truncated_knowledge);
+        assert_eq!(report.dropped_messages, 0);
+        assert!(report.tokens <= report.max_tokens);
+        assert!(trimmed.knowledges[0].starts_with("k1"));
+
+        let report = ContextPolicy::new()
+            .trim_order([ContextPart::History])
+            .fit(&mut trimmed, &Words, report.tokens - 8)
+            .unwrap();
+        assert_eq!(report.dropped_messages, 1);
+        assert_eq!(trimmed.history[0].content, "summary");
+        assert_eq!(trimmed.history[1].content, "five six");
+
+        assert!(
+            ContextPolicy::new()
+                .trim_order([])
+                .fit(&mut request(), &Words, 10)
+                .is_err()
+        );
+    }
+}
@@ -28,6 +28,12 @@ pub trait Embeddings: Clone + Send + Sync {
     /// Generate embeddings for a list of texts
     async fn embed_texts(&self, input: Vec<String>)
     -> Result<Vec<EmbeddingsData>, EmbeddingsError>;
+
+    /// Returns the name of the model, saved with the vectors to check that they are
+    /// reloaded with the same model.
+    fn model_name(&self) -> Option<String> {
+        None
+    }
 }
 
 // Trait that defines the embedding process for a document
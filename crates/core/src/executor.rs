@@ -1,10 +1,27 @@
 use crate::Ref;
-use crate::chat::{Completion, Request, ResponseContent, ResponseToolCalls, ToolCall};
+use crate::approval::{ApprovalDecision, ApprovalRequest, Approver};
+use crate::budget::Budget;
+use crate::cancel::Cancellation;
+use crate::chat::{
+    Completion, CompletionChunk, Message as ChatMessage, Request, ResponseContent, ResponseStream,
+    ResponseTokenUsage, ResponseToolCalls, StreamedCompletion, TokenUsage, ToolCall,
+};
+use crate::context::{ContextPolicy, ContextReport};
+use crate::hook::{Hook, ToolCallDecision};
 use crate::knowledge::Knowledge;
 use crate::mcp::MCPClient;
 use crate::memory::{Memory, Message};
-use crate::tool::Tool;
+use crate::task::TaskError;
+use crate::tool::{RetryPolicy, Tool, ToolError};
+use crate::usage::{AgentResponse, CallUsage, PriceTable, UsageTracker};
+use futures::{Stream, StreamExt, stream};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// The default maximum number of model calls in one tool calling loop.
+pub const DEFAULT_MAX_ITERATIONS: usize = 10;
+/// The default maximum number of tool calls of one model response run concurrently.
+pub const DEFAULT_TOOL_PARALLELISM: usize = 4;
 
 /// Manages the execution of tasks using an LLM, tools, and (optionally) memory components.
 pub struct Executor<M: Completion> {
@@ -14,6 +31,26 @@ pub struct Executor<M: Completion> {
     memory: Option<Ref<dyn Memory>>,
     /// The MCP client used to communicate with the MCP server
     mcp_clients: Ref<Vec<MCPClient>>,
+    /// The maximum number of model calls before giving up on a final answer.
+    max_iterations: usize,
+    /// The policy fitting the requests into the context window of the model.
+    context_policy: Option<ContextPolicy>,
+    /// The maximum number of tool calls run concurrently.
+    tool_parallelism: usize,
+    /// The maximum duration of a tool call, unless the tool sets its own.
+    tool_timeout: Option<Duration>,
+    /// The hooks invoked around model requests and tool calls.
+    hooks: Vec<Arc<dyn Hook>>,
+    /// The approver of the calls to the tools requiring approval.
+    approver: Option<Arc<dyn Approver>>,
+    /// The prices used to estimate the cost of the model calls.
+    price_table: Option<Arc<PriceTable>>,
+    /// The trackers accumulating the usage of the model calls.
+    usage_trackers: Vec<UsageTracker>,
+    /// The limits checked before the model and tool calls.
+    budget: Option<Budget>,
+    /// Stops the turn when cancelled or past its deadline.
+    cancellation: Cancellation,
 }
 
 impl<M: Completion> Executor<M> {
@@ -31,11 +68,211 @@ impl<M: Completion> Executor<M> {
             tools,
             memory,
             mcp_clients,
+            max_iterations: DEFAULT_MAX_ITERATIONS,
+            context_policy: None,
+            tool_parallelism: DEFAULT_TOOL_PARALLELISM,
+            tool_timeout: None,
+            hooks: Vec::new(),
+            approver: None,
+            price_table: None,
+            usage_trackers: Vec::new(),
+            budget: None,
+            cancellation: Cancellation::new(),
         }
     }
 
+    /// Sets the maximum number of model calls in the tool calling loop.
+    pub fn with_max_iterations(mut self, max_iterations: usize) -> Self {
+        self.max_iterations = max_iterations;
+        self
+    }
+
+    /// Sets the policy fitting the requests into the context window of the model.
+    pub fn with_context_policy(mut self, context_policy: ContextPolicy) -> Self {
+        self.context_policy = Some(context_policy);
+        self
+    }
+
+    /// Sets the maximum number of tool calls of one model response run concurrently.
+    pub fn with_tool_parallelism(mut self, tool_parallelism: usize) -> Self {
+        self.tool_parallelism = tool_parallelism.max(1);
+        self
+    }
+
+    /// Sets the maximum duration of a tool call, for the tools without their own timeout.
+    pub fn with_tool_timeout(mut self, tool_timeout: Duration) -> Self {
+        self.tool_timeout = Some(tool_timeout);
+        self
+    }
+
+    /// Sets the hooks invoked around model requests and tool calls, in order.
+    pub fn with_hooks(mut self, hooks: Vec<Arc<dyn Hook>>) -> Self {
+        self.hooks = hooks;
+        self
+    }
+
+    /// Adds a hook invoked after the other hooks.
+    pub fn with_hook(mut self, hook: Arc<dyn Hook>) -> Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    /// Doesn't store the turns into the memory, e.g. for the intermediate turns of a
+    /// [`crate::strategy::Strategy`].
+    pub fn without_memory(mut self) -> Self {
+        self.memory = None;
+        self
+    }
+
+    /// Sets the approver of the calls to the tools requiring approval.
+    ///
+    /// Without an approver, these calls are rejected.
+    pub fn with_approver(mut self, approver: Arc<dyn Approver>) -> Self {
+        self.approver = Some(approver);
+        self
+    }
+
+    /// Sets the prices used to estimate the cost of the model calls.
+    pub fn with_price_table(mut self, price_table: Arc<PriceTable>) -> Self {
+        self.price_table = Some(price_table);
+        self
+    }
+
+    /// Adds a tracker accumulating the usage of the model calls.
+    pub fn with_usage_tracker(mut self, usage_tracker: UsageTracker) -> Self {
+        self.usage_trackers.push(usage_tracker);
+        self
+    }
+
+    /// Sets the limits checked before the model and tool calls.
+    pub fn with_budget(mut self, budget: Budget) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Sets the cancellation stopping the turn, and the model requests and tool calls
+    /// running, when cancelled or past its deadline.
+    ///
+    /// A stopped turn fails with [`TaskError::Cancelled`] or [`TaskError::DeadlineExceeded`].
+    /// The tool calls of the last model response are stored with an error result, so the
+    /// memory stays consistent.
+    pub fn with_cancellation(mut self, cancellation: Cancellation) -> Self {
+        self.cancellation = cancellation;
+        self
+    }
+
     /// Executes the task by managing interactions between the LLM and tools.
-    pub async fn invoke(&mut self, mut request: Request) -> anyhow::Result<String> {
+    ///
+    /// The model is called repeatedly: the tool calls of each response are executed
+    /// concurrently and their results, or their errors, are sent back as tool messages
+    /// until the model answers without requesting any tool. Returns [`TaskError::MaxIterationsExceeded`] when no final
+    /// answer is produced within the iteration limit.
+    pub async fn invoke(&mut self, request: Request) -> anyhow::Result<String> {
+        self.invoke_response(request)
+            .await
+            .map(|response| response.content)
+    }
+
+    /// Executes the task like [`Executor::invoke`], returning the answer with the tool
+    /// calls made, the token usage, the latency and the estimated cost of the turn.
+    pub async fn invoke_response(&mut self, request: Request) -> anyhow::Result<AgentResponse> {
+        let started = Instant::now();
+        let mut response = AgentResponse::default();
+        let result = self.invoke_turn(request, &mut response).await;
+        response.latency = started.elapsed();
+        self.record_turn();
+        if let Err(err) = &result {
+            self.on_error(err).await;
+        }
+        self.compress_memory().await;
+        result.map(|content| AgentResponse {
+            content,
+            ..response
+        })
+    }
+
+    async fn invoke_turn(
+        &mut self,
+        mut request: Request,
+        turn: &mut AgentResponse,
+    ) -> anyhow::Result<String> {
+        turn.context = self.prepare(&mut request).await?;
+        let model_name = self.model.read().await.model_name();
+        for _ in 0..self.max_iterations {
+            // Interact with the LLM to get a response, locking the model only for the
+            // call so that the tools can use it meanwhile.
+            self.cancellation.check()?;
+            self.before_request(&mut request).await?;
+            let mut model = self.model.write().await;
+            self.check_request(&request, &*model)?;
+            let started = Instant::now();
+            let response = self
+                .cancellation
+                .run(model.completion(request.clone()))
+                .await??;
+            drop(model);
+            turn.add_call(self.record_call(
+                model_name.as_deref(),
+                response.token_usage(),
+                started.elapsed(),
+            ));
+            let mut content = response.content();
+            let calls = response.toolcalls();
+            self.after_response(&mut content, &calls).await;
+            self.check_tool_calls(turn.tool_calls.len() + calls.len())?;
+            self.add_ai_message(&content, &calls).await;
+            if calls.is_empty() {
+                return Ok(content);
+            }
+            turn.tool_calls.extend(calls.iter().cloned());
+            let output = self.call_tools(&mut request, &content, calls).await;
+            self.cancellation.check()?;
+            if let Some(output) = output {
+                return Ok(output);
+            }
+        }
+
+        Err(TaskError::MaxIterationsExceeded(self.max_iterations).into())
+    }
+
+    /// Executes the task like [`Executor::invoke`], streaming the chunks of every model
+    /// response as they are generated.
+    ///
+    /// Tool calls are executed between two model calls, and the stream ends after the
+    /// model answers without requesting any tool. The output of `return_direct` tools
+    /// is yielded as a final text chunk.
+    pub async fn invoke_stream(
+        self,
+        mut request: Request,
+    ) -> anyhow::Result<impl Stream<Item = anyhow::Result<CompletionChunk>> + Send>
+    where
+        M: Send + Sync,
+    {
+        if let Err(err) = self.prepare(&mut request).await {
+            self.on_error(&err).await;
+            return Err(err);
+        }
+        let state = InvokeStream {
+            executor: self,
+            request,
+            response: None,
+            streamed: StreamedCompletion::new(),
+            started: Instant::now(),
+            tool_calls: 0,
+            iterations: 0,
+            finished: false,
+        };
+        Ok(stream::unfold(state, |mut state| async move {
+            let chunk = state.next_chunk().await?;
+            Some((chunk, state))
+        }))
+    }
+
+    /// Enriches the request with the knowledges, fits it into the context window and adds
+    /// the prompt into the memory.
+    ///
+    /// Returns what the context policy trimmed from the request, if any.
+    async fn prepare(&self, request: &mut Request) -> anyhow::Result<Option<ContextReport>> {
         request.knowledges = {
             let mut enriched_knowledges = Vec::new();
             for knowledge in self.knowledges.iter() {
@@ -44,23 +281,110 @@ impl<M: Completion> Executor<M> {
             }
             enriched_knowledges
         };
+        let report = match &self.context_policy {
+            Some(policy) => {
+                let model = self.model.read().await;
+                policy
+                    .apply(request, model.prompt_tokenizer(), model.context_size())
+                    .map_err(TaskError::from)?
+            }
+            None => None,
+        };
+        request.cancellation = Some(self.cancellation.token().clone());
         // Add user memory
         self.add_user_message(&request.prompt).await;
-        // Interact with the LLM to get a response.
-        let mut model = self.model.write().await;
-        let response = model.completion(request.clone()).await?;
+        Ok(report)
+    }
 
-        let mut responses = vec![response.content()];
-        self.add_ai_message(&responses[0]).await;
+    /// Executes the tool calls of a model response concurrently and records them and
+    /// their results in the request, so they are sent back to the model.
+    ///
+    /// Returns the joined outputs when all the called tools succeed and return their
+    /// output directly.
+    async fn call_tools(
+        &self,
+        request: &mut Request,
+        content: &str,
+        calls: Vec<ToolCall>,
+    ) -> Option<String> {
+        request
+            .tool_messages
+            .push(ChatMessage::assistant_with_tool_calls(
+                content,
+                calls.clone(),
+            ));
+        // Execute the tool calls, at most `tool_parallelism` at a time.
+        let results: Vec<_> = stream::iter(calls.clone())
+            .map(|call| async move { self.call_tool(&call).await })
+            .buffered(self.tool_parallelism)
+            .collect()
+            .await;
+        let mut outputs = Vec::with_capacity(calls.len());
+        let mut return_direct = true;
+        // Send the results back to the model in the order of the calls.
+        for (call, (output, direct)) in calls.iter().zip(results) {
+            return_direct &= direct;
+            self.add_tool_message(&output, &call.id).await;
+            request
+                .tool_messages
+                .push(ChatMessage::tool_result(&output, &call.id));
+            outputs.push(output);
+        }
+        return_direct.then(|| outputs.join("\n"))
+    }
 
-        // Attempt to parse and execute a tool action.
-        for call in response.toolcalls() {
-            let tool_call = self.execute_tool(call).await?;
-            self.add_ai_message_with_tool_call(&tool_call).await?;
-            responses.push(tool_call);
+    /// Executes a tool call with its hooks, timeout and retry policy.
+    ///
+    /// Returns the output, or the error as a message for the model, and whether the
+    /// output is the final answer.
+    async fn call_tool(&self, call: &ToolCall) -> (String, bool) {
+        let mut call = call.clone();
+        let (mut output, return_direct) = match self.before_tool_call(&mut call).await {
+            ToolCallDecision::Run => match self.cancellation.run(self.run_tool(&call)).await {
+                Ok(output) => output,
+                Err(err) => (format!("Error: Tool call stopped: {err}"), false),
+            },
+            ToolCallDecision::Deny(reason) => (format!("Error: Tool call denied: {reason}"), false),
+            ToolCallDecision::Output(output) => (output, false),
+        };
+        for hook in &self.hooks {
+            hook.after_tool_call(&call, &mut output).await;
         }
+        (output, return_direct)
+    }
 
-        Ok(responses.join("\n"))
+    /// Runs a tool call with its approval, timeout and retry policy.
+    async fn run_tool(&self, call: &ToolCall) -> (String, bool) {
+        let Some(settings) = self.tool_settings(call).await else {
+            return (
+                format!("Error: Tool not found: {}", call.function.name),
+                false,
+            );
+        };
+        let decision = match settings.requires_approval {
+            true => self.approve(call).await,
+            false => ApprovalDecision::Approve,
+        };
+        if let ApprovalDecision::Reject(reason) = decision {
+            return (format!("Error: Tool call rejected: {reason}"), false);
+        }
+        let mut attempt = 0;
+        loop {
+            let result = match settings.timeout {
+                Some(timeout) => tokio::time::timeout(timeout, self.execute_tool(call))
+                    .await
+                    .unwrap_or_else(|_| Err(ToolError::Timeout(timeout).into())),
+                None => self.execute_tool(call).await,
+            };
+            match (result, settings.retry_policy) {
+                (Ok(output), _) => return (output, settings.return_direct),
+                (Err(err), Some(policy)) if attempt < policy.max_retries && is_transient(&err) => {
+                    tokio::time::sleep(policy.delay(attempt)).await;
+                    attempt += 1;
+                }
+                (Err(err), _) => return (format!("Error: {err}"), false),
+            }
+        }
     }
 
     /// Add a user message into the memory if the memory has been set.
@@ -71,26 +395,177 @@ impl<M: Completion> Executor<M> {
         }
     }
 
-    /// Add an AI message into the memory if the memory has been set.
-    async fn add_ai_message(&self, message: &str) {
+    /// Add an AI message and the tool calls it requests into the memory if the memory
+    /// has been set.
+    async fn add_ai_message(&self, message: &str, calls: &[ToolCall]) {
         if let Some(memory) = &self.memory {
             let mut memory = memory.write().await;
-            memory.add_ai_message(message);
+            if calls.is_empty() {
+                memory.add_ai_message(message);
+            } else {
+                memory.add_message(
+                    Message::new_ai_message(message).with_tool_calls(serde_json::json!(calls)),
+                );
+            }
         }
     }
 
-    /// Add an AI message into the memory if the memory has been set.
-    async fn add_ai_message_with_tool_call(&self, tool_call: &str) -> anyhow::Result<()> {
+    /// Add the result of a tool call into the memory if the memory has been set.
+    async fn add_tool_message(&self, output: &str, call_id: &str) {
         if let Some(memory) = &self.memory {
             let mut memory = memory.write().await;
-            let tool_call: serde_json::Value = serde_json::from_str(tool_call)?;
-            memory.add_message(Message::new_ai_message("").with_tool_calls(tool_call));
+            memory.add_message(Message::new_tool_message(output, call_id));
+        }
+    }
+
+    /// Waits for the approver to answer the approval request of a tool call.
+    async fn approve(&self, call: &ToolCall) -> ApprovalDecision {
+        let Some(approver) = &self.approver else {
+            return ApprovalDecision::Reject("no approver is configured".to_string());
+        };
+        approver
+            .approve(ApprovalRequest {
+                tool_call_id: call.id.clone(),
+                tool_name: call.function.name.clone(),
+                arguments: call.function.arguments.clone(),
+            })
+            .await
+    }
+
+    /// Invokes the hooks before a model request.
+    async fn before_request(&self, request: &mut Request) -> anyhow::Result<()> {
+        for hook in &self.hooks {
+            hook.before_request(request).await?;
+        }
+        Ok(())
+    }
+
+    /// Invokes the hooks after a model response.
+    async fn after_response(&self, content: &mut String, calls: &[ToolCall]) {
+        for hook in &self.hooks {
+            hook.after_response(content, calls).await;
+        }
+    }
+
+    /// Invokes the hooks before a tool call until one doesn't let the call run.
+    async fn before_tool_call(&self, call: &mut ToolCall) -> ToolCallDecision {
+        for hook in &self.hooks {
+            match hook.before_tool_call(call).await {
+                ToolCallDecision::Run => {}
+                decision => return decision,
+            }
         }
+        ToolCallDecision::Run
+    }
+
+    /// Invokes the hooks when the turn fails.
+    async fn on_error(&self, error: &anyhow::Error) {
+        for hook in &self.hooks {
+            hook.on_error(error).await;
+        }
+    }
+
+    /// Checks a model request against the budget.
+    fn check_request(&self, request: &Request, model: &M) -> Result<(), TaskError> {
+        let Some(budget) = &self.budget else {
+            return Ok(());
+        };
+        let tokenizer = model.prompt_tokenizer();
+        budget.check_request(request, tokenizer.as_deref())?;
         Ok(())
     }
 
+    /// Checks the number of tool calls requested in the turn against the budget.
+    fn check_tool_calls(&self, calls: usize) -> Result<(), TaskError> {
+        match &self.budget {
+            Some(budget) => Ok(budget.check_tool_calls(calls)?),
+            None => Ok(()),
+        }
+    }
+
+    /// Estimates the cost of a model call and adds its usage to the trackers.
+    fn record_call(
+        &self,
+        model_name: Option<&str>,
+        usage: TokenUsage,
+        latency: Duration,
+    ) -> CallUsage {
+        let cost = self
+            .price_table
+            .as_ref()
+            .zip(model_name)
+            .and_then(|(prices, model_name)| prices.cost(model_name, &usage));
+        let call = CallUsage {
+            usage,
+            latency,
+            cost,
+        };
+        for tracker in &self.usage_trackers {
+            tracker.record_call(&call);
+        }
+        if let Some(budget) = &self.budget {
+            let usage = &call.usage;
+            budget.record_tokens(
+                usage
+                    .total_tokens
+                    .max(usage.prompt_tokens + usage.completion_tokens) as u64,
+            );
+        }
+        call
+    }
+
+    /// Counts a finished turn in the trackers.
+    fn record_turn(&self) {
+        for tracker in &self.usage_trackers {
+            tracker.record_turn();
+        }
+    }
+
+    /// Compresses the memory at the end of a turn if the memory has been set.
+    ///
+    /// The compression is computed without locking the memory, which is only locked to
+    /// apply it, so that the agents sharing the memory can still read it meanwhile.
+    async fn compress_memory(&self) {
+        let Some(memory) = &self.memory else {
+            return;
+        };
+        let Some(compression) = memory.read().await.prepare_compression() else {
+            return;
+        };
+        match compression.await {
+            Ok(compression) => memory.write().await.apply_compression(compression),
+            Err(err) => tracing::error!("Failed to compress the memory: {err}"),
+        }
+    }
+
+    /// Returns the settings of the called tool, or `None` if there is no such tool.
+    async fn tool_settings(&self, call: &ToolCall) -> Option<ToolSettings> {
+        let tools = self.tools.read().await;
+        if let Some(tool) = tools
+            .iter()
+            .find(|t| t.name().eq_ignore_ascii_case(&call.function.name))
+        {
+            return Some(ToolSettings {
+                timeout: tool.timeout().or(self.tool_timeout),
+                retry_policy: tool.retry_policy(),
+                return_direct: tool.return_direct(),
+                requires_approval: tool.requires_approval(),
+            });
+        }
+        let mcp_clients = self.mcp_clients.read().await;
+        mcp_clients
+            .iter()
+            .find(|client| client.tools.contains_key(&call.function.name))
+            .map(|client| ToolSettings {
+                timeout: self.tool_timeout,
+                retry_policy: None,
+                return_direct: false,
+                requires_approval: client.requires_approval,
+            })
+    }
+
     /// Executes a tool action and returns the result.
-    async fn execute_tool(&self, call: ToolCall) -> anyhow::Result<String> {
+    async fn execute_tool(&self, call: &ToolCall) -> anyhow::Result<String> {
         let tools = self.tools.read().await;
         if let Some(tool) = tools
             .iter()
@@ -116,3 +591,735 @@ impl<M: Completion> Executor<M> {
         }
     }
 }
+
+/// Whether a failed tool call may succeed when retried. Invalid arguments are sent back
+/// to the model instead, so it can fix them.
+fn is_transient(err: &anyhow::Error) -> bool {
+    match err.downcast_ref::<ToolError>() {
+        Some(err) => err.is_transient(),
+        None => !err.is::<serde_json::Error>(),
+    }
+}
+
+/// How a tool is called by the executor.
+struct ToolSettings {
+    timeout: Option<Duration>,
+    retry_policy: Option<RetryPolicy>,
+    return_direct: bool,
+    requires_approval: bool,
+}
+
+/// The state of a streamed [`Executor::invoke_stream`] call.
+struct InvokeStream<M: Completion> {
+    executor: Executor<M>,
+    request: Request,
+    /// The response stream of the current model call.
+    response: Option<ResponseStream>,
+    /// The chunks of the current model call received so far.
+    streamed: StreamedCompletion,
+    /// When the current model call started.
+    started: Instant,
+    /// The number of tool calls requested in the turn.
+    tool_calls: usize,
+    iterations: usize,
+    finished: bool,
+}
+
+impl<M: Completion + Send + Sync> InvokeStream<M> {
+    async fn next_chunk(&mut self) -> Option<anyhow::Result<CompletionChunk>> {
+        if self.finished {
+            return None;
+        }
+        let chunk = self.try_next_chunk().await;
+        if let Some(Err(err)) = &chunk {
+            self.executor.on_error(err).await;
+        }
+        if !matches!(chunk, Some(Ok(_))) {
+            self.finished = true;
+        }
+        if self.finished {
+            self.executor.record_turn();
+            self.executor.compress_memory().await;
+        }
+        chunk
+    }
+
+    async fn try_next_chunk(&mut self) -> Option<anyhow::Result<CompletionChunk>> {
+        loop {
+            let Some(response) = &mut self.response else {
+                if self.iterations == self.executor.max_iterations {
+                    return Some(Err(TaskError::MaxIterationsExceeded(
+                        self.executor.max_iterations,
+                    )
+                    .into()));
+                }
+                self.iterations += 1;
+                // Interact with the LLM to get a response stream.
+                if let Err(err) = self.executor.cancellation.check() {
+                    return Some(Err(err.into()));
+                }
+                if let Err(err) = self.executor.before_request(&mut self.request).await {
+                    return Some(Err(err));
+                }
+                let mut model = self.executor.model.write().await;
+                if let Err(err) = self.executor.check_request(&self.request, &*model) {
+                    return Some(Err(err.into()));
+                }
+                self.started = Instant::now();
+                match self
+                    .executor
+                    .cancellation
+                    .run(model.completion_stream(self.request.clone()))
+                    .await
+                {
+                    Ok(Ok(response)) => self.response = Some(response),
+                    Ok(Err(err)) => return Some(Err(err.into())),
+                    Err(err) => return Some(Err(err.into())),
+                }
+                continue;
+            };
+            let next = match self.executor.cancellation.run(response.next()).await {
+                Ok(next) => next,
+                Err(err) => return Some(Err(err.into())),
+            };
+            match next {
+                Some(Ok(chunk)) => {
+                    self.streamed.push(&chunk);
+                    return Some(Ok(chunk));
+                }
+                Some(Err(err)) => return Some(Err(err.into())),
+                None => {
+                    // The model call is finished, execute the tool calls if any.
+                    self.response = None;
+                    let streamed = std::mem::take(&mut self.streamed);
+                    let model_name = self.executor.model.read().await.model_name();
+                    self.executor.record_call(
+                        model_name.as_deref(),
+                        streamed.token_usage.clone().unwrap_or_default(),
+                        self.started.elapsed(),
+                    );
+                    let mut content = streamed.content();
+                    let calls = streamed.toolcalls();
+                    self.executor.after_response(&mut content, &calls).await;
+                    self.tool_calls += calls.len();
+                    if let Err(err) = self.executor.check_tool_calls(self.tool_calls) {
+                        return Some(Err(err.into()));
+                    }
+                    self.executor.add_ai_message(&content, &calls).await;
+                    if calls.is_empty() {
+                        return None;
+                    }
+                    let output = self
+                        .executor
+                        .call_tools(&mut self.request, &content, calls)
+                        .await;
+                    if let Err(err) = self.executor.cancellation.check() {
+                        return Some(Err(err.into()));
+                    }
+                    match output {
+                        Some(output) => {
+                            self.finished = true;
+                            return Some(Ok(CompletionChunk::Text(output)));
+                        }
+                        None => continue,
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::approval::ChannelApprover;
+    use crate::budget::BudgetError;
+    use crate::chat::{CallFunction, CompletionError, ResponseTokenUsage, TokenUsage};
+    use crate::context::count_tokens;
+    use crate::make_ref;
+    use crate::memory::WindowBufferMemory;
+    use crate::tool::{StructureTool, ToolError};
+    use crate::usage::ModelPrice;
+    use alith_client::prelude::PromptTokenizer;
+    use async_trait::async_trait;
+    use schemars::JsonSchema;
+    use serde::Deserialize;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// A model requesting the given tool calls, then answering with their results.
+    struct Scripted(Vec<ToolCall>);
+
+    struct Reply(String, Vec<ToolCall>);
+
+    impl ResponseContent for Reply {
+        fn content(&self) -> String {
+            self.0.clone()
+        }
+    }
+
+    impl ResponseToolCalls for Reply {
+        fn toolcalls(&self) -> Vec<ToolCall> {
+            self.1.clone()
+        }
+    }
+
+    impl ResponseTokenUsage for Reply {
+        fn token_usage(&self) -> TokenUsage {
+            TokenUsage {
+                tokens_cached: None,
+                prompt_tokens: 1000,
+                completion_tokens: 100,
+                total_tokens: 1100,
+            }
+        }
+    }
+
+    impl Completion for Scripted {
+        type Response = Reply;
+
+        fn model_name(&self) -> Option<String> {
+            Some("scripted".to_string())
+        }
+
+        async fn completion(&mut self, request: Request) -> Result<Reply, CompletionError> {
+            if request.tool_messages.is_empty() {
+                return Ok(Reply("Let me check.".to_string(), self.0.clone()));
+            }
+            let results: Vec<&str> = request
+                .tool_messages
+                .iter()
+                .filter(|m| m.role == "tool")
+                .map(|m| m.content.as_str())
+                .collect();
+            Ok(Reply(results.join(" | "), Vec::new()))
+        }
+    }
+
+    fn call(id: &str, name: &str, arguments: &str) -> ToolCall {
+        ToolCall {
+            id: id.to_string(),
+            r#type: "function".to_string(),
+            function: CallFunction {
+                name: name.to_string(),
+                arguments: arguments.to_string(),
+            },
+        }
+    }
+
+    fn executor(model: Scripted, tools: Vec<Box<dyn Tool>>) -> Executor<Scripted> {
+        Executor::new(
+            make_ref(model),
+            Arc::new(Vec::new()),
+            make_ref(tools),
+            None,
+            make_ref(Vec::new()),
+        )
+    }
+
+    fn request() -> Request {
+        Request::new("question".to_string(), String::new())
+    }
+
+    struct Add;
+
+    #[derive(JsonSchema, Deserialize)]
+    struct AddInput {
+        x: usize,
+        y: usize,
+    }
+
+    #[async_trait]
+    impl StructureTool for Add {
+        type Input = AddInput;
+        type Output = usize;
+
+        fn name(&self) -> &str {
+            "add"
+        }
+
+        async fn run_with_args(&self, input: Self::Input) -> Result<Self::Output, ToolError> {
+            Ok(input.x + input.y)
+        }
+    }
+
+    /// A tool failing before the given number of calls, then sleeping for the given duration.
+    struct Flaky {
+        failures: usize,
+        sleep: Duration,
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl Flaky {
+        fn new(failures: usize, sleep: Duration) -> Self {
+            Self {
+                failures,
+                sleep,
+                calls: Arc::new(AtomicUsize::new(0)),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl Tool for Flaky {
+        fn name(&self) -> &str {
+            "flaky"
+        }
+
+        fn definition(&self) -> crate::tool::ToolDefinition {
+            crate::tool::ToolDefinition {
+                name: "flaky".to_string(),
+                description: String::new(),
+                parameters: serde_json::json!({}),
+            }
+        }
+
+        fn timeout(&self) -> Option<Duration> {
+            Some(Duration::from_millis(100))
+        }
+
+        fn retry_policy(&self) -> Option<RetryPolicy> {
+            Some(RetryPolicy::new(2).backoff(Duration::from_millis(1)))
+        }
+
+        async fn run(&self, input: &str) -> Result<String, ToolError> {
+            let calls = self.calls.fetch_add(1, Ordering::SeqCst) + 1;
+            serde_json::from_str::<serde_json::Value>(input)?;
+            if calls <= self.failures {
+                return Err(ToolError::Unknown(format!("failure {calls}")));
+            }
+            tokio::time::sleep(self.sleep).await;
+            Ok(format!("ok after {calls} calls"))
+        }
+    }
+
+    /// A tool reading the name of a model.
+    struct ModelName(Ref<Scripted>);
+
+    #[async_trait]
+    impl Tool for ModelName {
+        fn name(&self) -> &str {
+            "model_name"
+        }
+
+        fn definition(&self) -> crate::tool::ToolDefinition {
+            crate::tool::ToolDefinition {
+                name: "model_name".to_string(),
+                description: String::new(),
+                parameters: serde_json::json!({}),
+            }
+        }
+
+        async fn run(&self, _input: &str) -> Result<String, ToolError> {
+            Ok(self.0.read().await.model_name().unwrap_or_default())
+        }
+    }
+
+    /// A scripted model recording the requests it receives.
+    struct Recording(Scripted, Arc<std::sync::Mutex<Vec<Request>>>);
+
+    impl Completion for Recording {
+        type Response = Reply;
+
+        async fn completion(&mut self, request: Request) -> Result<Reply, CompletionError> {
+            self.1.lock().unwrap().push(request.clone());
+            self.0.completion(request).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_invoke_loops_until_a_final_answer() {
+        let requests = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let model = Recording(
+            Scripted(vec![call("call-1", "add", r#"{"x": 1, "y": 2}"#)]),
+            requests.clone(),
+        );
+        let tools: Vec<Box<dyn Tool>> = vec![Box::new(Add)];
+        let mut executor = Executor::new(
+            make_ref(model),
+            Arc::new(Vec::new()),
+            make_ref(tools),
+            None,
+            make_ref(Vec::new()),
+        );
+        assert_eq!(executor.invoke(request()).await.unwrap(), "3");
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0].tool_messages.is_empty());
+        // The second request links the tool result to the call of the model.
+        let messages = requests[1].map_messages();
+        let roles: Vec<&str> = messages.iter().map(|m| m["role"].as_str()).collect();
+        assert_eq!(roles, ["system", "user", "assistant", "tool"]);
+        assert!(messages[2]["tool_calls"].contains("call-1"));
+        assert_eq!(messages[3]["tool_call_id"], "call-1");
+        assert_eq!(messages[3]["content"], "3");
+    }
+
+    #[tokio::test]
+    async fn test_tool_calls_are_kept_in_memory() {
+        let memory: Ref<dyn Memory> = make_ref(WindowBufferMemory::new(10));
+        let model = Scripted(vec![call("call-1", "add", r#"{"x": 1, "y": 2}"#)]);
+        let mut executor = executor(model, vec![Box::new(Add)]);
+        executor.memory = Some(memory.clone());
+        let output = executor.invoke(request()).await.unwrap();
+        assert_eq!(output, "3");
+
+        let history: Vec<ChatMessage> = memory
+            .read()
+            .await
+            .messages()
+            .iter()
+            .map(ChatMessage::from)
+            .collect();
+        let roles: Vec<&str> = history.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, ["user", "assistant", "tool", "assistant"]);
+        assert_eq!(history[1].tool_calls[0].function.name, "add");
+        assert_eq!(history[2].content, "3");
+        assert_eq!(history[2].tool_call_id.as_deref(), Some("call-1"));
+    }
+
+    #[tokio::test]
+    async fn test_invoke_response_reports_usage() {
+        let tracker = UsageTracker::new();
+        let model = Scripted(vec![call("call-1", "add", r#"{"x": 1, "y": 2}"#)]);
+        let prices = PriceTable::new().price("scripted", ModelPrice::new(1.0, 10.0));
+        let mut executor = executor(model, vec![Box::new(Add)])
+            .with_price_table(Arc::new(prices))
+            .with_usage_tracker(tracker.clone());
+        let response = executor.invoke_response(request()).await.unwrap();
+        assert_eq!(response.content, "3");
+        assert_eq!(response.tool_calls.len(), 1);
+        assert_eq!(response.calls.len(), 2);
+        assert_eq!(response.usage.total_tokens, 2200);
+        assert_eq!(response.cost, Some(0.004));
+
+        executor.invoke(request()).await.unwrap();
+        let stats = tracker.stats();
+        assert_eq!((stats.turns, stats.requests), (2, 4));
+        assert_eq!(stats.prompt_tokens, 4000);
+        assert!((stats.cost - 0.008).abs() < 1e-12);
+    }
+
+    struct Words;
+
+    impl PromptTokenizer for Words {
+        fn tokenize(&self, input: &str) -> Vec<u32> {
+            input.split_whitespace().map(|_| 0).collect()
+        }
+
+        fn count_tokens(&self, input: &str) -> u32 {
+            input.split_whitespace().count() as u32
+        }
+    }
+
+    #[tokio::test]
+    async fn test_invoke_response_reports_the_trimmed_context() {
+        let mut long = request();
+        long.history = vec![
+            ChatMessage::new("user", "one two three four five six seven eight"),
+            ChatMessage::new("assistant", "nine"),
+        ];
+        let tokens = count_tokens(&long, &Words);
+        let policy = ContextPolicy::new()
+            .tokenizer(Arc::new(Words))
+            .max_tokens(tokens - 1);
+        let response = executor(Scripted(Vec::new()), Vec::new())
+            .with_context_policy(policy)
+            .invoke_response(long)
+            .await
+            .unwrap();
+        let report = response.context.unwrap();
+        assert_eq!(report.dropped_messages, 1);
+        assert_eq!(report.max_tokens, tokens - 1);
+
+        let response = executor(Scripted(Vec::new()), Vec::new())
+            .invoke_response(request())
+            .await
+            .unwrap();
+        assert!(response.context.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_budget_stops_the_turn() {
+        let model = Scripted(vec![
+            call("call-1", "add", r#"{"x": 1, "y": 2}"#),
+            call("call-2", "add", r#"{"x": 3, "y": 4}"#),
+        ]);
+        let budget = Budget::new().max_tool_calls_per_turn(1);
+        let mut executor = executor(model, vec![Box::new(Add)]).with_budget(budget.clone());
+        let err = executor.invoke(request()).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<TaskError>(),
+            Some(TaskError::BudgetExceeded(BudgetError::ToolCalls {
+                calls: 2,
+                max_calls: 1
+            }))
+        ));
+        assert_eq!(budget.usage().session_tokens, 1100);
+
+        let budget = Budget::new().max_session_tokens(1000);
+        let mut executor = executor.with_budget(budget);
+        let err = executor.invoke(request()).await.unwrap_err();
+        assert!(err.to_string().contains("limit is 1000"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn test_cancellation_stops_the_turn() {
+        let memory: Ref<dyn Memory> = make_ref(WindowBufferMemory::new(10));
+        let model = Scripted(vec![call("call-1", "flaky", "{}")]);
+        let tool = Flaky::new(0, Duration::from_millis(50));
+        let mut executor = executor(model, vec![Box::new(tool)])
+            .with_cancellation(Cancellation::new().timeout(Duration::from_millis(10)));
+        executor.memory = Some(memory.clone());
+        let err = executor.invoke(request()).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<TaskError>(),
+            Some(TaskError::DeadlineExceeded)
+        ));
+        let history: Vec<ChatMessage> = memory
+            .read()
+            .await
+            .messages()
+            .iter()
+            .map(ChatMessage::from)
+            .collect();
+        let roles: Vec<&str> = history.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, ["user", "assistant", "tool"]);
+        assert_eq!(history[2].tool_call_id.as_deref(), Some("call-1"));
+
+        let cancellation = Cancellation::new();
+        cancellation.cancel();
+        let mut executor = executor.with_cancellation(cancellation);
+        let err = executor.invoke(request()).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<TaskError>(),
+            Some(TaskError::Cancelled)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_tools_can_use_the_model() {
+        let model = make_ref(Scripted(vec![call("call-1", "model_name", "{}")]));
+        let tools: Vec<Box<dyn Tool>> = vec![Box::new(ModelName(model.clone()))];
+        let mut executor = Executor::new(
+            model,
+            Arc::new(Vec::new()),
+            make_ref(tools),
+            None,
+            make_ref(Vec::new()),
+        );
+        let output = tokio::time::timeout(Duration::from_secs(1), executor.invoke(request()))
+            .await
+            .expect("the model is locked during the tool calls")
+            .unwrap();
+        assert_eq!(output, "scripted");
+    }
+
+    #[tokio::test]
+    async fn test_tool_errors_are_sent_back_to_the_model() {
+        let model = Scripted(vec![
+            call("call-1", "add", r#"{"x": 1}"#),
+            call("call-2", "search", "{}"),
+            call("call-3", "flaky", "{}"),
+        ]);
+        let tools: Vec<Box<dyn Tool>> =
+            vec![Box::new(Add), Box::new(Flaky::new(2, Duration::ZERO))];
+        let output = executor(model, tools).invoke(request()).await.unwrap();
+        let results: Vec<&str> = output.split(" | ").collect();
+        assert!(results[0].starts_with("Error: JsonError"));
+        assert_eq!(results[1], "Error: Tool not found: search");
+        assert_eq!(results[2], "ok after 3 calls");
+    }
+
+    #[tokio::test]
+    async fn test_invalid_arguments_are_not_retried() {
+        let flaky = Flaky::new(0, Duration::ZERO);
+        let calls = flaky.calls.clone();
+        let model = Scripted(vec![call("call-1", "flaky", "{")]);
+        let output = executor(model, vec![Box::new(flaky)])
+            .invoke(request())
+            .await
+            .unwrap();
+        assert!(output.starts_with("Error: JsonError"), "{output}");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_tool_calls_run_concurrently_with_timeouts() {
+        let model = Scripted(vec![call("call-1", "flaky", "{}"); 3]);
+        let tools: Vec<Box<dyn Tool>> = vec![Box::new(Flaky::new(0, Duration::from_millis(60)))];
+        // The clock is paused and only advances to the next timer, so the three calls
+        // take 60ms when they run concurrently and 180ms one after the other.
+        let start = tokio::time::Instant::now();
+        let output = executor(model, tools)
+            .with_tool_parallelism(3)
+            .invoke(request())
+            .await
+            .unwrap();
+        assert_eq!(output.matches("ok after").count(), 3);
+        assert_eq!(start.elapsed(), Duration::from_millis(60));
+
+        let model = Scripted(vec![call("call-1", "flaky", "{}")]);
+        let tools: Vec<Box<dyn Tool>> = vec![Box::new(Flaky::new(0, Duration::from_secs(1)))];
+        let output = executor(model, tools).invoke(request()).await.unwrap();
+        assert_eq!(output, "Error: The tool call timed out after 100ms");
+    }
+
+    /// A hook doubling the first argument of `add`, denying `flaky` and spelling out fours.
+    #[derive(Default)]
+    struct Guard {
+        errors: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl Hook for Guard {
+        async fn before_request(&self, request: &mut Request) -> anyhow::Result<()> {
+            anyhow::ensure!(!request.prompt.contains("secret"), "the prompt is secret");
+            Ok(())
+        }
+
+        async fn before_tool_call(&self, call: &mut ToolCall) -> ToolCallDecision {
+            match call.function.name.as_str() {
+                "add" => {
+                    call.function.arguments = r#"{"x": 2, "y": 2}"#.to_string();
+                    ToolCallDecision::Run
+                }
+                "flaky" => ToolCallDecision::Deny("not allowed".to_string()),
+                _ => ToolCallDecision::Run,
+            }
+        }
+
+        async fn after_tool_call(&self, _call: &ToolCall, output: &mut String) {
+            *output = output.replace('4', "four");
+        }
+
+        async fn on_error(&self, _error: &anyhow::Error) {
+            self.errors.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hooks_change_tool_calls() {
+        let guard = Arc::new(Guard::default());
+        let model = Scripted(vec![
+            call("call-1", "add", r#"{"x": 1, "y": 2}"#),
+            call("call-2", "flaky", "{}"),
+        ]);
+        let tools: Vec<Box<dyn Tool>> =
+            vec![Box::new(Add), Box::new(Flaky::new(0, Duration::ZERO))];
+        let mut executor = executor(model, tools).with_hooks(vec![guard.clone()]);
+        let output = executor.invoke(request()).await.unwrap();
+        assert_eq!(output, "four | Error: Tool call denied: not allowed");
+
+        let secret = Request::new("a secret".to_string(), String::new());
+        assert!(executor.invoke(secret).await.is_err());
+        assert_eq!(guard.errors.load(Ordering::SeqCst), 1);
+    }
+
+    /// The `add` tool under another name, requiring approval.
+    struct Transfer;
+
+    #[async_trait]
+    impl Tool for Transfer {
+        fn name(&self) -> &str {
+            "transfer"
+        }
+
+        fn definition(&self) -> crate::tool::ToolDefinition {
+            Tool::definition(&Add)
+        }
+
+        fn requires_approval(&self) -> bool {
+            true
+        }
+
+        async fn run(&self, input: &str) -> Result<String, ToolError> {
+            Tool::run(&Add, input).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tool_calls_wait_for_approval() {
+        let calls = vec![
+            call("call-1", "transfer", r#"{"x": 1, "y": 2}"#),
+            call("call-2", "transfer", r#"{"x": 100, "y": 2}"#),
+        ];
+        let (approver, mut requests) = ChannelApprover::new(1);
+        tokio::spawn(async move {
+            while let Some(pending) = requests.recv().await {
+                if pending.request.arguments.contains("100") {
+                    pending.reject("too much");
+                } else {
+                    pending.approve();
+                }
+            }
+        });
+        let output = executor(Scripted(calls.clone()), vec![Box::new(Transfer)])
+            .with_approver(Arc::new(approver))
+            .invoke(request())
+            .await
+            .unwrap();
+        assert_eq!(output, "3 | Error: Tool call rejected: too much");
+
+        let output = executor(Scripted(calls), vec![Box::new(Transfer)])
+            .invoke(request())
+            .await
+            .unwrap();
+        assert_eq!(output.matches("no approver is configured").count(), 2);
+    }
+
+    /// An MCP server on stdin and stdout with a `transfer` tool.
+    #[cfg(unix)]
+    const MCP_SERVER: &str = r#"
+while read -r line; do
+  id=$(printf '%s' "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p')
+  case "$line" in
+    *'"initialize"'*) result='{"protocolVersion":"2024-11-05","capabilities":{},"serverInfo":{"name":"bank","version":"1.0.0"}}' ;;
+    *'"tools/list"'*) result='{"tools":[{"name":"transfer","description":"Transfers money","inputSchema":{"type":"object"}}]}' ;;
+    *'"tools/call"'*) result='{"content":[{"type":"text","text":"transferred"}],"isError":false}' ;;
+    *) continue ;;
+  esac
+  printf '{"jsonrpc":"2.0","id":%s,"result":%s}\n' "$id" "$result"
+done
+"#;
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_mcp_tool_calls_wait_for_approval() {
+        let client = crate::mcp::stdio_client("sh", vec!["-c", MCP_SERVER], Default::default())
+            .await
+            .unwrap()
+            .requires_approval(true);
+        let mcp_clients = make_ref(vec![client]);
+        let executor = || {
+            let model = Scripted(vec![call("call-1", "transfer", "{}")]);
+            let tools = make_ref(Vec::new());
+            Executor::new(
+                make_ref(model),
+                Arc::new(Vec::new()),
+                tools,
+                None,
+                mcp_clients.clone(),
+            )
+        };
+        let (approver, mut requests) = ChannelApprover::new(1);
+        tokio::spawn(async move {
+            while let Some(pending) = requests.recv().await {
+                assert_eq!(pending.request.tool_name, "transfer");
+                pending.approve();
+            }
+        });
+        let output = executor()
+            .with_approver(Arc::new(approver))
+            .invoke(request())
+            .await
+            .unwrap();
+        assert_eq!(output, "transferred");
+
+        let output = executor().invoke(request()).await.unwrap();
+        assert_eq!(
+            output,
+            "Error: Tool call rejected: no approver is configured"
+        );
+    }
+}
@@ -1,12 +1,4 @@
-use std::marker::PhantomData;
-
-use crate::{
-    agent::Agent,
-    chat::{Chat, Completion},
-    task::TaskError,
-    tool::{StructureTool, ToolError},
-};
-use async_trait::async_trait;
+use crate::{agent::Agent, chat::Completion, structured::StructuredOutputError, task::TaskError};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
@@ -29,14 +21,7 @@ where
     {
         Self {
             agent: Agent::new("extract-agent", model)
-                .preamble(
-                    r#"Extract the data structure from the input string.
-Note you MUST use the tool named `extractor` to extract the input string to the
-data structure.
-"#,
-                )
-                .tool(ExtractTool::<T> { _data: PhantomData })
-                .await,
+                .preamble("Extract the data structure from the input string."),
         }
     }
 
@@ -46,7 +31,7 @@ data structure.
     where
         T: Serialize + for<'a> Deserialize<'a> + JsonSchema + Send + Sync + 'static,
     {
-        Ok(serde_json::from_str(&self.agent.prompt(input).await?)?)
+        Ok(self.agent.prompt_typed(input).await?)
     }
 }
 
@@ -57,32 +42,6 @@ pub enum ExtractionError {
     TaskError(#[from] TaskError),
     #[error("JsonError: {0}")]
     JsonError(#[from] serde_json::Error),
-}
-
-struct ExtractTool<T>
-where
-    T: Serialize + for<'a> Deserialize<'a> + JsonSchema + Send + Sync,
-{
-    _data: PhantomData<T>,
-}
-
-#[async_trait]
-impl<T> StructureTool for ExtractTool<T>
-where
-    T: Serialize + for<'a> Deserialize<'a> + JsonSchema + Send + Sync,
-{
-    type Input = T;
-    type Output = T;
-
-    fn name(&self) -> &str {
-        "extractor"
-    }
-
-    fn description(&self) -> &str {
-        "Extract the data structure from the input string."
-    }
-
-    async fn run_with_args(&self, input: Self::Input) -> Result<Self::Output, ToolError> {
-        Ok(input)
-    }
+    #[error("StructuredOutputError: {0}")]
+    StructuredOutputError(#[from] StructuredOutputError),
 }
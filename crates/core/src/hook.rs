@@ -0,0 +1,49 @@
+use crate::chat::{Request, ToolCall};
+use async_trait::async_trait;
+
+/// What to do with a tool call, decided by [`Hook::before_tool_call`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ToolCallDecision {
+    /// Run the tool call.
+    Run,
+    /// Don't run the tool call and send the reason back to the model as an error.
+    Deny(String),
+    /// Don't run the tool call and send this output back to the model, e.g. a cached result.
+    Output(String),
+}
+
+/// Callbacks observing and changing the behaviour of an agent, invoked by the
+/// [`crate::executor::Executor`] in the order the hooks were added.
+///
+/// All the callbacks do nothing by default, so a hook only implements the ones it needs,
+/// e.g. to log, redact, cache or gate tool calls.
+#[async_trait]
+pub trait Hook: Send + Sync {
+    /// Called before each model request, can edit the request.
+    ///
+    /// Returning an error aborts the turn.
+    async fn before_request(&self, _request: &mut Request) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Called after each model response with its content and tool calls, can edit the content.
+    ///
+    /// When streaming, the chunks were already sent and the edited content is only stored
+    /// into the memory and sent back to the model with the tool results.
+    async fn after_response(&self, _content: &mut String, _calls: &[ToolCall]) {}
+
+    /// Called before each tool call, can change its arguments, deny it or provide its output.
+    ///
+    /// The first hook not returning [`ToolCallDecision::Run`] decides for the call.
+    async fn before_tool_call(&self, _call: &mut ToolCall) -> ToolCallDecision {
+        ToolCallDecision::Run
+    }
+
+    /// Called after each tool call with the output sent back to the model, can edit it.
+    ///
+    /// The output of a failed call is its error message.
+    async fn after_tool_call(&self, _call: &ToolCall, _output: &mut String) {}
+
+    /// Called when the turn fails, e.g. when the model request fails.
+    async fn on_error(&self, _error: &anyhow::Error) {}
+}
@@ -1,12 +1,17 @@
 pub mod agent;
+pub mod approval;
+pub mod budget;
+pub mod cancel;
 pub mod chat;
 pub mod chunking;
 pub mod cleaner;
 pub mod concatenator;
+pub mod context;
 pub mod embeddings;
 pub mod executor;
 pub mod extractor;
 pub mod flow;
+pub mod hook;
 pub mod json;
 pub mod knowledge;
 pub mod llm;
@@ -15,8 +20,12 @@ pub mod memory;
 pub mod parser;
 pub mod splitting;
 pub mod store;
+pub mod strategy;
+pub mod structured;
 pub mod task;
+pub mod team;
 pub mod tool;
+pub mod usage;
 
 pub use alith_client as client;
 pub use alith_interface as interface;
@@ -1,8 +1,9 @@
 pub mod client;
 
-use crate::chat::{Completion, CompletionError};
+use crate::chat::{Completion, CompletionError, ResponseStream};
 use crate::embeddings::{Embeddings, EmbeddingsData, EmbeddingsError};
 pub use crate::llm::client::ClientConfig;
+use alith_client::prelude::PromptTokenizer;
 use anyhow::Result;
 use async_trait::async_trait;
 use client::{Client, CompletionResponse};
@@ -14,7 +15,6 @@ pub use fastembed::{
     EmbeddingModel as FastEmbeddingsModelName, ExecutionProviderDispatch,
     InitOptions as FastEmbeddingsModelOptions,
 };
-#[cfg(feature = "fastembed")]
 use std::sync::Arc;
 
 // OpenAI models
@@ -123,6 +123,29 @@ impl Completion for LLM {
     ) -> Result<Self::Response, CompletionError> {
         self.client.completion(request).await
     }
+
+    async fn completion_stream(
+        &mut self,
+        request: crate::chat::Request,
+    ) -> Result<ResponseStream, CompletionError> {
+        self.client.completion_stream(request).await
+    }
+
+    fn prompt_tokenizer(&self) -> Option<Arc<dyn PromptTokenizer>> {
+        self.client.prompt_tokenizer()
+    }
+
+    fn context_size(&self) -> Option<usize> {
+        self.client.context_size()
+    }
+
+    fn model_name(&self) -> Option<String> {
+        Some(self.model.clone())
+    }
+
+    fn supports_json_schema(&self) -> bool {
+        self.client.supports_json_schema()
+    }
 }
 
 #[derive(Clone)]
@@ -141,12 +164,17 @@ impl Embeddings for EmbeddingsModel {
     ) -> Result<Vec<EmbeddingsData>, EmbeddingsError> {
         self.client.embed_texts(&self.model, input).await
     }
+
+    fn model_name(&self) -> Option<String> {
+        Some(self.model.clone())
+    }
 }
 
 #[cfg(feature = "fastembed")]
 #[derive(Clone)]
 pub struct FastEmbeddingsModel {
     model: Arc<TextEmbedding>,
+    model_name: String,
 }
 
 #[cfg(feature = "fastembed")]
@@ -157,9 +185,11 @@ impl FastEmbeddingsModel {
     ///
     /// Uses the total number of CPUs available as the number of intra-threads.
     pub fn try_new(opts: FastEmbeddingsModelOptions) -> anyhow::Result<Self> {
+        let model_name = format!("{:?}", opts.model_name);
         let model = TextEmbedding::try_new(opts)?;
         Ok(Self {
             model: Arc::new(model),
+            model_name,
         })
     }
 
@@ -197,4 +227,8 @@ impl Embeddings for FastEmbeddingsModel {
             })
             .collect())
     }
+
+    fn model_name(&self) -> Option<String> {
+        Some(self.model_name.clone())
+    }
 }
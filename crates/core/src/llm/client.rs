@@ -4,18 +4,22 @@ use std::ops::DerefMut;
 use std::str::FromStr;
 use std::sync::Arc;
 
-use crate::chat::CallFunction;
 use crate::chat::Completion;
 use crate::chat::CompletionError;
+use crate::chat::Message;
 use crate::chat::Request;
 use crate::chat::ResponseContent;
+use crate::chat::ResponseStream;
 use crate::chat::ResponseTokenUsage;
 use crate::chat::ResponseToolCalls;
+use crate::chat::StreamedCompletion;
 use crate::chat::ToolCall;
 use crate::embeddings::EmbeddingsData;
 use crate::embeddings::EmbeddingsError;
+use alith_interface::LLMBackend;
 use alith_interface::requests::completion::TokenUsage;
 use anyhow::Result;
+use futures::StreamExt;
 
 pub use alith_client as client;
 pub use alith_client::LLMClient;
@@ -143,18 +147,23 @@ impl ResponseToolCalls for CompletionResponse {
             .as_ref()
             .unwrap_or(&Vec::new())
             .iter()
-            .map(|call| ToolCall {
-                id: call.id.clone(),
-                r#type: call.r#type.clone(),
-                function: CallFunction {
-                    name: call.function.name.clone(),
-                    arguments: call.function.arguments.clone(),
-                },
-            })
+            .map(ToolCall::from)
             .collect()
     }
 }
 
+impl ResponseContent for StreamedCompletion {
+    fn content(&self) -> String {
+        self.content.to_string()
+    }
+}
+
+impl ResponseToolCalls for StreamedCompletion {
+    fn toolcalls(&self) -> Vec<ToolCall> {
+        self.tool_calls().iter().map(ToolCall::from).collect()
+    }
+}
+
 impl ResponseTokenUsage for CompletionResponse {
     fn token_usage(&self) -> TokenUsage {
         self.token_usage.clone()
@@ -171,6 +180,52 @@ impl Completion for Client {
     type Response = CompletionResponse;
 
     async fn completion(&mut self, request: Request) -> Result<Self::Response, CompletionError> {
+        let mut completion = self.chat_completion_request(&request)?;
+        // Execute the completion request
+        completion
+            .run()
+            .await
+            .map_err(|err| CompletionError::Normal(err.to_string()))
+    }
+
+    async fn completion_stream(
+        &mut self,
+        request: Request,
+    ) -> Result<ResponseStream, CompletionError> {
+        let mut completion = self.chat_completion_request(&request)?;
+        // Execute the streaming completion request
+        let stream = completion
+            .stream()
+            .await
+            .map_err(|err| CompletionError::Normal(err.to_string()))?;
+        Ok(Box::pin(stream.map(|chunk| {
+            chunk.map_err(|err| CompletionError::Normal(err.to_string()))
+        })))
+    }
+
+    fn prompt_tokenizer(&self) -> Option<Arc<dyn PromptTokenizer>> {
+        Some(Arc::clone(self.client.backend.tokenizer()) as Arc<dyn PromptTokenizer>)
+    }
+
+    fn context_size(&self) -> Option<usize> {
+        Some(self.client.backend.model_ctx_size() as usize)
+    }
+
+    fn model_name(&self) -> Option<String> {
+        Some(self.client.backend.model_id().to_string())
+    }
+
+    fn supports_json_schema(&self) -> bool {
+        matches!(*self.client.backend, LLMBackend::OpenAI(_))
+    }
+}
+
+impl Client {
+    /// Builds the backend chat completion request from a `Request`.
+    fn chat_completion_request(
+        &self,
+        request: &Request,
+    ) -> Result<ChatCompletion, CompletionError> {
         // New the complation request
         let mut completion = self.client.chat_completion();
         if let Some(temperature) = request.temperature {
@@ -184,20 +239,35 @@ impl Completion for Client {
         }
         // Construct the prompt
         let prompt = completion.prompt();
-        // Add preamble if provided
-        if !request.preamble.trim().is_empty() {
+        // Add preamble and the system messages of the history, e.g. a memory summary,
+        // as one system message since it must be the first message.
+        let system = std::iter::once(request.preamble.as_str())
+            .chain(
+                request
+                    .history
+                    .iter()
+                    .filter(|msg| msg.role == "system")
+                    .map(|msg| msg.content.as_str()),
+            )
+            .filter(|content| !content.trim().is_empty())
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        if !system.is_empty() {
             prompt
                 .add_system_message()
                 .map_err(|err| CompletionError::Normal(err.to_string()))?
-                .set_content(&request.preamble);
+                .set_content(&system);
         }
         // Add conversation history
         for msg in &request.history {
+            if msg.role == "tool" || !msg.tool_calls.is_empty() {
+                add_tool_message(prompt, msg)?;
+                continue;
+            }
             let result = match msg.role.as_str() {
-                "system" => prompt.add_system_message(),
                 "user" => prompt.add_user_message(),
                 "assistant" => prompt.add_assistant_message(),
-                _ => continue, // Just skip unknown roles
+                _ => continue, // Just skip system messages and unknown roles
             };
             result
                 .map_err(|err| CompletionError::Normal(err.to_string()))?
@@ -207,14 +277,38 @@ impl Completion for Client {
             .add_user_message()
             .map_err(|err| CompletionError::Normal(err.to_string()))?
             .set_content(request.effective_prompt().as_str());
+        // Add the tool calls and tool results of the current turn
+        for msg in &request.tool_messages {
+            add_tool_message(prompt, msg)?;
+        }
         // Add custom tools
         completion.base_req.tools.append(&mut request.tools.clone());
-        // Execute the completion request
-        completion
-            .run()
-            .await
-            .map_err(|err| CompletionError::Normal(err.to_string()))
+        completion.base_req.response_format = request.response_format.clone();
+        completion.base_req.cancellation = request.cancellation.clone();
+        Ok(completion)
+    }
+}
+
+/// Adds an assistant tool call message or a tool result message into the prompt.
+fn add_tool_message(prompt: &LLMPrompt, msg: &Message) -> Result<(), CompletionError> {
+    let message = match msg.role.as_str() {
+        "assistant" => prompt.add_assistant_message(),
+        "tool" => prompt.add_tool_message(),
+        role => {
+            return Err(CompletionError::Normal(format!(
+                "unsupported tool message role {role}"
+            )));
+        }
+    }
+    .map_err(|err| CompletionError::Normal(err.to_string()))?;
+    message.set_content(&msg.content);
+    if !msg.tool_calls.is_empty() {
+        message.set_attribute("tool_calls", serde_json::to_string(&msg.tool_calls)?);
+    }
+    if let Some(tool_call_id) = &msg.tool_call_id {
+        message.set_attribute("tool_call_id", tool_call_id);
     }
+    Ok(())
 }
 
 impl Client {
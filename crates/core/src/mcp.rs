@@ -19,6 +19,9 @@ pub struct MCPServerConfig {
     pub args: Vec<String>,
     #[serde(default)]
     pub env: HashMap<String, String>,
+    /// Whether the calls to the tools of the server must be approved before running.
+    #[serde(default, rename = "requiresApproval")]
+    pub requires_approval: bool,
 }
 
 #[derive(Deserialize)]
@@ -30,6 +33,17 @@ pub struct MCPConfig {
 pub struct MCPClient {
     pub client: Box<dyn McpClientTrait>,
     pub tools: HashMap<String, ToolDefinition>,
+    /// Whether the calls to the tools of the client must be approved before running.
+    /// See [`crate::approval::Approver`].
+    pub requires_approval: bool,
+}
+
+impl MCPClient {
+    /// Sets whether the calls to the tools of the client must be approved before running.
+    pub fn requires_approval(mut self, requires_approval: bool) -> Self {
+        self.requires_approval = requires_approval;
+        self
+    }
 }
 
 impl Deref for MCPClient {
@@ -67,7 +81,9 @@ pub async fn setup_mcp_clients<P: AsRef<Path>>(
 
     // For each server in the config, spawn an MCP client
     for (server_name, server_conf) in config.mcp_servers {
-        let client = stdio_client(server_conf.command, server_conf.args, server_conf.env).await?;
+        let client = stdio_client(server_conf.command, server_conf.args, server_conf.env)
+            .await?
+            .requires_approval(server_conf.requires_approval);
         mcp_clients_map.insert(server_name, client);
     }
 
@@ -124,6 +140,7 @@ pub async fn sse_client<S: AsRef<str>>(
     Ok(MCPClient {
         client: Box::new(client),
         tools,
+        requires_approval: false,
     })
 }
 
@@ -171,5 +188,6 @@ pub async fn stdio_client<S: AsRef<str>>(
     Ok(MCPClient {
         client: Box::new(client),
         tools,
+        requires_approval: false,
     })
 }
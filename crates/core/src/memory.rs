@@ -1,8 +1,40 @@
+use futures::future::BoxFuture;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
+mod jsonl;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod store;
+mod summary;
+
+pub use jsonl::{JsonlMemory, JsonlMemoryStore};
+#[cfg(feature = "sqlite")]
+pub use sqlite::{SqliteMemory, SqliteMemoryStore};
+pub use store::MemoryStore;
+pub use summary::SummaryBufferMemory;
+
+#[derive(Debug, thiserror::Error)]
+pub enum MemoryError {
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+    /// JSON error (e.g.: serialization, deserialization, etc.)
+    #[error("JSON error: {0}")]
+    JsonError(#[from] serde_json::Error),
+    #[cfg(feature = "sqlite")]
+    #[error("SQLite error: {0}")]
+    SqliteError(#[from] rusqlite::Error),
+    #[error("Invalid session ID: {0}")]
+    InvalidSessionId(String),
+    /// The memory of the session is locked by a running turn, retry once it ended.
+    #[error("Session {0} is in use")]
+    SessionInUse(String),
+    #[error("Completion error: {0}")]
+    CompletionError(#[from] crate::chat::CompletionError),
+}
+
 /// Represents the type of a message.
 #[derive(PartialEq, Eq, Serialize, Deserialize, Debug, Clone)]
 pub enum MessageType {
@@ -127,6 +159,34 @@ pub trait Memory: Send + Sync {
     /// Clears all messages from memory.
     fn clear(&mut self);
 
+    /// Compresses the memory, e.g. by summarizing old messages.
+    ///
+    /// Runs [`Memory::prepare_compression`] and applies its result.
+    fn compress(&mut self) -> BoxFuture<'_, Result<(), MemoryError>> {
+        Box::pin(async move {
+            if let Some(compression) = self.prepare_compression() {
+                let compression = compression.await?;
+                self.apply_compression(compression);
+            }
+            Ok(())
+        })
+    }
+
+    /// Returns the computation of the compression of the memory, e.g. the summary of its
+    /// old messages, or `None` if the memory doesn't need to be compressed.
+    ///
+    /// The computation works on a snapshot of the memory, so that a shared memory isn't
+    /// locked while it runs. The executor calls it at the end of each turn, the default
+    /// never compresses the memory.
+    fn prepare_compression(&self) -> Option<BoxFuture<'static, Result<Compression, MemoryError>>> {
+        None
+    }
+
+    /// Applies a compression computed by [`Memory::prepare_compression`].
+    ///
+    /// The compression is discarded if the memory was cleared or compressed meanwhile.
+    fn apply_compression(&mut self, _compression: Compression) {}
+
     /// Converts the memory's messages to a string representation.
     fn to_string(&self) -> String {
         self.messages()
@@ -137,6 +197,18 @@ pub trait Memory: Send + Sync {
     }
 }
 
+/// A compression of a memory computed by [`Memory::prepare_compression`].
+#[derive(Debug, Clone)]
+pub struct Compression {
+    /// The number of oldest messages the compression replaces.
+    pub compressed: usize,
+    /// The text replacing these messages, e.g. their summary.
+    pub summary: String,
+    /// The generation of the memory the compression was computed from, changed by the
+    /// memory whenever it is cleared or compressed.
+    pub generation: u64,
+}
+
 /// Converts a type implementing `Memory` into a boxed trait object.
 impl<M> From<M> for Box<dyn Memory>
 where
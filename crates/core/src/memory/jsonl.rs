@@ -0,0 +1,196 @@
+use super::store::{Sessions, validate_session_id};
+use super::{Memory, MemoryError, MemoryStore, Message};
+use crate::Ref;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+/// A memory persisted in a JSON Lines file, one message per line.
+///
+/// Messages are appended and synced to the file when they are added, so the conversation
+/// survives restarts.
+///
+/// The [`Memory`] methods log the failures to write the file, use
+/// [`JsonlMemory::try_add_message`] and [`JsonlMemory::try_clear`] to handle them.
+pub struct JsonlMemory {
+    path: PathBuf,
+    messages: Vec<Message>,
+}
+
+impl JsonlMemory {
+    /// Opens the memory stored at `path`, creating it on the first added message.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, MemoryError> {
+        let path = path.as_ref().to_path_buf();
+        let messages = match File::open(&path) {
+            Ok(file) => {
+                let mut messages = Vec::new();
+                for line in BufReader::new(file).lines() {
+                    let line = line?;
+                    if !line.trim().is_empty() {
+                        messages.push(serde_json::from_str(&line)?);
+                    }
+                }
+                messages
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(Self { path, messages })
+    }
+
+    /// Returns the path of the memory file.
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends a message to the file and the memory.
+    pub fn try_add_message(&mut self, message: Message) -> Result<(), MemoryError> {
+        let mut line = serde_json::to_string(&message)?;
+        line.push('\n');
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(line.as_bytes())?;
+        file.sync_data()?;
+        self.messages.push(message);
+        Ok(())
+    }
+
+    /// Removes all the messages from the file and the memory.
+    pub fn try_clear(&mut self) -> Result<(), MemoryError> {
+        match fs::remove_file(&self.path) {
+            Err(err) if err.kind() != ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        }
+        self.messages.clear();
+        Ok(())
+    }
+}
+
+impl Memory for JsonlMemory {
+    fn messages(&self) -> Vec<Message> {
+        self.messages.clone()
+    }
+
+    fn add_message(&mut self, message: Message) {
+        if let Err(err) = self.try_add_message(message) {
+            tracing::error!(
+                "Failed to persist message to {}: {err}",
+                self.path.display()
+            );
+        }
+    }
+
+    fn clear(&mut self) {
+        if let Err(err) = self.try_clear() {
+            tracing::error!("Failed to clear memory {}: {err}", self.path.display());
+        }
+    }
+}
+
+/// Keeps the memory of each session in a `<session_id>.jsonl` file of a directory.
+pub struct JsonlMemoryStore {
+    dir: PathBuf,
+    sessions: Sessions<JsonlMemory>,
+}
+
+impl JsonlMemoryStore {
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+            sessions: Sessions::default(),
+        }
+    }
+
+    fn session_path(&self, session_id: &str) -> Result<PathBuf, MemoryError> {
+        validate_session_id(session_id)?;
+        Ok(self.dir.join(format!("{session_id}.jsonl")))
+    }
+}
+
+impl MemoryStore for JsonlMemoryStore {
+    fn memory(&self, session_id: &str) -> Result<Ref<dyn Memory>, MemoryError> {
+        let path = self.session_path(session_id)?;
+        self.sessions
+            .get_or_load(session_id, || JsonlMemory::open(path))
+    }
+
+    fn remove(&self, session_id: &str) -> Result<(), MemoryError> {
+        let path = self.session_path(session_id)?;
+        self.sessions.remove(session_id)?;
+        match fs::remove_file(path) {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_jsonl_store_persists_sessions() {
+        let dir = std::env::temp_dir().join(format!("alith-memory-{}", uuid::Uuid::new_v4()));
+        let store = JsonlMemoryStore::new(&dir);
+        {
+            let memory = store.memory("user-1").unwrap();
+            let mut memory = memory.write().await;
+            memory.add_user_message("Hi");
+            memory.add_ai_message("Hello!");
+        }
+        // The memory was dropped from the cache, so it is loaded again from the file.
+        let memory = JsonlMemoryStore::new(&dir).memory("user-1").unwrap();
+        let messages = memory.read().await.messages();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[1].content, "Hello!");
+        assert!(store.memory("../user-1").is_err());
+
+        store.remove("user-1").unwrap();
+        assert!(
+            store
+                .memory("user-1")
+                .unwrap()
+                .read()
+                .await
+                .messages()
+                .is_empty()
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_sessions_in_use_are_not_removed() {
+        let dir = std::env::temp_dir().join(format!("alith-memory-{}", uuid::Uuid::new_v4()));
+        let store = JsonlMemoryStore::new(&dir);
+        let memory = store.memory("user-1").unwrap();
+        let mut turn = memory.write().await;
+        turn.add_user_message("Hi");
+        assert!(matches!(
+            store.remove("user-1"),
+            Err(MemoryError::SessionInUse(_))
+        ));
+        turn.add_ai_message("Hello!");
+        drop(turn);
+        assert_eq!(
+            JsonlMemoryStore::new(&dir)
+                .memory("user-1")
+                .unwrap()
+                .read()
+                .await
+                .messages()
+                .len(),
+            2
+        );
+
+        store.remove("user-1").unwrap();
+        assert!(memory.read().await.messages().is_empty());
+        assert!(!dir.join("user-1.jsonl").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,199 @@
+use super::store::Sessions;
+use super::{Memory, MemoryError, MemoryStore, Message};
+use crate::Ref;
+use rusqlite::{Connection, params};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// A memory persisted in the `messages` table of a SQLite database.
+///
+/// Messages are written in their own transaction when they are added, so the conversation
+/// survives restarts. One database can hold the memories of many sessions.
+///
+/// The [`Memory`] methods log the failures to write the database, use
+/// [`SqliteMemory::try_add_message`] and [`SqliteMemory::try_clear`] to handle them.
+pub struct SqliteMemory {
+    conn: Arc<Mutex<Connection>>,
+    session_id: String,
+    messages: Vec<Message>,
+}
+
+impl SqliteMemory {
+    /// Opens the memory of a session in the database at `path`, creating the database if needed.
+    pub fn open(
+        path: impl AsRef<Path>,
+        session_id: impl Into<String>,
+    ) -> Result<Self, MemoryError> {
+        let conn = Arc::new(Mutex::new(open_database(path.as_ref())?));
+        Self::with_connection(conn, session_id.into())
+    }
+
+    fn with_connection(
+        conn: Arc<Mutex<Connection>>,
+        session_id: String,
+    ) -> Result<Self, MemoryError> {
+        let messages = {
+            let conn = conn.lock().unwrap_or_else(|err| err.into_inner());
+            let mut stmt =
+                conn.prepare("SELECT message FROM messages WHERE session_id = ?1 ORDER BY id")?;
+            let rows = stmt.query_map(params![session_id], |row| row.get::<_, String>(0))?;
+            let mut messages = Vec::new();
+            for row in rows {
+                messages.push(serde_json::from_str(&row?)?);
+            }
+            messages
+        };
+        Ok(Self {
+            conn,
+            session_id,
+            messages,
+        })
+    }
+
+    /// Returns the session ID of the memory.
+    #[inline]
+    pub fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    /// Inserts a message into the database and the memory.
+    pub fn try_add_message(&mut self, message: Message) -> Result<(), MemoryError> {
+        let json = serde_json::to_string(&message)?;
+        self.conn
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .execute(
+                "INSERT INTO messages (session_id, message) VALUES (?1, ?2)",
+                params![self.session_id, json],
+            )?;
+        self.messages.push(message);
+        Ok(())
+    }
+
+    /// Removes all the messages of the session from the database and the memory.
+    pub fn try_clear(&mut self) -> Result<(), MemoryError> {
+        delete_session(
+            &self.conn.lock().unwrap_or_else(|err| err.into_inner()),
+            &self.session_id,
+        )?;
+        self.messages.clear();
+        Ok(())
+    }
+}
+
+impl Memory for SqliteMemory {
+    fn messages(&self) -> Vec<Message> {
+        self.messages.clone()
+    }
+
+    fn add_message(&mut self, message: Message) {
+        if let Err(err) = self.try_add_message(message) {
+            tracing::error!(
+                "Failed to persist message of session {}: {err}",
+                self.session_id
+            );
+        }
+    }
+
+    fn clear(&mut self) {
+        if let Err(err) = self.try_clear() {
+            tracing::error!(
+                "Failed to clear memory of session {}: {err}",
+                self.session_id
+            );
+        }
+    }
+}
+
+/// Keeps the memories of all the sessions in one SQLite database.
+pub struct SqliteMemoryStore {
+    conn: Arc<Mutex<Connection>>,
+    sessions: Sessions<SqliteMemory>,
+}
+
+impl SqliteMemoryStore {
+    /// Opens the database at `path`, creating it if needed.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, MemoryError> {
+        Ok(Self {
+            conn: Arc::new(Mutex::new(open_database(path.as_ref())?)),
+            sessions: Sessions::default(),
+        })
+    }
+}
+
+impl MemoryStore for SqliteMemoryStore {
+    fn memory(&self, session_id: &str) -> Result<Ref<dyn Memory>, MemoryError> {
+        self.sessions.get_or_load(session_id, || {
+            SqliteMemory::with_connection(self.conn.clone(), session_id.to_string())
+        })
+    }
+
+    fn remove(&self, session_id: &str) -> Result<(), MemoryError> {
+        self.sessions.remove(session_id)?;
+        delete_session(
+            &self.conn.lock().unwrap_or_else(|err| err.into_inner()),
+            session_id,
+        )
+    }
+}
+
+fn open_database(path: &Path) -> Result<Connection, MemoryError> {
+    let conn = Connection::open(path)?;
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS messages (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            session_id TEXT NOT NULL,
+            message TEXT NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS messages_session_id ON messages (session_id, id);",
+    )?;
+    Ok(conn)
+}
+
+fn delete_session(conn: &Connection, session_id: &str) -> Result<(), MemoryError> {
+    conn.execute(
+        "DELETE FROM messages WHERE session_id = ?1",
+        params![session_id],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sqlite_store_persists_sessions() {
+        let path = std::env::temp_dir().join(format!("alith-memory-{}.db", uuid::Uuid::new_v4()));
+        let store = SqliteMemoryStore::open(&path).unwrap();
+        {
+            let memory = store.memory("user-1").unwrap();
+            let mut memory = memory.write().await;
+            memory.add_user_message("Hi");
+            memory.add_ai_message("Hello!");
+        }
+        store
+            .memory("user-2")
+            .unwrap()
+            .write()
+            .await
+            .add_user_message("Bye");
+        // The memories are loaded again from the database.
+        let reopened = SqliteMemoryStore::open(&path).unwrap();
+        let messages = reopened.memory("user-1").unwrap().read().await.messages();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[1].content, "Hello!");
+        assert_eq!(
+            SqliteMemory::open(&path, "user-2").unwrap().messages()[0].content,
+            "Bye"
+        );
+
+        reopened.remove("user-1").unwrap();
+        let memory = SqliteMemoryStore::open(&path)
+            .unwrap()
+            .memory("user-1")
+            .unwrap();
+        assert!(memory.read().await.messages().is_empty());
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,85 @@
+use super::{Memory, MemoryError};
+use crate::{Ref, make_ref};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// A store of conversation memories, one per session, e.g. per user or per chat.
+pub trait MemoryStore: Send + Sync {
+    /// Returns the memory of the session, loading it on first use.
+    ///
+    /// The same memory is returned while it is in use, so concurrent requests of a
+    /// session share it.
+    fn memory(&self, session_id: &str) -> Result<Ref<dyn Memory>, MemoryError>;
+
+    /// Deletes the persisted messages of the session.
+    ///
+    /// Fails with [`MemoryError::SessionInUse`], without deleting anything, while the
+    /// memory of the session is locked, e.g. by a running turn, which would otherwise
+    /// write the messages of the session again.
+    fn remove(&self, session_id: &str) -> Result<(), MemoryError>;
+}
+
+/// Caches the loaded memories of a store by session ID.
+pub(crate) struct Sessions<M> {
+    sessions: Mutex<HashMap<String, Ref<M>>>,
+}
+
+impl<M> Default for Sessions<M> {
+    fn default() -> Self {
+        Self {
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<M: Memory + 'static> Sessions<M> {
+    /// Returns the cached memory of the session, or loads it with `load`.
+    pub(crate) fn get_or_load(
+        &self,
+        session_id: &str,
+        load: impl FnOnce() -> Result<M, MemoryError>,
+    ) -> Result<Ref<dyn Memory>, MemoryError> {
+        let mut sessions = self.sessions.lock().unwrap_or_else(|err| err.into_inner());
+        // Drop the sessions no longer used outside of the cache.
+        sessions.retain(|_, memory| Arc::strong_count(memory) > 1);
+        let memory = match sessions.get(session_id) {
+            Some(memory) => memory.clone(),
+            None => {
+                let memory = make_ref(load()?);
+                sessions.insert(session_id.to_string(), memory.clone());
+                memory
+            }
+        };
+        Ok(memory)
+    }
+
+    /// Removes the session from the cache and clears its memory if it is still in use.
+    ///
+    /// Fails with [`MemoryError::SessionInUse`], keeping the session, when its memory is
+    /// locked.
+    pub(crate) fn remove(&self, session_id: &str) -> Result<(), MemoryError> {
+        let mut sessions = self.sessions.lock().unwrap_or_else(|err| err.into_inner());
+        if let Some(memory) = sessions.get(session_id) {
+            let Ok(mut memory) = memory.try_write() else {
+                return Err(MemoryError::SessionInUse(session_id.to_string()));
+            };
+            memory.clear();
+        }
+        sessions.remove(session_id);
+        Ok(())
+    }
+}
+
+/// Checks that a session ID can be used as a file name.
+pub(crate) fn validate_session_id(session_id: &str) -> Result<(), MemoryError> {
+    let valid = !session_id.is_empty()
+        && !session_id.starts_with('.')
+        && session_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '@'));
+    if valid {
+        Ok(())
+    } else {
+        Err(MemoryError::InvalidSessionId(session_id.to_string()))
+    }
+}
@@ -0,0 +1,240 @@
+use super::{Compression, Memory, MemoryError, Message, MessageType};
+use crate::chat::{Completion, Request, ResponseContent};
+use alith_models::tokenizer::Tokenizer;
+use futures::future::BoxFuture;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+const SUMMARY_PREAMBLE: &str = "You summarize conversations between a user and an AI assistant. Keep the facts, names, decisions and open questions, and write the summary in the third person.";
+
+/// A memory that folds the oldest messages into a running summary once the messages
+/// exceed a token budget.
+///
+/// The summary is written by the given model in [`Memory::prepare_compression`], which the
+/// executor calls at the end of every turn, and is returned as the first, system message.
+pub struct SummaryBufferMemory<M: Completion> {
+    model: Arc<Mutex<M>>,
+    tokenizer: Arc<Tokenizer>,
+    max_tokens: usize,
+    summary: Option<String>,
+    messages: Vec<Message>,
+    /// Changed when the messages are cleared or folded, see [`Compression::generation`].
+    generation: u64,
+}
+
+impl<M: Completion + Send + Sync + 'static> SummaryBufferMemory<M> {
+    /// Creates a memory keeping at most `max_tokens` tokens of messages besides the summary,
+    /// counted with `tokenizer`.
+    pub fn new(model: M, tokenizer: Arc<Tokenizer>, max_tokens: usize) -> Self {
+        Self {
+            model: Arc::new(Mutex::new(model)),
+            tokenizer,
+            max_tokens,
+            summary: None,
+            messages: Vec::new(),
+            generation: 0,
+        }
+    }
+
+    /// Returns the summary of the messages folded so far.
+    #[inline]
+    pub fn summary(&self) -> Option<&str> {
+        self.summary.as_deref()
+    }
+
+    /// Returns the number of tokens of the messages, without the summary.
+    pub fn num_tokens(&self) -> usize {
+        self.messages
+            .iter()
+            .map(|message| self.message_tokens(message))
+            .sum()
+    }
+
+    fn message_tokens(&self, message: &Message) -> usize {
+        let mut tokens = self.tokenizer.count_tokens(&message.content) as usize;
+        if let Some(tool_calls) = &message.tool_calls {
+            tokens += self.tokenizer.count_tokens(&tool_calls.to_string()) as usize;
+        }
+        tokens
+    }
+
+    /// Returns the number of oldest messages to fold so that the others fit in the budget.
+    fn prunable(&self) -> usize {
+        let mut tokens = self.num_tokens();
+        let mut pruned = 0;
+        while pruned < self.messages.len()
+            && (tokens > self.max_tokens
+                // Don't keep tool results without the message calling the tool.
+                || self.messages[pruned].message_type == MessageType::Tool)
+        {
+            tokens -= self.message_tokens(&self.messages[pruned]);
+            pruned += 1;
+        }
+        pruned
+    }
+
+    /// Returns the prompt asking to fold the messages into the current summary.
+    fn fold_prompt(&self, pruned: &[Message]) -> String {
+        let lines = pruned
+            .iter()
+            .map(|message| match &message.tool_calls {
+                Some(tool_calls) => format!("{}: {tool_calls}", message.message_type.type_string()),
+                None => format!(
+                    "{}: {}",
+                    message.message_type.type_string(),
+                    message.content
+                ),
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        format!(
+            "Current summary:\n{}\n\nNew lines of conversation:\n{lines}\n\nWrite the new summary of the whole conversation, adding onto the current summary.",
+            self.summary.as_deref().unwrap_or("(empty)"),
+        )
+    }
+}
+
+impl<M: Completion + Send + Sync + 'static> Memory for SummaryBufferMemory<M> {
+    /// Returns the summary as a system message, followed by the messages not folded yet.
+    fn messages(&self) -> Vec<Message> {
+        let summary = self.summary.iter().map(|summary| {
+            Message::new_system_message(format!("Summary of the earlier conversation:\n{summary}"))
+        });
+        summary.chain(self.messages.iter().cloned()).collect()
+    }
+
+    fn add_message(&mut self, message: Message) {
+        self.messages.push(message);
+    }
+
+    fn clear(&mut self) {
+        self.summary = None;
+        self.messages.clear();
+        self.generation += 1;
+    }
+
+    /// Summarizes the oldest messages with the current summary when the messages exceed
+    /// the budget.
+    fn prepare_compression(&self) -> Option<BoxFuture<'static, Result<Compression, MemoryError>>> {
+        if self.num_tokens() <= self.max_tokens {
+            return None;
+        }
+        let compressed = self.prunable();
+        if compressed == 0 {
+            return None;
+        }
+        let request = Request::new(
+            self.fold_prompt(&self.messages[..compressed]),
+            SUMMARY_PREAMBLE.to_string(),
+        );
+        let model = self.model.clone();
+        let generation = self.generation;
+        Some(Box::pin(async move {
+            let response = model.lock().await.completion(request).await?;
+            Ok(Compression {
+                compressed,
+                summary: response.content().trim().to_string(),
+                generation,
+            })
+        }))
+    }
+
+    /// Replaces the folded messages with the new summary.
+    fn apply_compression(&mut self, compression: Compression) {
+        if compression.generation != self.generation || compression.compressed > self.messages.len()
+        {
+            return;
+        }
+        self.messages.drain(..compression.compressed);
+        self.summary = Some(compression.summary);
+        self.generation += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chat::{
+        CompletionError, ResponseTokenUsage, ResponseToolCalls, TokenUsage, ToolCall,
+    };
+
+    /// A model summarizing a conversation by counting its lines.
+    struct Counter;
+
+    struct Text(String);
+
+    impl ResponseContent for Text {
+        fn content(&self) -> String {
+            self.0.clone()
+        }
+    }
+
+    impl ResponseToolCalls for Text {
+        fn toolcalls(&self) -> Vec<ToolCall> {
+            Vec::new()
+        }
+    }
+
+    impl ResponseTokenUsage for Text {
+        fn token_usage(&self) -> TokenUsage {
+            TokenUsage {
+                tokens_cached: None,
+                prompt_tokens: 0,
+                completion_tokens: 0,
+                total_tokens: 0,
+            }
+        }
+    }
+
+    impl Completion for Counter {
+        type Response = Text;
+
+        async fn completion(&mut self, request: Request) -> Result<Text, CompletionError> {
+            let lines = request
+                .prompt
+                .lines()
+                .filter(|line| line.starts_with("user: ") || line.starts_with("assistant: "))
+                .count();
+            Ok(Text(format!("{lines} messages")))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_folds_old_messages_into_the_summary() {
+        let tokenizer = Arc::new(Tokenizer::new_tiktoken("gpt-4").unwrap());
+        let mut memory = SummaryBufferMemory::new(Counter, tokenizer, 9);
+        memory.add_user_message("What is the capital of France?");
+        memory.add_ai_message("Paris.");
+        memory.compress().await.unwrap();
+        assert_eq!(memory.summary(), None);
+
+        memory.add_user_message("And the capital of Germany?");
+        memory.add_ai_message("Berlin.");
+        memory.compress().await.unwrap();
+        assert!(memory.num_tokens() <= 9);
+        assert_eq!(memory.summary(), Some("2 messages"));
+        let messages = memory.messages();
+        assert_eq!(messages[0].message_type, MessageType::System);
+        assert!(messages[0].content.ends_with("2 messages"));
+        assert_eq!(messages[1].content, "And the capital of Germany?");
+    }
+
+    #[tokio::test]
+    async fn test_messages_added_while_summarizing_are_kept() {
+        let tokenizer = Arc::new(Tokenizer::new_tiktoken("gpt-4").unwrap());
+        let mut memory = SummaryBufferMemory::new(Counter, tokenizer, 9);
+        memory.add_user_message("What is the capital of France?");
+        memory.add_ai_message("Paris.");
+        memory.add_user_message("And the capital of Germany?");
+        let compression = memory.prepare_compression().unwrap().await.unwrap();
+        memory.add_ai_message("Berlin.");
+        memory.apply_compression(compression.clone());
+        assert_eq!(memory.summary(), Some("1 messages"));
+        assert_eq!(memory.messages().last().unwrap().content, "Berlin.");
+
+        // A compression of an older generation is discarded.
+        let messages = memory.messages();
+        memory.apply_compression(compression);
+        assert_eq!(memory.messages().len(), messages.len());
+    }
+}
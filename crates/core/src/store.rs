@@ -2,9 +2,21 @@ use crate::embeddings::{Embeddings, EmbeddingsData, EmbeddingsError};
 use async_trait::async_trait;
 use hnsw_rs::prelude::*;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
+pub mod filter;
+pub mod hybrid;
+pub mod keyword;
+mod snapshot;
+
+pub use filter::{Filter, Range};
+pub use hybrid::{Fusion, HybridSearch, HybridStorage};
+pub use keyword::KeywordIndex;
+pub use snapshot::SNAPSHOT_VERSION;
+
 #[derive(Debug, thiserror::Error)]
 pub enum VectorStoreError {
     #[error("Embedding error: {0}")]
@@ -20,6 +32,11 @@ pub enum VectorStoreError {
     SearchError(String),
     #[error("Custom error: {0}")]
     CustomError(String),
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+    /// The storage file can't be loaded, e.g. it has another format version or embedding model
+    #[error("Incompatible snapshot: {0}")]
+    IncompatibleSnapshot(String),
 }
 
 pub type TopNItem = (DocumentId, String, f32);
@@ -38,20 +55,178 @@ impl Serialize for DocumentId {
     }
 }
 
+impl std::fmt::Display for DocumentId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// The metadata of a stored document, e.g. its source file, tenant or date.
+pub type Metadata = serde_json::Map<String, Value>;
+
+/// A document of a [`Storage`]: its text, which is embedded, and its metadata.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StorageDocument {
+    pub text: String,
+    #[serde(default)]
+    pub metadata: Metadata,
+}
+
+impl StorageDocument {
+    /// Creates a document without metadata.
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            metadata: Metadata::new(),
+        }
+    }
+
+    /// Sets a metadata value of the document.
+    pub fn with_metadata(mut self, key: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+}
+
+impl From<String> for StorageDocument {
+    fn from(text: String) -> Self {
+        Self::new(text)
+    }
+}
+
+impl From<&str> for StorageDocument {
+    fn from(text: &str) -> Self {
+        Self::new(text)
+    }
+}
+
 /// Trait representing a storage backend.
 #[async_trait]
 pub trait Storage: Send + Sync {
-    /// Saves a value into the storage.
-    async fn save(&self, value: String) -> Result<(), VectorStoreError>;
+    /// Saves a value into the storage and returns its ID.
+    async fn save(&self, value: String) -> Result<DocumentId, VectorStoreError> {
+        self.save_documents(vec![StorageDocument::new(value)])
+            .await?
+            .pop()
+            .ok_or_else(|| VectorStoreError::MissingIdError("the saved document".to_string()))
+    }
+    /// Saves documents into the storage and returns their IDs, in order.
+    async fn save_documents(
+        &self,
+        documents: Vec<StorageDocument>,
+    ) -> Result<Vec<DocumentId>, VectorStoreError>;
+    /// Saves a document with the given ID, replacing the document with this ID if any.
+    async fn upsert(
+        &self,
+        id: &DocumentId,
+        document: StorageDocument,
+    ) -> Result<(), VectorStoreError>;
+    /// Returns the document with the given ID, `None` if there is no such document.
+    async fn get(&self, id: &DocumentId) -> Result<Option<StorageDocument>, VectorStoreError>;
+    /// Deletes the document with the given ID, doing nothing if there is no such document.
+    async fn delete(&self, id: &DocumentId) -> Result<(), VectorStoreError>;
     /// Searches the storage with a query, limiting the results and applying a threshold.
-    async fn search(&self, query: &str, limit: usize, threshold: f32) -> TopNResults;
+    async fn search(&self, query: &str, limit: usize, threshold: f32) -> TopNResults {
+        self.search_filtered(query, limit, threshold, None).await
+    }
+    /// Searches the storage like [`Storage::search`], only among the documents whose
+    /// metadata match the filter.
+    async fn search_filtered(
+        &self,
+        query: &str,
+        limit: usize,
+        threshold: f32,
+        filter: Option<&Filter>,
+    ) -> TopNResults;
     /// Resets the storage by clearing all stored data.
     async fn reset(&self) -> Result<(), VectorStoreError>;
 }
 
+/// The smallest number of points an HNSW index of an [`InMemoryStorage`] is sized for.
+const MIN_HNSW_CAPACITY: usize = 1024;
+
+/// A document of an [`InMemoryStorage`], with its embedding.
+struct Entry {
+    id: DocumentId,
+    data: EmbeddingsData,
+    metadata: Metadata,
+}
+
+/// The documents of an [`InMemoryStorage`].
+///
+/// The HNSW index can't remove points, so a deleted or replaced document leaves a
+/// `None` tombstone at its point ID and is skipped by the searches. The index is rebuilt
+/// without the tombstones when it outgrows its capacity or is half tombstones.
+#[derive(Default)]
+struct Entries {
+    /// The documents by HNSW point ID.
+    points: Vec<Option<Entry>>,
+    /// The HNSW point ID of each document.
+    ids: HashMap<DocumentId, usize>,
+    /// The number of the next generated document ID, never reused.
+    next_id: u64,
+    /// The number of points the HNSW index was sized for.
+    capacity: usize,
+}
+
+impl Entries {
+    /// Adds a document at the next point ID, replacing the document with the same ID.
+    fn insert(&mut self, id: DocumentId, data: EmbeddingsData, metadata: Metadata) -> usize {
+        let point = self.points.len();
+        if let Some(old) = self.ids.insert(id.clone(), point) {
+            self.points[old] = None;
+        }
+        self.points.push(Some(Entry { id, data, metadata }));
+        point
+    }
+
+    /// Returns an unused generated document ID.
+    fn next_id(&mut self) -> DocumentId {
+        loop {
+            let id = DocumentId(self.next_id.to_string());
+            self.next_id += 1;
+            if !self.ids.contains_key(&id) {
+                return id;
+            }
+        }
+    }
+
+    /// Returns the number of tombstones.
+    fn deleted(&self) -> usize {
+        self.points.len() - self.ids.len()
+    }
+
+    /// Returns whether the HNSW index should be rebuilt.
+    fn needs_reindex(&self) -> bool {
+        self.points.len() > self.capacity || self.deleted() > self.capacity / 2
+    }
+
+    /// Drops the tombstones and builds a new HNSW index of the documents, sized for
+    /// twice as many documents.
+    fn reindex(&mut self) -> Hnsw<'static, f64, DistCosine> {
+        self.points.retain(Option::is_some);
+        self.ids = self
+            .points
+            .iter()
+            .enumerate()
+            .filter_map(|(point, entry)| Some((entry.as_ref()?.id.clone(), point)))
+            .collect();
+        self.capacity = (self.points.len() * 2).max(MIN_HNSW_CAPACITY);
+        let hnsw = Hnsw::new(32, self.capacity, 16, 200, DistCosine {});
+        let list: Vec<_> = self
+            .points
+            .iter()
+            .enumerate()
+            .filter_map(|(point, entry)| Some((&entry.as_ref()?.data.vec, point)))
+            .collect();
+        hnsw.parallel_insert(&list);
+        hnsw
+    }
+}
+
 /// In-memory storage implementation.
 pub struct InMemoryStorage<E: Embeddings> {
-    data: Arc<RwLock<Vec<EmbeddingsData>>>, // Simple in-memory vector to store data.
+    entries: Arc<RwLock<Entries>>,
     hnsw: Arc<RwLock<Hnsw<'static, f64, DistCosine>>>,
     embeddings: Arc<E>,
 }
@@ -59,9 +234,15 @@ pub struct InMemoryStorage<E: Embeddings> {
 impl<E: Embeddings> InMemoryStorage<E> {
     /// Creates a new instance of `InMemoryStorage`.
     pub fn from_documents(embeddings: E, documents: Vec<EmbeddingsData>) -> Self {
+        let mut entries = Entries::default();
+        for data in documents {
+            let id = entries.next_id();
+            entries.insert(id, data, Metadata::new());
+        }
+        let hnsw = entries.reindex();
         Self {
-            hnsw: Arc::new(RwLock::new(Self::build_hnsw(&documents))),
-            data: Arc::new(RwLock::new(documents)),
+            entries: Arc::new(RwLock::new(entries)),
+            hnsw: Arc::new(RwLock::new(hnsw)),
             embeddings: Arc::new(embeddings),
         }
     }
@@ -76,57 +257,134 @@ impl<E: Embeddings> InMemoryStorage<E> {
     }
 }
 
-#[async_trait]
-impl<E: Embeddings> Storage for InMemoryStorage<E> {
-    async fn save(&self, value: String) -> Result<(), VectorStoreError> {
-        let mut data = self.data.write().await;
+impl<E: Embeddings> InMemoryStorage<E> {
+    /// Embeds the documents and adds them under the given IDs, or generated IDs.
+    async fn insert(
+        &self,
+        documents: Vec<(Option<DocumentId>, StorageDocument)>,
+    ) -> Result<Vec<DocumentId>, VectorStoreError> {
+        let texts = documents.iter().map(|(_, doc)| doc.text.clone()).collect();
         let embeddings = self
             .embeddings
-            .embed_texts(vec![value])
+            .embed_texts(texts)
             .await
             .map_err(VectorStoreError::EmbeddingError)?;
-        data.append(&mut embeddings.clone());
-        let list: Vec<_> = embeddings
-            .iter()
-            .enumerate()
-            .map(|(k, data)| (&data.vec, k))
-            .collect();
-        self.hnsw.write().await.parallel_insert(&list);
+        let mut entries = self.entries.write().await;
+        let mut ids = Vec::with_capacity(documents.len());
+        let mut points = Vec::with_capacity(documents.len());
+        for ((id, document), data) in documents.into_iter().zip(embeddings) {
+            let id = id.unwrap_or_else(|| entries.next_id());
+            points.push(entries.insert(id.clone(), data, document.metadata));
+            ids.push(id);
+        }
+        if entries.needs_reindex() {
+            *self.hnsw.write().await = entries.reindex();
+        } else {
+            let list: Vec<_> = points
+                .iter()
+                .filter_map(|point| {
+                    let entry = entries.points[*point].as_ref()?;
+                    Some((&entry.data.vec, *point))
+                })
+                .collect();
+            self.hnsw.write().await.parallel_insert(&list);
+        }
+        Ok(ids)
+    }
+}
+
+#[async_trait]
+impl<E: Embeddings> Storage for InMemoryStorage<E> {
+    async fn save_documents(
+        &self,
+        documents: Vec<StorageDocument>,
+    ) -> Result<Vec<DocumentId>, VectorStoreError> {
+        self.insert(documents.into_iter().map(|doc| (None, doc)).collect())
+            .await
+    }
+
+    async fn upsert(
+        &self,
+        id: &DocumentId,
+        document: StorageDocument,
+    ) -> Result<(), VectorStoreError> {
+        self.insert(vec![(Some(id.clone()), document)]).await?;
         Ok(())
     }
 
-    async fn search(&self, query: &str, limit: usize, threshold: f32) -> TopNResults {
-        // Collect the necessary data from the MutexGuard before entering the async block
-        let data = self.data.read().await;
+    async fn get(&self, id: &DocumentId) -> Result<Option<StorageDocument>, VectorStoreError> {
+        let entries = self.entries.read().await;
+        Ok(entries
+            .ids
+            .get(id)
+            .and_then(|point| entries.points[*point].as_ref())
+            .map(|entry| StorageDocument {
+                text: entry.data.document.clone(),
+                metadata: entry.metadata.clone(),
+            }))
+    }
+
+    async fn delete(&self, id: &DocumentId) -> Result<(), VectorStoreError> {
+        let mut entries = self.entries.write().await;
+        if let Some(point) = entries.ids.remove(id) {
+            entries.points[point] = None;
+            if entries.needs_reindex() {
+                *self.hnsw.write().await = entries.reindex();
+            }
+        }
+        Ok(())
+    }
+
+    async fn search_filtered(
+        &self,
+        query: &str,
+        limit: usize,
+        threshold: f32,
+        filter: Option<&Filter>,
+    ) -> TopNResults {
         let embeddings = self
             .embeddings
             .clone()
             .embed_texts(vec![query.to_string()])
             .await?;
-        self.vector_search(embeddings, limit, threshold)
-            .await
-            .map(|result| {
-                result
-                    .iter()
-                    .map(|result| {
-                        (
-                            result.0.clone(),
-                            data[result.0.0.parse::<usize>().unwrap()].document.clone(),
-                            result.1,
-                        )
-                    })
-                    .collect::<Vec<_>>()
-            })
+        let entries = self.entries.read().await;
+        // Search past the tombstones and the filtered out documents to still find
+        // `limit` documents, widening the search until the whole index is searched.
+        let mut k = limit + entries.deleted();
+        loop {
+            let results: Vec<_> = self
+                .vector_search(embeddings.clone(), k, threshold)
+                .await?
+                .into_iter()
+                .filter_map(|(point, score)| {
+                    let entry = entries
+                        .points
+                        .get(point.0.parse::<usize>().ok()?)?
+                        .as_ref()?;
+                    filter
+                        .is_none_or(|filter| filter.matches(&entry.metadata))
+                        .then(|| (entry.id.clone(), entry.data.document.clone(), score))
+                })
+                .take(limit)
+                .collect();
+            if results.len() == limit || k >= entries.points.len() {
+                return Ok(results);
+            }
+            k = (k * 2).max(1);
+        }
     }
 
     async fn reset(&self) -> Result<(), VectorStoreError> {
-        let mut data = self.data.write().await;
-        data.clear();
+        let mut entries = self.entries.write().await;
+        entries.points.clear();
+        entries.ids.clear();
+        *self.hnsw.write().await = entries.reindex();
         Ok(())
     }
 }
 
 impl<E: Embeddings> InMemoryStorage<E> {
+    /// Searches the HNSW index, returning the point IDs of the nearest vectors.
     pub async fn vector_search(
         &self,
         embeddings: Vec<EmbeddingsData>,
@@ -155,15 +413,248 @@ impl<E: Embeddings> InMemoryStorage<E> {
             .collect();
         Ok(output)
     }
+}
 
-    pub fn build_hnsw(data: &[EmbeddingsData]) -> Hnsw<'static, f64, DistCosine> {
-        let hnsw = Hnsw::new(32, data.len(), 16, 200, DistCosine {});
-        let list: Vec<_> = data
-            .iter()
-            .enumerate()
-            .map(|(k, data)| (&data.vec, k))
-            .collect();
-        hnsw.parallel_insert(&list);
-        hnsw
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    /// Embeds a text as its length.
+    #[derive(Clone)]
+    struct Lengths;
+
+    #[async_trait]
+    impl Embeddings for Lengths {
+        async fn embed_texts(
+            &self,
+            input: Vec<String>,
+        ) -> Result<Vec<EmbeddingsData>, EmbeddingsError> {
+            Ok(input
+                .into_iter()
+                .map(|document| EmbeddingsData {
+                    vec: vec![document.len() as f64, 1.0],
+                    document,
+                })
+                .collect())
+        }
+
+        fn model_name(&self) -> Option<String> {
+            Some("lengths".to_string())
+        }
+    }
+
+    /// Embeds a text as a pseudo-random vector derived from its hash, so that distinct
+    /// texts are far apart.
+    #[derive(Clone)]
+    struct Hashes;
+
+    #[async_trait]
+    impl Embeddings for Hashes {
+        async fn embed_texts(
+            &self,
+            input: Vec<String>,
+        ) -> Result<Vec<EmbeddingsData>, EmbeddingsError> {
+            use std::hash::{DefaultHasher, Hash, Hasher};
+            Ok(input
+                .into_iter()
+                .map(|document| {
+                    let vec = (0..16)
+                        .map(|i| {
+                            let mut hasher = DefaultHasher::new();
+                            (i, &document).hash(&mut hasher);
+                            (hasher.finish() % 2001) as f64 / 1000.0 - 1.0
+                        })
+                        .collect();
+                    EmbeddingsData { vec, document }
+                })
+                .collect())
+        }
+    }
+
+    /// An operation on the storage, picking the documents to update or delete by index.
+    #[derive(Debug, Clone)]
+    enum Op {
+        Save(usize),
+        Upsert(prop::sample::Index),
+        Delete(prop::sample::Index),
+        Reindex,
+        Reset,
+    }
+
+    fn op() -> impl Strategy<Value = Op> {
+        prop_oneof![
+            10 => (1..=5usize).prop_map(Op::Save),
+            // Outgrows the index capacity after a few saves.
+            1 => (300..=600usize).prop_map(Op::Save),
+            4 => any::<prop::sample::Index>().prop_map(Op::Upsert),
+            5 => any::<prop::sample::Index>().prop_map(Op::Delete),
+            1 => Just(Op::Reindex),
+            1 => Just(Op::Reset),
+        ]
+    }
+
+    fn cosine(a: &[f64], b: &[f64]) -> f32 {
+        let dot: f64 = a.iter().zip(b).map(|(a, b)| a * b).sum();
+        let norm = |v: &[f64]| v.iter().map(|x| x * x).sum::<f64>().sqrt();
+        (dot / (norm(a) * norm(b))) as f32
+    }
+
+    /// Checks the storage against the model of its documents: every document is found
+    /// again by ID and by its text, and a search of all the documents returns them with
+    /// their brute-force cosine similarities, best first.
+    async fn assert_found(storage: &InMemoryStorage<Hashes>, model: &HashMap<DocumentId, String>) {
+        for (id, text) in model {
+            let document = storage.get(id).await.unwrap();
+            assert_eq!(document.map(|document| document.text).as_ref(), Some(text));
+            let results = storage.search(text, 1, 0.0).await.unwrap();
+            assert_eq!(results[0].0, *id, "{text} was not found");
+            assert_eq!(results[0].1, *text);
+        }
+        let query = Hashes
+            .embed_texts(vec!["query".to_string()])
+            .await
+            .unwrap()
+            .remove(0);
+        let results = storage.search("query", model.len(), -1.0).await.unwrap();
+        assert_eq!(results.len(), model.len());
+        for (i, (id, text, score)) in results.iter().enumerate() {
+            assert_eq!(model.get(id), Some(text));
+            let vec = &Hashes.embed_texts(vec![text.clone()]).await.unwrap()[0].vec;
+            assert!((score - cosine(&query.vec, vec)).abs() < 1e-4, "{text}");
+            assert!(i == 0 || results[i - 1].2 >= *score);
+        }
+        let entries = storage.entries.read().await;
+        assert_eq!(entries.ids.len(), model.len());
+    }
+
+    /// Runs the operations on a storage and on a model of its documents.
+    async fn run(ops: Vec<Op>) {
+        let storage = InMemoryStorage::from_documents(Hashes, vec![]);
+        let mut model: HashMap<DocumentId, String> = HashMap::new();
+        let mut seen = std::collections::HashSet::new();
+        let mut texts = 0..;
+        let mut text = || format!("document {}", texts.next().unwrap());
+        for op in ops {
+            match op {
+                Op::Save(n) => {
+                    let documents: Vec<_> = (0..n).map(|_| StorageDocument::new(text())).collect();
+                    let ids = storage.save_documents(documents.clone()).await.unwrap();
+                    for (id, document) in ids.into_iter().zip(documents) {
+                        // Generated IDs are never reused, even after deletes and resets.
+                        assert!(seen.insert(id.clone()), "{id} was reused");
+                        model.insert(id, document.text);
+                    }
+                }
+                Op::Upsert(index) if !model.is_empty() => {
+                    let id = model.keys().nth(index.index(model.len())).unwrap().clone();
+                    let text = text();
+                    storage.upsert(&id, text.as_str().into()).await.unwrap();
+                    model.insert(id, text);
+                }
+                Op::Delete(index) if !model.is_empty() => {
+                    let id = model.keys().nth(index.index(model.len())).unwrap().clone();
+                    storage.delete(&id).await.unwrap();
+                    model.remove(&id);
+                    assert_eq!(storage.get(&id).await.unwrap(), None);
+                }
+                Op::Reindex => {
+                    let mut entries = storage.entries.write().await;
+                    *storage.hnsw.write().await = entries.reindex();
+                    assert_eq!(entries.deleted(), 0);
+                }
+                Op::Reset => {
+                    storage.reset().await.unwrap();
+                    model.clear();
+                    assert!(
+                        storage
+                            .search("document 0", 10, 0.0)
+                            .await
+                            .unwrap()
+                            .is_empty()
+                    );
+                }
+                _ => {}
+            }
+            let entries = storage.entries.read().await;
+            assert!(entries.points.len() <= entries.capacity);
+        }
+        assert_found(&storage, &model).await;
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(16))]
+
+        #[test]
+        fn test_storage_matches_a_model(ops in prop::collection::vec(op(), 1..60)) {
+            futures::executor::block_on(run(ops));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_documents_can_be_updated_and_deleted() {
+        let storage = InMemoryStorage::from_documents(
+            Lengths,
+            vec![EmbeddingsData {
+                document: "first".to_string(),
+                vec: vec![5.0, 1.0],
+            }],
+        );
+        let ids = storage
+            .save_documents(vec![
+                StorageDocument::new("second").with_metadata("source", "a.md"),
+                "third".into(),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(
+            ids,
+            [DocumentId("1".to_string()), DocumentId("2".to_string())]
+        );
+        let second = storage.get(&ids[0]).await.unwrap().unwrap();
+        assert_eq!(second.text, "second");
+        assert_eq!(second.metadata["source"], "a.md");
+
+        storage
+            .upsert(&ids[0], StorageDocument::new("updated"))
+            .await
+            .unwrap();
+        assert_eq!(storage.get(&ids[0]).await.unwrap().unwrap().text, "updated");
+        storage.delete(&ids[1]).await.unwrap();
+        assert_eq!(storage.get(&ids[1]).await.unwrap(), None);
+        assert_eq!(storage.save("fourth".to_string()).await.unwrap().0, "3");
+        assert_eq!(storage.entries.read().await.deleted(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_storage_can_be_saved_and_loaded() {
+        let storage = InMemoryStorage::from_documents(Lengths, vec![]);
+        let ids = storage
+            .save_documents(vec![
+                StorageDocument::new("first").with_metadata("source", "a.md"),
+                "second".into(),
+            ])
+            .await
+            .unwrap();
+        storage.delete(&ids[1]).await.unwrap();
+        let path = std::env::temp_dir().join(format!("alith-store-{}.json", uuid::Uuid::new_v4()));
+        storage.save_to(&path).await.unwrap();
+
+        let loaded = InMemoryStorage::load_from(Lengths, &path).await.unwrap();
+        let first = loaded.get(&ids[0]).await.unwrap().unwrap();
+        assert_eq!(first.text, "first");
+        assert_eq!(first.metadata["source"], "a.md");
+        assert_eq!(loaded.get(&ids[1]).await.unwrap(), None);
+        assert_eq!(loaded.entries.read().await.deleted(), 0);
+        assert_eq!(loaded.save("third".to_string()).await.unwrap().0, "2");
+
+        let mut json: Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        json["model"] = "other".into();
+        std::fs::write(&path, serde_json::to_vec(&json).unwrap()).unwrap();
+        assert!(matches!(
+            InMemoryStorage::load_from(Lengths, &path).await,
+            Err(VectorStoreError::IncompatibleSnapshot(_))
+        ));
+        std::fs::remove_file(path).unwrap();
     }
 }
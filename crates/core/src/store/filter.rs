@@ -0,0 +1,202 @@
+use super::Metadata;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// A filter on the metadata of the documents, passed to [`super::Storage::search_filtered`].
+///
+/// Each storage translates it into its native filter. The keys are top-level metadata keys
+/// and ranges compare numbers, so dates are best stored as UNIX timestamps.
+///
+/// An empty [`Filter::And`] matches every document and an empty [`Filter::Or`] none.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Filter {
+    /// The value of the key equals the value.
+    Eq { key: String, value: Value },
+    /// The value of the key equals one of the values.
+    In { key: String, values: Vec<Value> },
+    /// The value of the key is a number within the range.
+    Range { key: String, range: Range },
+    /// All the filters match.
+    And(Vec<Filter>),
+    /// At least one of the filters matches.
+    Or(Vec<Filter>),
+}
+
+/// The bounds of a [`Filter::Range`], unbounded when not set.
+///
+/// A range without any bound contains every number, so its filter matches the documents
+/// whose value of the key is a number, in every storage.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Range {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gt: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gte: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lt: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lte: Option<f64>,
+}
+
+impl Filter {
+    /// Matches the documents whose value of the key equals the value.
+    pub fn eq(key: impl Into<String>, value: impl Into<Value>) -> Self {
+        Self::Eq {
+            key: key.into(),
+            value: value.into(),
+        }
+    }
+
+    /// Matches the documents whose value of the key equals one of the values.
+    pub fn any_of<V: Into<Value>>(
+        key: impl Into<String>,
+        values: impl IntoIterator<Item = V>,
+    ) -> Self {
+        Self::In {
+            key: key.into(),
+            values: values.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Matches the documents whose value of the key is a number within the range.
+    pub fn range(key: impl Into<String>, range: Range) -> Self {
+        Self::Range {
+            key: key.into(),
+            range,
+        }
+    }
+
+    /// Matches the documents matching this filter and the other one.
+    pub fn and(self, other: Filter) -> Self {
+        match self {
+            Self::And(mut filters) => {
+                filters.push(other);
+                Self::And(filters)
+            }
+            filter => Self::And(vec![filter, other]),
+        }
+    }
+
+    /// Matches the documents matching this filter or the other one.
+    pub fn or(self, other: Filter) -> Self {
+        match self {
+            Self::Or(mut filters) => {
+                filters.push(other);
+                Self::Or(filters)
+            }
+            filter => Self::Or(vec![filter, other]),
+        }
+    }
+
+    /// Returns whether the metadata of a document matches the filter.
+    pub fn matches(&self, metadata: &Metadata) -> bool {
+        match self {
+            Self::Eq { key, value } => metadata.get(key).is_some_and(|v| value_eq(v, value)),
+            Self::In { key, values } => metadata
+                .get(key)
+                .is_some_and(|v| values.iter().any(|value| value_eq(v, value))),
+            Self::Range { key, range } => metadata
+                .get(key)
+                .and_then(Value::as_f64)
+                .is_some_and(|v| range.contains(v)),
+            Self::And(filters) => filters.iter().all(|filter| filter.matches(metadata)),
+            Self::Or(filters) => filters.iter().any(|filter| filter.matches(metadata)),
+        }
+    }
+}
+
+impl Range {
+    /// Sets the exclusive lower bound.
+    pub fn gt(mut self, gt: f64) -> Self {
+        self.gt = Some(gt);
+        self
+    }
+
+    /// Sets the inclusive lower bound.
+    pub fn gte(mut self, gte: f64) -> Self {
+        self.gte = Some(gte);
+        self
+    }
+
+    /// Sets the exclusive upper bound.
+    pub fn lt(mut self, lt: f64) -> Self {
+        self.lt = Some(lt);
+        self
+    }
+
+    /// Sets the inclusive upper bound.
+    pub fn lte(mut self, lte: f64) -> Self {
+        self.lte = Some(lte);
+        self
+    }
+
+    /// Returns whether no bound is set.
+    pub fn is_unbounded(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Returns the range, with [`f64::MIN`] as inclusive lower bound when no bound is set,
+    /// for the storages which only match numbers by comparing them.
+    pub fn bounded(self) -> Self {
+        match self.is_unbounded() {
+            true => self.gte(f64::MIN),
+            false => self,
+        }
+    }
+
+    /// Returns whether the number is within the range.
+    pub fn contains(&self, value: f64) -> bool {
+        self.gt.is_none_or(|gt| value > gt)
+            && self.gte.is_none_or(|gte| value >= gte)
+            && self.lt.is_none_or(|lt| value < lt)
+            && self.lte.is_none_or(|lte| value <= lte)
+    }
+}
+
+/// Compares two values, numbers by their value so `1` equals `1.0`.
+fn value_eq(a: &Value, b: &Value) -> bool {
+    match (a.as_f64(), b.as_f64()) {
+        (Some(a), Some(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_filter_matches() {
+        let metadata = json!({"tenant": "acme", "source": "a.md", "date": 1700000000})
+            .as_object()
+            .cloned()
+            .unwrap();
+        let filter = Filter::eq("tenant", "acme")
+            .and(Filter::any_of("source", ["a.md", "b.md"]))
+            .and(Filter::range("date", Range::default().gte(1.6e9).lt(1.8e9)));
+        assert!(filter.matches(&metadata));
+        assert!(
+            !filter
+                .clone()
+                .and(Filter::eq("tenant", "other"))
+                .matches(&metadata)
+        );
+        assert!(
+            Filter::eq("tenant", "other")
+                .or(Filter::eq("date", 1700000000.0))
+                .matches(&metadata)
+        );
+        assert!(!Filter::range("source", Range::default().gt(0.0)).matches(&metadata));
+        assert!(Filter::range("date", Range::default()).matches(&metadata));
+        assert!(!Filter::range("source", Range::default()).matches(&metadata));
+        assert!(Range::default().bounded().contains(f64::MIN));
+        assert!(Filter::And(vec![]).matches(&metadata));
+        assert!(!Filter::Or(vec![]).matches(&metadata));
+
+        let json = serde_json::to_value(&filter).unwrap();
+        assert_eq!(json["and"][0]["eq"]["key"], "tenant");
+        assert_eq!(serde_json::from_value::<Filter>(json).unwrap(), filter);
+    }
+}
@@ -0,0 +1,335 @@
+use super::{
+    DocumentId, Filter, KeywordIndex, Storage, StorageDocument, TopNResults, VectorStoreError,
+};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tokio::sync::RwLock;
+
+/// The default rank constant of the reciprocal-rank fusion.
+pub const DEFAULT_RRF_K: f32 = 60.0;
+
+/// How the keyword and vector rankings of a hybrid search are fused.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Fusion {
+    /// Reciprocal-rank fusion: a document scores `1 / (k + rank)` in each ranking, so only
+    /// the ranks matter and not the scales of the scores.
+    ReciprocalRank { k: f32 },
+    /// The scores of each ranking are normalized to `[0, 1]` and summed with the weights.
+    Weighted { keyword: f32, vector: f32 },
+}
+
+impl Default for Fusion {
+    fn default() -> Self {
+        Self::ReciprocalRank { k: DEFAULT_RRF_K }
+    }
+}
+
+/// The settings of a hybrid search, see [`HybridStorage::search_hybrid`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct HybridSearch {
+    pub fusion: Fusion,
+    /// The number of candidates taken from each ranking, four times the limit when not set.
+    #[serde(default)]
+    pub candidates: Option<usize>,
+}
+
+impl HybridSearch {
+    /// Fuses the rankings with the reciprocal-rank fusion.
+    pub fn reciprocal_rank(k: f32) -> Self {
+        Self {
+            fusion: Fusion::ReciprocalRank { k },
+            candidates: None,
+        }
+    }
+
+    /// Fuses the rankings with the weighted sum of their normalized scores.
+    pub fn weighted(keyword: f32, vector: f32) -> Self {
+        Self {
+            fusion: Fusion::Weighted { keyword, vector },
+            candidates: None,
+        }
+    }
+
+    /// Sets the number of candidates taken from each ranking.
+    pub fn candidates(mut self, candidates: usize) -> Self {
+        self.candidates = Some(candidates);
+        self
+    }
+}
+
+/// A storage searching its documents both by keywords, with a local [`KeywordIndex`], and
+/// by embeddings, with the wrapped storage, and fusing the two rankings.
+///
+/// The documents saved through the wrapper are indexed, the documents already in the
+/// wrapped storage can be indexed with [`HybridStorage::index_documents`].
+pub struct HybridStorage<S: Storage> {
+    storage: S,
+    index: RwLock<KeywordIndex>,
+    search: HybridSearch,
+}
+
+impl<S: Storage> HybridStorage<S> {
+    /// Creates a hybrid storage over a vector storage, with an empty keyword index.
+    pub fn new(storage: S) -> Self {
+        Self {
+            storage,
+            index: RwLock::new(KeywordIndex::new()),
+            search: HybridSearch::default(),
+        }
+    }
+
+    /// Sets the keyword index, e.g. with other BM25 parameters or the existing documents.
+    pub fn keyword_index(mut self, index: KeywordIndex) -> Self {
+        self.index = RwLock::new(index);
+        self
+    }
+
+    /// Sets the settings of the searches made through [`Storage::search`].
+    pub fn hybrid_search(mut self, search: HybridSearch) -> Self {
+        self.search = search;
+        self
+    }
+
+    /// Returns the wrapped storage.
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    /// Indexes documents already in the wrapped storage.
+    pub async fn index_documents(&self, documents: Vec<(DocumentId, StorageDocument)>) {
+        let mut index = self.index.write().await;
+        for (id, document) in documents {
+            index.insert(id, document);
+        }
+    }
+
+    /// Searches the documents by keywords and embeddings and fuses the two rankings.
+    ///
+    /// The threshold applies to the scores of the wrapped storage, the returned scores are
+    /// the fused ones.
+    pub async fn search_hybrid(
+        &self,
+        query: &str,
+        limit: usize,
+        threshold: f32,
+        filter: Option<&Filter>,
+        search: &HybridSearch,
+    ) -> TopNResults {
+        let candidates = search.candidates.unwrap_or(limit * 4).max(limit);
+        let vector = self
+            .storage
+            .search_filtered(query, candidates, threshold, filter)
+            .await?;
+        let index = self.index.read().await;
+        let keyword = index.search(query, candidates, filter);
+
+        let mut texts: HashMap<DocumentId, String> = vector
+            .iter()
+            .map(|(id, text, _)| (id.clone(), text.clone()))
+            .collect();
+        for (id, _) in &keyword {
+            if let Some(document) = index.get(id) {
+                texts
+                    .entry(id.clone())
+                    .or_insert_with(|| document.text.clone());
+            }
+        }
+        let vector: Vec<_> = vector
+            .into_iter()
+            .map(|(id, _, score)| (id, score))
+            .collect();
+        Ok(fuse(&search.fusion, &keyword, &vector)
+            .into_iter()
+            .filter_map(|(id, score)| {
+                let text = texts.remove(&id)?;
+                Some((id, text, score))
+            })
+            .take(limit)
+            .collect())
+    }
+}
+
+#[async_trait]
+impl<S: Storage> Storage for HybridStorage<S> {
+    async fn save_documents(
+        &self,
+        documents: Vec<StorageDocument>,
+    ) -> Result<Vec<DocumentId>, VectorStoreError> {
+        let ids = self.storage.save_documents(documents.clone()).await?;
+        self.index_documents(ids.iter().cloned().zip(documents).collect())
+            .await;
+        Ok(ids)
+    }
+
+    async fn upsert(
+        &self,
+        id: &DocumentId,
+        document: StorageDocument,
+    ) -> Result<(), VectorStoreError> {
+        self.storage.upsert(id, document.clone()).await?;
+        self.index.write().await.insert(id.clone(), document);
+        Ok(())
+    }
+
+    async fn get(&self, id: &DocumentId) -> Result<Option<StorageDocument>, VectorStoreError> {
+        self.storage.get(id).await
+    }
+
+    async fn delete(&self, id: &DocumentId) -> Result<(), VectorStoreError> {
+        self.storage.delete(id).await?;
+        self.index.write().await.remove(id);
+        Ok(())
+    }
+
+    async fn search_filtered(
+        &self,
+        query: &str,
+        limit: usize,
+        threshold: f32,
+        filter: Option<&Filter>,
+    ) -> TopNResults {
+        self.search_hybrid(query, limit, threshold, filter, &self.search)
+            .await
+    }
+
+    async fn reset(&self) -> Result<(), VectorStoreError> {
+        self.storage.reset().await?;
+        self.index.write().await.clear();
+        Ok(())
+    }
+}
+
+/// Fuses two rankings, best first, into one ranking, best first.
+fn fuse(
+    fusion: &Fusion,
+    keyword: &[(DocumentId, f32)],
+    vector: &[(DocumentId, f32)],
+) -> Vec<(DocumentId, f32)> {
+    let mut scores: HashMap<&DocumentId, f32> = HashMap::new();
+    match *fusion {
+        Fusion::ReciprocalRank { k } => {
+            for ranking in [keyword, vector] {
+                for (rank, (id, _)) in ranking.iter().enumerate() {
+                    *scores.entry(id).or_default() += 1.0 / (k + rank as f32 + 1.0);
+                }
+            }
+        }
+        Fusion::Weighted {
+            keyword: keyword_weight,
+            vector: vector_weight,
+        } => {
+            for (ranking, weight) in [(keyword, keyword_weight), (vector, vector_weight)] {
+                let max = ranking.iter().map(|(_, s)| *s).fold(f32::MIN, f32::max);
+                let min = ranking.iter().map(|(_, s)| *s).fold(f32::MAX, f32::min);
+                for (id, score) in ranking {
+                    let normalized = match max > min {
+                        true => (score - min) / (max - min),
+                        false => 1.0,
+                    };
+                    *scores.entry(id).or_default() += weight * normalized;
+                }
+            }
+        }
+    }
+    let mut fused: Vec<_> = scores
+        .into_iter()
+        .map(|(id, score)| (id.clone(), score))
+        .collect();
+    fused.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    fused
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embeddings::{Embeddings, EmbeddingsData, EmbeddingsError};
+    use crate::store::InMemoryStorage;
+
+    /// Embeds a text as its length, so a query is closest to the texts of its length
+    /// whatever their words.
+    #[derive(Clone)]
+    struct Lengths;
+
+    #[async_trait]
+    impl Embeddings for Lengths {
+        async fn embed_texts(
+            &self,
+            input: Vec<String>,
+        ) -> Result<Vec<EmbeddingsData>, EmbeddingsError> {
+            Ok(input
+                .into_iter()
+                .map(|document| EmbeddingsData {
+                    vec: vec![document.len() as f64, 1.0],
+                    document,
+                })
+                .collect())
+        }
+    }
+
+    #[test]
+    fn test_fusion() {
+        let id = |id: &str| DocumentId(id.to_string());
+        let keyword = [(id("a"), 7.5), (id("b"), 2.0)];
+        let vector = [(id("c"), 0.9), (id("b"), 0.8), (id("a"), 0.1)];
+
+        let fused = fuse(&Fusion::default(), &keyword, &vector);
+        let ids: Vec<_> = fused.iter().map(|(id, _)| id.0.as_str()).collect();
+        assert_eq!(ids, ["a", "b", "c"]);
+
+        let fused = fuse(
+            &Fusion::Weighted {
+                keyword: 0.3,
+                vector: 0.7,
+            },
+            &keyword,
+            &vector,
+        );
+        let ids: Vec<_> = fused.iter().map(|(id, _)| id.0.as_str()).collect();
+        assert_eq!(ids, ["c", "b", "a"]);
+        assert!((fused[0].1 - 0.7).abs() < 1e-6);
+    }
+
+    #[tokio::test]
+    async fn test_hybrid_storage() {
+        let storage = HybridStorage::new(InMemoryStorage::from_documents(Lengths, vec![]))
+            .hybrid_search(HybridSearch::weighted(0.0, 1.0));
+        let texts = [
+            "ERR_TIMEOUT while syncing the mailbox",
+            "the printer is out of paper",
+            "the laptop does not boot",
+            "wifi drops",
+        ];
+        let ids = storage
+            .save_documents(texts.into_iter().map(StorageDocument::new).collect())
+            .await
+            .unwrap();
+        let top = |results: TopNResults| results.unwrap()[0].1.clone();
+
+        // The embeddings rank the identifier last, the keywords find it.
+        let vector = storage.storage().search("ERR_TIMEOUT", 4, 0.0).await;
+        assert_eq!(vector.unwrap()[3].0, ids[0]);
+        let fused = storage
+            .search_hybrid("ERR_TIMEOUT", 1, 0.0, None, &HybridSearch::default())
+            .await;
+        assert_eq!(top(fused), texts[0]);
+        // The storage settings only weigh the embeddings.
+        assert_eq!(top(storage.search("ERR_TIMEOUT", 1, 0.0).await), texts[3]);
+
+        storage.delete(&ids[0]).await.unwrap();
+        assert!(storage.index.read().await.get(&ids[0]).is_none());
+        let fused = storage
+            .search_hybrid("ERR_TIMEOUT", 4, 0.0, None, &HybridSearch::default())
+            .await
+            .unwrap();
+        assert!(fused.iter().all(|(id, _, _)| *id != ids[0]));
+
+        storage.reset().await.unwrap();
+        assert!(storage.index.read().await.is_empty());
+        let fused = storage
+            .search_hybrid("printer", 4, 0.0, None, &HybridSearch::default())
+            .await;
+        assert!(fused.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,192 @@
+use super::{DocumentId, Filter, Metadata, StorageDocument};
+use std::collections::HashMap;
+
+/// The default term frequency saturation of BM25.
+pub const DEFAULT_BM25_K1: f32 = 1.2;
+/// The default document length normalization of BM25.
+pub const DEFAULT_BM25_B: f32 = 0.75;
+
+/// A document of a [`KeywordIndex`].
+struct IndexedDocument {
+    document: StorageDocument,
+    /// The number of terms of the document.
+    len: usize,
+}
+
+/// An inverted index ranking the documents matching the terms of a query with BM25.
+///
+/// Unlike embeddings, it finds the exact identifiers, error codes and names of a query.
+/// Terms are the lowercased alphanumeric words of the text, `_` included, so
+/// `ERR_TIMEOUT` is a single term and `ERR-42` matches `err` and `42`.
+pub struct KeywordIndex {
+    k1: f32,
+    b: f32,
+    documents: HashMap<DocumentId, IndexedDocument>,
+    /// The frequency of each term in the documents containing it.
+    postings: HashMap<String, HashMap<DocumentId, u32>>,
+    /// The total number of terms of the documents.
+    total_len: usize,
+}
+
+impl Default for KeywordIndex {
+    fn default() -> Self {
+        Self {
+            k1: DEFAULT_BM25_K1,
+            b: DEFAULT_BM25_B,
+            documents: HashMap::new(),
+            postings: HashMap::new(),
+            total_len: 0,
+        }
+    }
+}
+
+impl KeywordIndex {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the BM25 parameters: `k1` limits the weight of repeated terms and `b` how much
+    /// long documents are penalized.
+    pub fn bm25(mut self, k1: f32, b: f32) -> Self {
+        self.k1 = k1;
+        self.b = b;
+        self
+    }
+
+    /// Returns the number of indexed documents.
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    /// Returns whether the index has no documents.
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+
+    /// Indexes a document, replacing the document with the same ID.
+    pub fn insert(&mut self, id: DocumentId, document: StorageDocument) {
+        self.remove(&id);
+        let terms = tokenize(&document.text);
+        let len = terms.len();
+        for term in terms {
+            *self
+                .postings
+                .entry(term)
+                .or_default()
+                .entry(id.clone())
+                .or_default() += 1;
+        }
+        self.total_len += len;
+        self.documents.insert(id, IndexedDocument { document, len });
+    }
+
+    /// Removes a document, returning it if it was indexed.
+    pub fn remove(&mut self, id: &DocumentId) -> Option<StorageDocument> {
+        let indexed = self.documents.remove(id)?;
+        self.total_len -= indexed.len;
+        for term in tokenize(&indexed.document.text) {
+            if let Some(postings) = self.postings.get_mut(&term) {
+                postings.remove(id);
+                if postings.is_empty() {
+                    self.postings.remove(&term);
+                }
+            }
+        }
+        Some(indexed.document)
+    }
+
+    /// Removes every document.
+    pub fn clear(&mut self) {
+        self.documents.clear();
+        self.postings.clear();
+        self.total_len = 0;
+    }
+
+    /// Returns the indexed document with the given ID.
+    pub fn get(&self, id: &DocumentId) -> Option<&StorageDocument> {
+        self.documents.get(id).map(|indexed| &indexed.document)
+    }
+
+    /// Returns the metadata of the indexed document with the given ID.
+    pub fn metadata(&self, id: &DocumentId) -> Option<&Metadata> {
+        self.get(id).map(|document| &document.metadata)
+    }
+
+    /// Returns the documents matching the terms of the query, and the filter if any,
+    /// with their BM25 score, best first.
+    pub fn search(
+        &self,
+        query: &str,
+        limit: usize,
+        filter: Option<&Filter>,
+    ) -> Vec<(DocumentId, f32)> {
+        let count = self.documents.len() as f32;
+        let avg_len = self.total_len as f32 / count.max(1.0);
+        let mut terms = tokenize(query);
+        terms.sort();
+        terms.dedup();
+        let mut scores: HashMap<&DocumentId, f32> = HashMap::new();
+        for term in &terms {
+            let Some(postings) = self.postings.get(term) else {
+                continue;
+            };
+            let df = postings.len() as f32;
+            let idf = ((count - df + 0.5) / (df + 0.5) + 1.0).ln();
+            for (id, tf) in postings {
+                let len = self.documents[id].len as f32;
+                let tf = *tf as f32;
+                let norm = self.k1 * (1.0 - self.b + self.b * len / avg_len.max(f32::EPSILON));
+                *scores.entry(id).or_default() += idf * tf * (self.k1 + 1.0) / (tf + norm);
+            }
+        }
+        let mut results: Vec<_> = scores
+            .into_iter()
+            .filter(|(id, _)| {
+                filter.is_none_or(|filter| filter.matches(&self.documents[*id].document.metadata))
+            })
+            .map(|(id, score)| (id.clone(), score))
+            .collect();
+        results.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        results.truncate(limit);
+        results
+    }
+}
+
+/// Splits a text into lowercased terms.
+fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|term| !term.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keyword_search() {
+        let mut index = KeywordIndex::new();
+        let id = |id: &str| DocumentId(id.to_string());
+        index.insert(id("a"), "The request failed with ERR_TIMEOUT".into());
+        index.insert(id("b"), "Timeouts are retried, see the retry policy".into());
+        index.insert(
+            id("c"),
+            StorageDocument::new("ERR_TIMEOUT is raised after 30s").with_metadata("lang", "en"),
+        );
+
+        let results = index.search("what is err_timeout?", 10, None);
+        let ids: Vec<_> = results.iter().map(|(id, _)| id.0.as_str()).collect();
+        assert_eq!(ids.len(), 2);
+        assert!(ids.contains(&"a") && ids.contains(&"c"));
+        let results = index.search("err_timeout", 10, Some(&Filter::eq("lang", "en")));
+        assert_eq!(results[0].0, id("c"));
+
+        index.insert(id("c"), "Replaced".into());
+        assert_eq!(index.search("err_timeout", 10, None).len(), 1);
+        assert!(index.remove(&id("a")).is_some());
+        assert!(index.search("err_timeout", 10, None).is_empty());
+        assert_eq!(index.len(), 2);
+    }
+}
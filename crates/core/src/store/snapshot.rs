@@ -0,0 +1,125 @@
+use super::{DocumentId, Entries, InMemoryStorage, Metadata, VectorStoreError};
+use crate::embeddings::{Embeddings, EmbeddingsData};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// The version of the [`InMemoryStorage`] file format.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// The content of an [`InMemoryStorage`] file.
+///
+/// The HNSW graph isn't saved, it is rebuilt from the vectors when loading, which is
+/// much faster than embedding the documents again.
+#[derive(Serialize, Deserialize)]
+struct Snapshot<'a> {
+    version: u32,
+    /// The name of the embedding model of the vectors, if known.
+    model: Option<String>,
+    /// The length of the vectors.
+    dimensions: Option<usize>,
+    next_id: u64,
+    documents: Vec<SnapshotDocument<'a>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SnapshotDocument<'a> {
+    id: Cow<'a, DocumentId>,
+    text: Cow<'a, str>,
+    #[serde(default)]
+    metadata: Cow<'a, Metadata>,
+    vector: Cow<'a, [f64]>,
+}
+
+impl<E: Embeddings> InMemoryStorage<E> {
+    /// Saves the documents and their vectors to a file, replacing it atomically.
+    pub async fn save_to(&self, path: impl AsRef<Path>) -> Result<(), VectorStoreError> {
+        let path = path.as_ref();
+        let bytes = {
+            let entries = self.entries.read().await;
+            let documents: Vec<_> = entries
+                .points
+                .iter()
+                .flatten()
+                .map(|entry| SnapshotDocument {
+                    id: Cow::Borrowed(&entry.id),
+                    text: Cow::Borrowed(&entry.data.document),
+                    metadata: Cow::Borrowed(&entry.metadata),
+                    vector: Cow::Borrowed(&entry.data.vec),
+                })
+                .collect();
+            serde_json::to_vec(&Snapshot {
+                version: SNAPSHOT_VERSION,
+                model: self.embeddings.model_name(),
+                dimensions: documents.first().map(|document| document.vector.len()),
+                next_id: entries.next_id,
+                documents,
+            })?
+        };
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        tokio::fs::write(&tmp, bytes).await?;
+        tokio::fs::rename(&tmp, path).await?;
+        Ok(())
+    }
+
+    /// Loads a storage saved with [`InMemoryStorage::save_to`], rebuilding its index.
+    ///
+    /// Fails if the file has another format version, or if its vectors were made by
+    /// another embedding model than `embeddings`, when both model names are known.
+    pub async fn load_from(
+        embeddings: E,
+        path: impl AsRef<Path>,
+    ) -> Result<Self, VectorStoreError> {
+        let bytes = tokio::fs::read(path).await?;
+        let snapshot: Snapshot = serde_json::from_slice(&bytes)?;
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(VectorStoreError::IncompatibleSnapshot(format!(
+                "version {} is not supported, expected {SNAPSHOT_VERSION}",
+                snapshot.version
+            )));
+        }
+        match (&snapshot.model, embeddings.model_name()) {
+            (Some(saved), Some(model)) if *saved != model => {
+                return Err(VectorStoreError::IncompatibleSnapshot(format!(
+                    "the vectors were embedded with `{saved}`, not `{model}`"
+                )));
+            }
+            _ => {}
+        }
+        let mut entries = Entries::default();
+        for document in snapshot.documents {
+            if snapshot
+                .dimensions
+                .is_some_and(|dimensions| document.vector.len() != dimensions)
+            {
+                return Err(VectorStoreError::IncompatibleSnapshot(format!(
+                    "the vector of the document {} has {} dimensions",
+                    document.id,
+                    document.vector.len()
+                )));
+            }
+            let data = EmbeddingsData {
+                document: document.text.into_owned(),
+                vec: document.vector.into_owned(),
+            };
+            entries.insert(
+                document.id.into_owned(),
+                data,
+                document.metadata.into_owned(),
+            );
+        }
+        entries.next_id = snapshot.next_id;
+        let hnsw = entries.reindex();
+        Ok(Self {
+            entries: Arc::new(RwLock::new(entries)),
+            hnsw: Arc::new(RwLock::new(hnsw)),
+            embeddings: Arc::new(embeddings),
+        })
+    }
+}
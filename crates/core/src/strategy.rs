@@ -0,0 +1,608 @@
+use crate::agent::{Agent, task_error};
+use crate::chat::{Completion, Message, ToolCall};
+use crate::hook::Hook;
+use crate::structured;
+use crate::task::TaskError;
+use async_trait::async_trait;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// The default maximum number of steps run by [`PlanAndExecute`], including the failed ones.
+pub const DEFAULT_MAX_STEPS: usize = 8;
+/// The default maximum number of plan revisions of [`PlanAndExecute`].
+pub const DEFAULT_MAX_REVISIONS: usize = 2;
+/// The prefix of the answer of the model when it can't complete a step of a plan.
+pub const STEP_FAILED: &str = "STEP FAILED:";
+/// The prefix of the final answer of a [`ReAct`] loop.
+pub const FINAL_ANSWER: &str = "Final Answer:";
+
+const REACT_INSTRUCTIONS: &str = "Solve the task step by step. Before each action, write your \
+reasoning as `Thought: ...` and call a tool, then use its result to decide the next step. When \
+you know the answer, write `Final Answer:` followed by the answer.";
+
+/// A step of the reasoning of an agent.
+#[derive(Debug, Clone, Serialize)]
+pub enum Step {
+    /// The reasoning of the model before its actions.
+    Thought(String),
+    /// A tool call requested by the model.
+    Action(ToolCall),
+    /// The output of a tool call, or its error.
+    Observation {
+        tool_call_id: String,
+        output: String,
+    },
+    /// The steps planned by the model, or revised after a failure.
+    Plan(Vec<String>),
+    /// A step of the plan and its result.
+    StepCompleted { step: String, output: String },
+    /// A step of the plan that couldn't be completed.
+    StepFailed { step: String, reason: String },
+    /// The final answer.
+    Answer(String),
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Step::Thought(thought) => write!(f, "Thought: {thought}"),
+            Step::Action(call) => write!(
+                f,
+                "Action: {}({})",
+                call.function.name, call.function.arguments
+            ),
+            Step::Observation { output, .. } => write!(f, "Observation: {output}"),
+            Step::Plan(steps) => {
+                write!(f, "Plan:")?;
+                for (index, step) in steps.iter().enumerate() {
+                    write!(f, "\n  {}. {step}", index + 1)?;
+                }
+                Ok(())
+            }
+            Step::StepCompleted { step, output } => write!(f, "Completed: {step}\n  {output}"),
+            Step::StepFailed { step, reason } => write!(f, "Failed: {step}\n  {reason}"),
+            Step::Answer(answer) => write!(f, "{FINAL_ANSWER} {answer}"),
+        }
+    }
+}
+
+/// The recorded steps of a strategy run, ending with the final answer.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Trace {
+    pub steps: Vec<Step>,
+}
+
+impl Trace {
+    /// Returns the final answer, empty when the run didn't produce one.
+    pub fn answer(&self) -> &str {
+        self.steps
+            .iter()
+            .rev()
+            .find_map(|step| match step {
+                Step::Answer(answer) => Some(answer.as_str()),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, step) in self.steps.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{step}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A reasoning strategy answering a prompt with an agent, its model and its tools.
+///
+/// Strategies run the turns of the agent with the [`crate::executor::Executor`] and
+/// record their steps into a [`Trace`]. See [`Agent::strategy`].
+#[async_trait]
+pub trait Strategy<M: Completion>: Send + Sync {
+    async fn run(
+        &self,
+        agent: &Agent<M>,
+        prompt: &str,
+        history: Vec<Message>,
+    ) -> Result<Trace, TaskError>
+    where
+        M: Send + Sync;
+}
+
+/// Records the thoughts, actions and observations of a turn.
+#[derive(Default)]
+struct Recorder {
+    steps: Mutex<Vec<Step>>,
+}
+
+impl Recorder {
+    async fn take(&self) -> Vec<Step> {
+        std::mem::take(&mut *self.steps.lock().await)
+    }
+}
+
+#[async_trait]
+impl Hook for Recorder {
+    async fn after_response(&self, content: &mut String, calls: &[ToolCall]) {
+        if calls.is_empty() {
+            return;
+        }
+        let mut steps = self.steps.lock().await;
+        let thought = content.trim();
+        if !thought.is_empty() {
+            steps.push(Step::Thought(
+                thought.trim_start_matches("Thought:").trim().to_string(),
+            ));
+        }
+        steps.extend(calls.iter().cloned().map(Step::Action));
+    }
+
+    async fn after_tool_call(&self, call: &ToolCall, output: &mut String) {
+        self.steps.lock().await.push(Step::Observation {
+            tool_call_id: call.id.clone(),
+            output: output.clone(),
+        });
+    }
+}
+
+/// Runs a single turn of the agent, recording its tool calls. The default strategy.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Direct;
+
+#[async_trait]
+impl<M: Completion> Strategy<M> for Direct {
+    async fn run(
+        &self,
+        agent: &Agent<M>,
+        prompt: &str,
+        history: Vec<Message>,
+    ) -> Result<Trace, TaskError>
+    where
+        M: Send + Sync,
+    {
+        let recorder = Arc::new(Recorder::default());
+        let (executor, req) = agent.prepare_chat(prompt, history).await?;
+        let output = executor
+            .with_hook(recorder.clone())
+            .invoke(req)
+            .await
+            .map_err(task_error)?;
+        let mut steps = recorder.take().await;
+        steps.push(Step::Answer(output));
+        Ok(Trace { steps })
+    }
+}
+
+/// Interleaves thoughts, actions and observations until the model knows the answer.
+///
+/// The model is asked to reason before each tool call, and the loop ends when it answers
+/// without calling a tool. The text before [`FINAL_ANSWER`] is recorded as a last thought.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReAct;
+
+#[async_trait]
+impl<M: Completion> Strategy<M> for ReAct {
+    async fn run(
+        &self,
+        agent: &Agent<M>,
+        prompt: &str,
+        history: Vec<Message>,
+    ) -> Result<Trace, TaskError>
+    where
+        M: Send + Sync,
+    {
+        let recorder = Arc::new(Recorder::default());
+        let (executor, mut req) = agent.prepare_chat(prompt, history).await?;
+        req.preamble = join(&req.preamble, REACT_INSTRUCTIONS);
+        let output = executor
+            .with_hook(recorder.clone())
+            .invoke(req)
+            .await
+            .map_err(task_error)?;
+        let mut steps = recorder.take().await;
+        let answer = match output.split_once(FINAL_ANSWER) {
+            Some((thought, answer)) => {
+                let thought = thought.trim().trim_start_matches("Thought:").trim();
+                if !thought.is_empty() {
+                    steps.push(Step::Thought(thought.to_string()));
+                }
+                answer.trim().to_string()
+            }
+            None => output,
+        };
+        steps.push(Step::Answer(answer));
+        Ok(Trace { steps })
+    }
+}
+
+/// The steps planned by the model.
+#[derive(Deserialize, JsonSchema)]
+struct Plan {
+    /// The steps to complete the task, in order.
+    steps: Vec<String>,
+}
+
+/// Plans the steps of the task with the model, runs them one at a time with the tools,
+/// and revises the remaining plan when a step fails.
+///
+/// A step fails when its turn fails or when the model answers with [`STEP_FAILED`]. The
+/// intermediate turns are not stored into the memory, only the prompt and the answer.
+#[derive(Debug, Clone)]
+pub struct PlanAndExecute {
+    max_steps: usize,
+    max_revisions: usize,
+}
+
+impl Default for PlanAndExecute {
+    fn default() -> Self {
+        Self {
+            max_steps: DEFAULT_MAX_STEPS,
+            max_revisions: DEFAULT_MAX_REVISIONS,
+        }
+    }
+}
+
+impl PlanAndExecute {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum number of steps run, including the failed ones.
+    pub fn max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    /// Sets the maximum number of plan revisions after a failed step.
+    pub fn max_revisions(mut self, max_revisions: usize) -> Self {
+        self.max_revisions = max_revisions;
+        self
+    }
+
+    /// Asks the model for the steps completing the task, without calling tools.
+    async fn plan<M: Completion + Send + Sync>(
+        &self,
+        agent: &Agent<M>,
+        prompt: &str,
+        history: Vec<Message>,
+        request: String,
+    ) -> Result<Vec<String>, TaskError> {
+        let schema = structured::schema_of::<Plan>();
+        let (executor, mut req) = agent.prepare_chat(prompt, history).await?;
+        let tools: Vec<String> = req
+            .tools
+            .drain(..)
+            .map(|tool| format!("- {}: {}", tool.name, tool.description))
+            .collect();
+        req.prompt = format!(
+            "{request}\nUse at most {} steps. The available tools are:\n{}",
+            self.max_steps,
+            tools.join("\n")
+        );
+        req.preamble = join(&req.preamble, &structured::instructions(&schema));
+        let output = executor
+            .without_memory()
+            .invoke(req)
+            .await
+            .map_err(task_error)?;
+        let plan: Plan = structured::parse_typed(&output, &schema)
+            .map_err(|errors| TaskError::ExecutionError(format!("Invalid plan: {errors}")))?;
+        Ok(plan.steps)
+    }
+
+    /// Runs a step with the tools, returning its output or why it failed.
+    async fn execute<M: Completion + Send + Sync>(
+        &self,
+        agent: &Agent<M>,
+        prompt: &str,
+        history: Vec<Message>,
+        completed: &str,
+        step: &str,
+        recorder: &Arc<Recorder>,
+    ) -> Result<Result<String, String>, TaskError> {
+        let (executor, mut req) = agent.prepare_chat(prompt, history).await?;
+        req.prompt = format!(
+            "Task: {prompt}\n\nCompleted steps:\n{completed}\n\nComplete only this step: {step}\n\
+            If you can't complete it, answer `{STEP_FAILED}` followed by the reason."
+        );
+        let result = executor
+            .without_memory()
+            .with_hook(recorder.clone())
+            .invoke(req)
+            .await;
+        Ok(match result {
+            Ok(output) => match output.trim().strip_prefix(STEP_FAILED) {
+                Some(reason) => Err(reason.trim().to_string()),
+                None => Ok(output),
+            },
+            Err(err) => Err(task_error(err).to_string()),
+        })
+    }
+}
+
+#[async_trait]
+impl<M: Completion> Strategy<M> for PlanAndExecute {
+    async fn run(
+        &self,
+        agent: &Agent<M>,
+        prompt: &str,
+        history: Vec<Message>,
+    ) -> Result<Trace, TaskError>
+    where
+        M: Send + Sync,
+    {
+        let recorder = Arc::new(Recorder::default());
+        let mut trace = Trace::default();
+        let mut plan = self
+            .plan(
+                agent,
+                prompt,
+                history.clone(),
+                format!("Make a plan to complete this task: {prompt}"),
+            )
+            .await?;
+        trace.steps.push(Step::Plan(plan.clone()));
+        let mut completed: Vec<(String, String)> = Vec::new();
+        let (mut executed, mut revisions) = (0, 0);
+        while let Some(step) = (!plan.is_empty()).then(|| plan.remove(0)) {
+            if executed == self.max_steps {
+                return Err(TaskError::ExecutionError(format!(
+                    "The plan needs more than {} steps",
+                    self.max_steps
+                )));
+            }
+            executed += 1;
+            let result = self
+                .execute(
+                    agent,
+                    prompt,
+                    history.clone(),
+                    &format_completed(&completed),
+                    &step,
+                    &recorder,
+                )
+                .await?;
+            trace.steps.extend(recorder.take().await);
+            match result {
+                Ok(output) => {
+                    trace.steps.push(Step::StepCompleted {
+                        step: step.clone(),
+                        output: output.clone(),
+                    });
+                    completed.push((step, output));
+                }
+                Err(reason) => {
+                    trace.steps.push(Step::StepFailed {
+                        step: step.clone(),
+                        reason: reason.clone(),
+                    });
+                    if revisions == self.max_revisions {
+                        return Err(TaskError::ExecutionError(format!(
+                            "The step `{step}` failed after {revisions} plan revisions: {reason}"
+                        )));
+                    }
+                    revisions += 1;
+                    plan = self
+                        .plan(
+                            agent,
+                            prompt,
+                            history.clone(),
+                            format!(
+                                "Task: {prompt}\n\nCompleted steps:\n{}\n\nThe step `{step}` failed: \
+                                {reason}\nMake a new plan of the remaining steps to complete the task.",
+                                format_completed(&completed)
+                            ),
+                        )
+                        .await?;
+                    trace.steps.push(Step::Plan(plan.clone()));
+                }
+            }
+        }
+
+        let (executor, mut req) = agent.prepare_chat(prompt, history).await?;
+        req.tools.clear();
+        req.prompt = format!(
+            "Task: {prompt}\n\nCompleted steps:\n{}\n\nAnswer the task using the results of the steps.",
+            format_completed(&completed)
+        );
+        let answer = executor
+            .without_memory()
+            .invoke(req)
+            .await
+            .map_err(task_error)?;
+        if let Some(memory) = &agent.memory {
+            let mut memory = memory.write().await;
+            memory.add_user_message(prompt);
+            memory.add_ai_message(&answer);
+        }
+        trace.steps.push(Step::Answer(answer));
+        Ok(trace)
+    }
+}
+
+/// Joins the preamble of the agent and the instructions of a strategy.
+fn join(preamble: &str, instructions: &str) -> String {
+    [preamble, instructions].join("\n\n").trim().to_string()
+}
+
+fn format_completed(completed: &[(String, String)]) -> String {
+    if completed.is_empty() {
+        return "None".to_string();
+    }
+    completed
+        .iter()
+        .enumerate()
+        .map(|(index, (step, output))| format!("{}. {step}\n   Result: {output}", index + 1))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chat::{
+        CallFunction, CompletionError, Request, ResponseContent, ResponseTokenUsage,
+        ResponseToolCalls, TokenUsage,
+    };
+    use crate::memory::WindowBufferMemory;
+    use crate::tool::{StructureTool, ToolError};
+
+    /// A model following a script keyed by the prompt and the tool results of the turn.
+    struct Scripted;
+
+    struct Reply(String, Vec<ToolCall>);
+
+    impl ResponseContent for Reply {
+        fn content(&self) -> String {
+            self.0.clone()
+        }
+    }
+
+    impl ResponseToolCalls for Reply {
+        fn toolcalls(&self) -> Vec<ToolCall> {
+            self.1.clone()
+        }
+    }
+
+    impl ResponseTokenUsage for Reply {
+        fn token_usage(&self) -> TokenUsage {
+            TokenUsage {
+                tokens_cached: None,
+                prompt_tokens: 0,
+                completion_tokens: 0,
+                total_tokens: 0,
+            }
+        }
+    }
+
+    fn reply(content: &str) -> Reply {
+        Reply(content.to_string(), Vec::new())
+    }
+
+    impl Completion for Scripted {
+        type Response = Reply;
+
+        async fn completion(&mut self, request: Request) -> Result<Reply, CompletionError> {
+            let result = request.tool_messages.iter().find(|m| m.role == "tool");
+            let prompt = request.prompt.as_str();
+            Ok(if prompt.starts_with("Make a plan") {
+                reply(r#"{"steps": ["add 1 and 2", "double it"]}"#)
+            } else if prompt.contains("failed: no doubling tool") {
+                reply(r#"{"steps": ["add the sum to itself"]}"#)
+            } else if prompt.contains("Answer the task") {
+                reply("The answer is 6")
+            } else if prompt.contains("this step: double it") {
+                reply("STEP FAILED: no doubling tool")
+            } else if let Some(result) = result {
+                reply(&format!(
+                    "Thought: the sum is {0}\nFinal Answer: {0}",
+                    result.content
+                ))
+            } else {
+                let arguments = match prompt.contains("add the sum") {
+                    true => r#"{"x": 3, "y": 3}"#,
+                    false => r#"{"x": 1, "y": 2}"#,
+                };
+                let call = ToolCall {
+                    id: "call-1".to_string(),
+                    r#type: "function".to_string(),
+                    function: CallFunction {
+                        name: "add".to_string(),
+                        arguments: arguments.to_string(),
+                    },
+                };
+                Reply("Thought: I need to add".to_string(), vec![call])
+            })
+        }
+    }
+
+    struct Add;
+
+    #[derive(JsonSchema, Deserialize)]
+    struct AddInput {
+        x: usize,
+        y: usize,
+    }
+
+    #[async_trait]
+    impl StructureTool for Add {
+        type Input = AddInput;
+        type Output = usize;
+
+        fn name(&self) -> &str {
+            "add"
+        }
+
+        async fn run_with_args(&self, input: Self::Input) -> Result<Self::Output, ToolError> {
+            Ok(input.x + input.y)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_react_records_the_trace() {
+        let agent = Agent::new("agent", Scripted)
+            .tool(Add)
+            .await
+            .strategy(ReAct);
+        let trace = agent.prompt_traced("What is 1 + 2?").await.unwrap();
+        assert_eq!(trace.answer(), "3");
+        assert_eq!(
+            trace.to_string(),
+            "Thought: I need to add\nAction: add({\"x\": 1, \"y\": 2})\nObservation: 3\n\
+            Thought: the sum is 3\nFinal Answer: 3"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_plan_and_execute_revises_the_plan() {
+        let agent = Agent::new("agent", Scripted)
+            .tool(Add)
+            .await
+            .memory(WindowBufferMemory::new(10))
+            .strategy(PlanAndExecute::new());
+        let trace = agent.prompt_traced("Double 1 + 2").await.unwrap();
+        let kinds: Vec<&str> = trace
+            .steps
+            .iter()
+            .map(|step| match step {
+                Step::Thought(_) => "thought",
+                Step::Action(_) => "action",
+                Step::Observation { .. } => "observation",
+                Step::Plan(_) => "plan",
+                Step::StepCompleted { .. } => "completed",
+                Step::StepFailed { .. } => "failed",
+                Step::Answer(_) => "answer",
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                "plan",
+                "thought",
+                "action",
+                "observation",
+                "completed",
+                "failed",
+                "plan",
+                "thought",
+                "action",
+                "observation",
+                "completed",
+                "answer"
+            ]
+        );
+        assert_eq!(trace.answer(), "The answer is 6");
+        // Only the prompt and the answer are stored.
+        let memory = agent.memory.as_ref().unwrap().read().await.messages();
+        assert_eq!(memory.len(), 2);
+    }
+}
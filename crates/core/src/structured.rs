@@ -0,0 +1,357 @@
+use crate::chat::{JsonSchemaFormat, ResponseFormat};
+use crate::json::{JsonParseError, parse_json_markdown, parse_partial_json};
+use crate::task::TaskError;
+use schemars::{JsonSchema, schema_for};
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+
+/// The default number of times the model is re-prompted with the validation errors of
+/// its output.
+pub const DEFAULT_MAX_REPAIRS: usize = 2;
+
+#[derive(Debug, thiserror::Error)]
+pub enum StructuredOutputError {
+    #[error("Task error: {0}")]
+    TaskError(#[from] TaskError),
+    #[error("The output is invalid after {attempts} attempts: {errors}")]
+    InvalidOutput { attempts: usize, errors: String },
+}
+
+/// Returns the JSON schema of the type.
+pub fn schema_of<T: JsonSchema>() -> Value {
+    serde_json::to_value(schema_for!(T)).unwrap_or_default()
+}
+
+/// Returns the response format constraining the output to the schema, if the schema
+/// describes an object as required by the providers.
+pub fn response_format(schema: &Value) -> Option<ResponseFormat> {
+    if schema.get("type").and_then(Value::as_str) != Some("object") {
+        return None;
+    }
+    let name: String = schema
+        .get("title")
+        .and_then(Value::as_str)
+        .unwrap_or("output")
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '-')
+        .collect();
+    Some(ResponseFormat::JsonSchema {
+        json_schema: JsonSchemaFormat {
+            name: if name.is_empty() {
+                "output".to_string()
+            } else {
+                name
+            },
+            description: None,
+            schema: schema.clone(),
+            strict: None,
+        },
+    })
+}
+
+/// Returns the instructions asking the model to answer with JSON matching the schema.
+pub(crate) fn instructions(schema: &Value) -> String {
+    format!(
+        "Respond only with a JSON value matching this JSON schema, without any other text:\n{}",
+        serde_json::to_string_pretty(schema).unwrap_or_default()
+    )
+}
+
+/// Returns the prompt asking the model to fix its previous output.
+pub(crate) fn repair_prompt(errors: &str) -> String {
+    format!(
+        "Your previous answer is invalid: {errors}\nRespond again only with a JSON value matching the JSON schema."
+    )
+}
+
+/// Parses the JSON value in the output of a model, which may be in a markdown code block,
+/// surrounded by text or truncated.
+pub fn parse_output(output: &str) -> Result<Value, JsonParseError> {
+    parse_json_markdown(output).or_else(|err| {
+        let start = output.find(['{', '[']);
+        let end = output.rfind(['}', ']']);
+        match (start, end) {
+            (Some(start), Some(end)) if start < end => parse_partial_json(&output[start..=end]),
+            (Some(start), _) => parse_partial_json(&output[start..]),
+            _ => Err(err),
+        }
+    })
+}
+
+/// Parses the output of a model, validates it against the schema and deserializes it.
+///
+/// Returns the errors as a message for the model when the output is invalid.
+pub fn parse_typed<T: DeserializeOwned>(output: &str, schema: &Value) -> Result<T, String> {
+    let value = parse_output(output).map_err(|err| err.to_string())?;
+    let errors = validate(&value, schema);
+    if !errors.is_empty() {
+        return Err(errors.join("; "));
+    }
+    serde_json::from_value(value).map_err(|err| err.to_string())
+}
+
+/// Validates a value against a JSON schema, returning the errors with their paths.
+///
+/// Supports the keywords generated by `schemars`: `$ref` to the definitions, `type`,
+/// `enum`, `const`, `properties`, `required`, `additionalProperties`, `items`, `anyOf`,
+/// `oneOf`, `allOf`, `minimum` and `maximum`.
+pub fn validate(value: &Value, schema: &Value) -> Vec<String> {
+    let definitions = schema
+        .get("definitions")
+        .or_else(|| schema.get("$defs"))
+        .and_then(Value::as_object);
+    let mut errors = Vec::new();
+    Validator { definitions }.validate(value, schema, "$", &mut errors);
+    errors
+}
+
+struct Validator<'a> {
+    definitions: Option<&'a Map<String, Value>>,
+}
+
+impl Validator<'_> {
+    fn validate(&self, value: &Value, schema: &Value, path: &str, errors: &mut Vec<String>) {
+        let Some(schema) = schema.as_object() else {
+            // `true` accepts anything, `false` nothing.
+            if schema == &Value::Bool(false) {
+                errors.push(format!("{path}: unexpected value"));
+            }
+            return;
+        };
+        if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+            let name = reference.rsplit('/').next().unwrap_or_default();
+            match self
+                .definitions
+                .and_then(|definitions| definitions.get(name))
+            {
+                Some(definition) => self.validate(value, definition, path, errors),
+                None => errors.push(format!("{path}: unknown schema reference {reference}")),
+            }
+        }
+        if let Some(types) = schema.get("type") {
+            let types: Vec<&str> = match types {
+                Value::Array(types) => types.iter().filter_map(Value::as_str).collect(),
+                types => types.as_str().into_iter().collect(),
+            };
+            if !types.iter().any(|t| is_type(value, t)) {
+                errors.push(format!(
+                    "{path}: expected {}, got {value}",
+                    types.join(" or ")
+                ));
+                return;
+            }
+        }
+        if let Some(variants) = schema
+            .get("enum")
+            .and_then(Value::as_array)
+            .filter(|variants| !variants.contains(value))
+        {
+            errors.push(format!(
+                "{path}: expected one of {}",
+                Value::from(variants.clone())
+            ));
+        }
+        if let Some(constant) = schema.get("const").filter(|constant| *constant != value) {
+            errors.push(format!("{path}: expected {constant}"));
+        }
+        if let Some(number) = value.as_f64() {
+            let minimum = schema.get("minimum").and_then(Value::as_f64);
+            let maximum = schema.get("maximum").and_then(Value::as_f64);
+            if minimum.is_some_and(|minimum| number < minimum)
+                || maximum.is_some_and(|maximum| number > maximum)
+            {
+                errors.push(format!("{path}: {number} is out of range"));
+            }
+        }
+        if let Some(object) = value.as_object() {
+            self.validate_object(object, schema, path, errors);
+        }
+        if let (Some(items), Some(array)) = (schema.get("items"), value.as_array()) {
+            for (index, item) in array.iter().enumerate() {
+                self.validate(item, items, &format!("{path}[{index}]"), errors);
+            }
+        }
+        for keyword in ["anyOf", "oneOf"] {
+            let Some(variants) = schema.get(keyword).and_then(Value::as_array) else {
+                continue;
+            };
+            let mut variant_errors = Vec::new();
+            let matches = variants.iter().any(|variant| {
+                let mut errors = Vec::new();
+                self.validate(value, variant, path, &mut errors);
+                variant_errors.extend(errors.first().cloned());
+                errors.is_empty()
+            });
+            if !matches {
+                errors.push(format!(
+                    "{path}: doesn't match any variant ({})",
+                    variant_errors.join(", ")
+                ));
+            }
+        }
+        if let Some(variants) = schema.get("allOf").and_then(Value::as_array) {
+            for variant in variants {
+                self.validate(value, variant, path, errors);
+            }
+        }
+    }
+
+    fn validate_object(
+        &self,
+        object: &Map<String, Value>,
+        schema: &Map<String, Value>,
+        path: &str,
+        errors: &mut Vec<String>,
+    ) {
+        let properties = schema.get("properties").and_then(Value::as_object);
+        for required in schema
+            .get("required")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+        {
+            if !object.contains_key(required) {
+                errors.push(format!("{path}: missing required property \"{required}\""));
+            }
+        }
+        for (key, value) in object {
+            let path = format!("{path}.{key}");
+            match properties.and_then(|properties| properties.get(key)) {
+                Some(property) => self.validate(value, property, &path, errors),
+                None => match schema.get("additionalProperties") {
+                    Some(Value::Bool(false)) => {
+                        errors.push(format!("{path}: unexpected property"));
+                    }
+                    Some(additional) => self.validate(value, additional, &path, errors),
+                    None => {}
+                },
+            }
+        }
+    }
+}
+
+fn is_type(value: &Value, name: &str) -> bool {
+    match name {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[allow(dead_code)]
+    #[derive(Debug, JsonSchema, Deserialize, PartialEq)]
+    struct Person {
+        name: String,
+        age: u8,
+        email: Option<String>,
+        role: Role,
+    }
+
+    #[derive(Debug, JsonSchema, Deserialize, PartialEq)]
+    enum Role {
+        Admin,
+        User,
+    }
+
+    #[test]
+    fn test_parse_typed() {
+        let schema = schema_of::<Person>();
+        let output = r#"Sure! Here is the person: {"name": "Alice", "age": 30, "role": "Admin"} Hope it helps."#;
+        let person: Person = parse_typed(output, &schema).unwrap();
+        assert_eq!(person.name, "Alice");
+        assert_eq!(person.role, Role::Admin);
+
+        let output =
+            "```json\n{\"name\": \"Bob\", \"age\": -1, \"email\": 3, \"role\": \"Guest\"}\n```";
+        let errors = parse_typed::<Person>(output, &schema).unwrap_err();
+        assert!(errors.contains("$.age"), "{errors}");
+        assert!(errors.contains("$.email"), "{errors}");
+        assert!(errors.contains("$.role"), "{errors}");
+
+        let errors = parse_typed::<Person>(r#"{"name": "Carol"}"#, &schema).unwrap_err();
+        assert!(
+            errors.contains("missing required property \"age\""),
+            "{errors}"
+        );
+        assert!(response_format(&schema).is_some());
+    }
+
+    /// A model answering with text around invalid JSON, then valid JSON once told its errors.
+    struct Sloppy;
+
+    struct Text(String);
+
+    impl crate::chat::ResponseContent for Text {
+        fn content(&self) -> String {
+            self.0.clone()
+        }
+    }
+
+    impl crate::chat::ResponseToolCalls for Text {
+        fn toolcalls(&self) -> Vec<crate::chat::ToolCall> {
+            Vec::new()
+        }
+    }
+
+    impl crate::chat::ResponseTokenUsage for Text {
+        fn token_usage(&self) -> crate::chat::TokenUsage {
+            crate::chat::TokenUsage {
+                tokens_cached: None,
+                prompt_tokens: 0,
+                completion_tokens: 0,
+                total_tokens: 0,
+            }
+        }
+    }
+
+    impl crate::chat::Completion for Sloppy {
+        type Response = Text;
+
+        async fn completion(
+            &mut self,
+            request: crate::chat::Request,
+        ) -> Result<Text, crate::chat::CompletionError> {
+            assert!(request.preamble.contains("JSON schema"));
+            Ok(Text(match request.prompt.contains("invalid") {
+                true => r#"{"name": "Alice", "age": 30, "role": "User"}"#.to_string(),
+                false => r#"Here you go: {"name": "Alice", "age": "thirty"}"#.to_string(),
+            }))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_prompt_typed_repairs_the_output() {
+        let agent = crate::agent::Agent::new("agent", Sloppy);
+        let person: Person = agent.prompt_typed("Who is Alice?").await.unwrap();
+        assert_eq!(person.age, 30);
+
+        let err = agent
+            .max_repairs(0)
+            .prompt_typed::<Person>("Who is Alice?")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("$.age"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn test_prompt_typed_stores_only_the_valid_answer() {
+        let agent = crate::agent::Agent::new("agent", Sloppy)
+            .memory(crate::memory::WindowBufferMemory::new(10));
+        let _: Person = agent.prompt_typed("Who is Alice?").await.unwrap();
+        let messages = agent.memory.as_ref().unwrap().read().await.messages();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].content, "Who is Alice?");
+        assert!(messages[1].content.contains(r#""age": 30"#));
+    }
+}
@@ -1,3 +1,5 @@
+use crate::budget::BudgetError;
+use crate::context::ContextError;
 use crate::mcp::MCPError;
 use crate::{
     agent::Agent,
@@ -84,4 +86,14 @@ pub enum TaskError {
     Unknown(String),
     #[error("MCP error: {0}")]
     MCPError(#[from] MCPError),
+    #[error("Exceeded the maximum number of tool calling iterations: {0}")]
+    MaxIterationsExceeded(usize),
+    #[error("Context error: {0}")]
+    ContextError(#[from] ContextError),
+    #[error("Budget exceeded: {0}")]
+    BudgetExceeded(#[from] BudgetError),
+    #[error("The task was cancelled")]
+    Cancelled,
+    #[error("The task did not finish before its deadline")]
+    DeadlineExceeded,
 }
@@ -0,0 +1,495 @@
+use crate::agent::Agent;
+use crate::chat::{Chat, ChatStream, Completion, Message};
+use crate::memory::Memory;
+use crate::task::TaskError;
+use crate::tool::{Tool, ToolDefinition, ToolError};
+use crate::{Ref, make_ref};
+use async_trait::async_trait;
+use schemars::{JsonSchema, schema_for};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::json;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::sync::{Arc, RwLock};
+
+/// The default maximum number of handoffs in a single turn of an [`AgentRegistry`].
+pub const DEFAULT_MAX_HANDOFFS: usize = 4;
+/// The name of the tool transferring the conversation to another agent of a registry.
+pub const HANDOFF_TOOL_NAME: &str = "transfer_to_agent";
+
+/// The typed input of an [`AgentTool`], turned into the prompt of the agent.
+///
+/// The default prompt is the pretty printed JSON of the input.
+pub trait TaskInput: Serialize + DeserializeOwned + JsonSchema + Send + Sync {
+    fn to_prompt(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+}
+
+/// The default input of an [`AgentTool`], a task described in natural language.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct AgentTask {
+    /// The task to complete, with all the context needed to complete it.
+    pub task: String,
+}
+
+impl TaskInput for AgentTask {
+    fn to_prompt(&self) -> String {
+        self.task.clone()
+    }
+}
+
+/// Exposes an agent as a tool, so a supervisor agent can delegate tasks to it.
+///
+/// The model calls the tool with an `I`, the agent is prompted with
+/// [`TaskInput::to_prompt`] and its answer is the tool output.
+pub struct AgentTool<I: TaskInput = AgentTask> {
+    name: String,
+    description: String,
+    agent: Arc<dyn Chat>,
+    input: PhantomData<fn(I)>,
+}
+
+impl<I: TaskInput> AgentTool<I> {
+    /// Creates a tool prompting the agent.
+    ///
+    /// The name must be a valid tool name, e.g. `billing_agent`.
+    pub fn new(
+        name: impl ToString,
+        description: impl ToString,
+        agent: impl Chat + 'static,
+    ) -> Self {
+        Self::from_shared(name, description, Arc::new(agent))
+    }
+
+    /// Creates a tool prompting an agent shared with other tools or callers.
+    pub fn from_shared(
+        name: impl ToString,
+        description: impl ToString,
+        agent: Arc<dyn Chat>,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            description: description.to_string(),
+            agent,
+            input: PhantomData,
+        }
+    }
+}
+
+#[async_trait]
+impl<I: TaskInput> Tool for AgentTool<I> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition {
+            name: self.name.clone(),
+            description: self.description.clone(),
+            parameters: json!(schema_for!(I)),
+        }
+    }
+
+    async fn run(&self, input: &str) -> Result<String, ToolError> {
+        let input: I = serde_json::from_str(input)?;
+        self.agent
+            .prompt(&input.to_prompt())
+            .await
+            .map_err(|err| ToolError::NormalError(Box::new(err)))
+    }
+}
+
+/// A transfer of the conversation from an agent to another one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Handoff {
+    /// The name of the agent handing off the conversation.
+    pub from: String,
+    /// The name of the agent taking over the conversation.
+    pub to: String,
+    /// The note of the agent handing off, e.g. a summary of the request.
+    pub message: String,
+}
+
+impl Handoff {
+    /// Returns the prompt of the agent taking over the conversation.
+    fn prompt(&self) -> String {
+        format!(
+            "The agent `{}` transferred the conversation to you: {}",
+            self.from, self.message
+        )
+    }
+}
+
+#[derive(Deserialize)]
+struct HandoffInput {
+    agent: String,
+    message: String,
+}
+
+/// The agents of a registry and their descriptions, by name.
+type Directory = Arc<RwLock<Vec<(String, String)>>>;
+
+/// The tool added to the agents of a registry to hand off the conversation.
+struct HandoffTool {
+    from: String,
+    directory: Directory,
+    pending: Ref<Option<Handoff>>,
+}
+
+#[async_trait]
+impl Tool for HandoffTool {
+    fn name(&self) -> &str {
+        HANDOFF_TOOL_NAME
+    }
+
+    fn description(&self) -> &str {
+        "Transfers the conversation to another agent"
+    }
+
+    fn definition(&self) -> ToolDefinition {
+        let directory = self.directory.read().unwrap_or_else(|err| err.into_inner());
+        let others: Vec<&(String, String)> = directory
+            .iter()
+            .filter(|(name, _)| *name != self.from)
+            .collect();
+        let agents: Vec<String> = others
+            .iter()
+            .map(|(name, description)| format!("- {name}: {description}"))
+            .collect();
+        ToolDefinition {
+            name: HANDOFF_TOOL_NAME.to_string(),
+            description: format!(
+                "Transfers the conversation to the agent better suited to answer it:\n{}",
+                agents.join("\n")
+            ),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "agent": {
+                        "type": "string",
+                        "enum": others.iter().map(|(name, _)| name).collect::<Vec<_>>(),
+                        "description": "The name of the agent taking over the conversation."
+                    },
+                    "message": {
+                        "type": "string",
+                        "description": "What the agent taking over needs to know about the conversation."
+                    }
+                },
+                "required": ["agent", "message"]
+            }),
+        }
+    }
+
+    /// Ends the turn of the agent, the registry then prompts the agent taking over.
+    fn return_direct(&self) -> bool {
+        true
+    }
+
+    async fn run(&self, input: &str) -> Result<String, ToolError> {
+        let input: HandoffInput = serde_json::from_str(input)?;
+        let known = self
+            .directory
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .iter()
+            .any(|(name, _)| *name == input.agent && *name != self.from);
+        if !known {
+            return Err(ToolError::Unknown(format!(
+                "No agent named `{}` to transfer to",
+                input.agent
+            )));
+        }
+        let output = format!("Transferred the conversation to `{}`.", input.agent);
+        *self.pending.write().await = Some(Handoff {
+            from: self.from.clone(),
+            to: input.agent,
+            message: input.message,
+        });
+        Ok(output)
+    }
+}
+
+/// Named agents sharing a conversation and handing it off to each other.
+///
+/// The registered agents share the memory of the registry and can call the
+/// [`HANDOFF_TOOL_NAME`] tool to transfer the conversation to another agent, which then
+/// answers in the same turn. The next prompts go to the agent holding the conversation,
+/// initially the first registered one. A registry holds a single conversation at a time.
+pub struct AgentRegistry {
+    agents: HashMap<String, Arc<dyn Chat>>,
+    directory: Directory,
+    memory: Ref<dyn Memory>,
+    active: Ref<Option<String>>,
+    pending: Ref<Option<Handoff>>,
+    max_handoffs: usize,
+}
+
+impl AgentRegistry {
+    /// Creates an empty registry storing the conversation into the memory.
+    pub fn new(memory: impl Memory + 'static) -> Self {
+        Self::with_shared_memory(make_ref(memory))
+    }
+
+    /// Creates an empty registry storing the conversation into a shared memory.
+    pub fn with_shared_memory(memory: Ref<dyn Memory>) -> Self {
+        Self {
+            agents: HashMap::new(),
+            directory: Arc::new(RwLock::new(Vec::new())),
+            memory,
+            active: make_ref(None),
+            pending: make_ref(None),
+            max_handoffs: DEFAULT_MAX_HANDOFFS,
+        }
+    }
+
+    /// Registers an agent under its name, described to the other agents so they know when
+    /// to hand off the conversation to it.
+    ///
+    /// The agent is given the memory of the registry and the handoff tool.
+    pub async fn agent<M>(mut self, agent: Agent<M>, description: impl ToString) -> Self
+    where
+        M: Completion + Send + Sync + 'static,
+    {
+        let name = agent.name.clone();
+        let agent = agent
+            .shared_memory(self.memory.clone())
+            .tool(HandoffTool {
+                from: name.clone(),
+                directory: self.directory.clone(),
+                pending: self.pending.clone(),
+            })
+            .await;
+        self.directory
+            .write()
+            .unwrap_or_else(|err| err.into_inner())
+            .push((name.clone(), description.to_string()));
+        self.active
+            .write()
+            .await
+            .get_or_insert_with(|| name.clone());
+        self.agents.insert(name, Arc::new(agent));
+        self
+    }
+
+    /// Sets the maximum number of handoffs in a single turn.
+    pub fn max_handoffs(mut self, max_handoffs: usize) -> Self {
+        self.max_handoffs = max_handoffs;
+        self
+    }
+
+    /// Returns the memory holding the conversation.
+    pub fn memory(&self) -> Ref<dyn Memory> {
+        self.memory.clone()
+    }
+
+    /// Returns the name of the agent holding the conversation.
+    pub async fn active(&self) -> Option<String> {
+        self.active.read().await.clone()
+    }
+
+    /// Transfers the conversation to the agent, without a handoff note.
+    pub async fn set_active(&self, name: &str) -> Result<(), TaskError> {
+        self.get(name)?;
+        *self.active.write().await = Some(name.to_string());
+        Ok(())
+    }
+
+    /// Returns the agent registered under the name.
+    pub fn get(&self, name: &str) -> Result<Arc<dyn Chat>, TaskError> {
+        self.agents
+            .get(name)
+            .cloned()
+            .ok_or_else(|| TaskError::ExecutionError(format!("No agent named `{name}`")))
+    }
+
+    /// Prompts the agent holding the conversation, then the agents it is handed off to.
+    async fn run(&self, prompt: &str, history: Option<Vec<Message>>) -> Result<String, TaskError> {
+        let Some(mut name) = self.active().await else {
+            return Err(TaskError::ExecutionError(
+                "No agent is registered".to_string(),
+            ));
+        };
+        // Drop a handoff left by a failed turn.
+        self.pending.write().await.take();
+        let mut output = match history {
+            Some(history) => self.get(&name)?.chat(prompt, history).await?,
+            None => self.get(&name)?.prompt(prompt).await?,
+        };
+        let mut handoffs = 0;
+        while let Some(handoff) = self.pending.write().await.take() {
+            if handoffs == self.max_handoffs {
+                return Err(TaskError::ExecutionError(format!(
+                    "The conversation was handed off more than {} times",
+                    self.max_handoffs
+                )));
+            }
+            handoffs += 1;
+            name = handoff.to.clone();
+            *self.active.write().await = Some(name.clone());
+            output = self.get(&name)?.prompt(&handoff.prompt()).await?;
+        }
+        Ok(output)
+    }
+}
+
+#[async_trait]
+impl Chat for AgentRegistry {
+    /// Processes a prompt using the agent holding the conversation.
+    async fn prompt(&self, prompt: &str) -> Result<String, TaskError> {
+        self.run(prompt, None).await
+    }
+
+    /// Processes a prompt and history using the agent holding the conversation.
+    async fn chat(&self, prompt: &str, history: Vec<Message>) -> Result<String, TaskError> {
+        self.run(prompt, Some(history)).await
+    }
+
+    /// Processes a prompt using the agent holding the conversation and yields the final
+    /// answer, after the handoffs, as a single chunk.
+    async fn prompt_stream<'a>(&'a self, prompt: &'a str) -> Result<ChatStream<'a>, TaskError> {
+        let response = self.prompt(prompt).await?;
+        Ok(Box::pin(futures::stream::iter([Ok(
+            crate::chat::CompletionChunk::Text(response),
+        )])))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cancel::Cancellation;
+    use crate::chat::{
+        CallFunction, CompletionError, Request, ResponseContent, ResponseTokenUsage,
+        ResponseToolCalls, TokenUsage, ToolCall,
+    };
+    use crate::memory::WindowBufferMemory;
+
+    /// A model calling the tool named in the preamble with the arguments in the prompt,
+    /// then answering with the tool result, or echoing the prompt without a tool.
+    struct Delegating;
+
+    struct Reply(String, Vec<ToolCall>);
+
+    impl ResponseContent for Reply {
+        fn content(&self) -> String {
+            self.0.clone()
+        }
+    }
+
+    impl ResponseToolCalls for Reply {
+        fn toolcalls(&self) -> Vec<ToolCall> {
+            self.1.clone()
+        }
+    }
+
+    impl ResponseTokenUsage for Reply {
+        fn token_usage(&self) -> TokenUsage {
+            TokenUsage {
+                tokens_cached: None,
+                prompt_tokens: 0,
+                completion_tokens: 0,
+                total_tokens: 0,
+            }
+        }
+    }
+
+    impl Completion for Delegating {
+        type Response = Reply;
+
+        async fn completion(&mut self, request: Request) -> Result<Reply, CompletionError> {
+            if let Some(result) = request.tool_messages.iter().find(|m| m.role == "tool") {
+                return Ok(Reply(format!("done: {}", result.content), Vec::new()));
+            }
+            if request.preamble.is_empty() {
+                return Ok(Reply(format!("echo: {}", request.prompt), Vec::new()));
+            }
+            let call = ToolCall {
+                id: "call-1".to_string(),
+                r#type: "function".to_string(),
+                function: CallFunction {
+                    name: request.preamble.clone(),
+                    arguments: request.prompt.clone(),
+                },
+            };
+            Ok(Reply(String::new(), vec![call]))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_agent_tool() {
+        let specialist = Agent::new("specialist", Delegating);
+        let supervisor = Agent::new("supervisor", Delegating)
+            .preamble("specialist")
+            .tool(AgentTool::<AgentTask>::new(
+                "specialist",
+                "A specialist",
+                specialist,
+            ))
+            .await;
+        let output = supervisor
+            .prompt(r#"{"task": "sum 1 and 2"}"#)
+            .await
+            .unwrap();
+        assert_eq!(output, "done: echo: sum 1 and 2");
+    }
+
+    #[tokio::test]
+    async fn test_handoff_transfers_the_conversation() {
+        let registry = AgentRegistry::new(WindowBufferMemory::new(20))
+            .agent(
+                Agent::new("triage", Delegating).preamble(HANDOFF_TOOL_NAME),
+                "Routes the requests",
+            )
+            .await
+            .agent(
+                Agent::new("billing", Delegating),
+                "Answers billing questions",
+            )
+            .await;
+        let output = registry
+            .prompt(r#"{"agent": "billing", "message": "refund order 42"}"#)
+            .await
+            .unwrap();
+        assert_eq!(
+            output,
+            "echo: The agent `triage` transferred the conversation to you: refund order 42"
+        );
+        assert_eq!(registry.active().await.as_deref(), Some("billing"));
+        // The billing agent sees the whole conversation.
+        let messages = registry.memory().read().await.messages();
+        assert_eq!(messages.len(), 5);
+
+        // Unknown agents are reported back to the model.
+        registry.set_active("triage").await.unwrap();
+        let output = registry
+            .prompt(r#"{"agent": "sales", "message": "upgrade"}"#)
+            .await
+            .unwrap();
+        assert!(output.contains("No agent named `sales`"), "{output}");
+        assert_eq!(registry.active().await.as_deref(), Some("triage"));
+    }
+
+    #[tokio::test]
+    async fn test_agent_tool_is_cancelled_with_the_turn() {
+        let specialist = Agent::new("specialist", Delegating);
+        let tool = AgentTool::<AgentTask>::new("specialist", "A specialist", specialist);
+        let cancellation = Cancellation::new();
+        cancellation.cancel();
+        let err = cancellation
+            .scope(tool.run(r#"{"task": "sum 1 and 2"}"#))
+            .await
+            .unwrap_err();
+        let ToolError::NormalError(err) = err else {
+            panic!("unexpected error: {err}");
+        };
+        assert!(
+            matches!(err.downcast_ref::<TaskError>(), Some(TaskError::Cancelled)),
+            "{err}"
+        );
+    }
+}
@@ -2,6 +2,7 @@ use async_trait::async_trait;
 use schemars::{JsonSchema, schema::RootSchema, schema_for};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::time::Duration;
 
 pub use alith_interface::requests::completion::{ToolChoice, ToolDefinition};
 
@@ -25,6 +26,28 @@ pub trait Tool: Send + Sync {
 
     fn definition(&self) -> ToolDefinition;
 
+    /// Whether the tool output should be returned to the caller as the final answer
+    /// instead of being sent back to the model.
+    fn return_direct(&self) -> bool {
+        false
+    }
+
+    /// The maximum duration of a call to the tool, `None` to use the timeout of the executor.
+    fn timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    /// How the failed calls to the tool are retried, `None` to not retry them.
+    fn retry_policy(&self) -> Option<RetryPolicy> {
+        None
+    }
+
+    /// Whether the calls to the tool must be approved before running, e.g. because the
+    /// tool moves money or sends messages. See [`crate::approval::Approver`].
+    fn requires_approval(&self) -> bool {
+        false
+    }
+
     fn validate_input(&self, input: &str) -> Result<(), ToolError> {
         if input.trim().is_empty() {
             Err(ToolError::InvalidInput)
@@ -69,6 +92,28 @@ pub trait StructureTool: Send + Sync {
         }
     }
 
+    /// Whether the tool output should be returned to the caller as the final answer
+    /// instead of being sent back to the model.
+    fn return_direct(&self) -> bool {
+        false
+    }
+
+    /// The maximum duration of a call to the tool, `None` to use the timeout of the executor.
+    fn timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    /// How the failed calls to the tool are retried, `None` to not retry them.
+    fn retry_policy(&self) -> Option<RetryPolicy> {
+        None
+    }
+
+    /// Whether the calls to the tool must be approved before running, e.g. because the
+    /// tool moves money or sends messages. See [`crate::approval::Approver`].
+    fn requires_approval(&self) -> bool {
+        false
+    }
+
     async fn run_with_args(&self, input: Self::Input) -> Result<Self::Output, ToolError>;
 
     async fn run(&self, input: &str) -> Result<String, ToolError> {
@@ -104,6 +149,22 @@ impl<T: StructureTool> Tool for T {
         self.definition()
     }
 
+    fn return_direct(&self) -> bool {
+        self.return_direct()
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.timeout()
+    }
+
+    fn retry_policy(&self) -> Option<RetryPolicy> {
+        self.retry_policy()
+    }
+
+    fn requires_approval(&self) -> bool {
+        self.requires_approval()
+    }
+
     async fn run(&self, input: &str) -> Result<String, ToolError> {
         match serde_json::from_str(input) {
             Ok(input) => {
@@ -115,6 +176,41 @@ impl<T: StructureTool> Tool for T {
     }
 }
 
+/// The default delay before the first retry of a failed tool call.
+pub const DEFAULT_RETRY_BACKOFF: Duration = Duration::from_millis(200);
+
+/// Retries the calls to a tool failing with a transient error, see
+/// [`ToolError::is_transient`], doubling the delay after each attempt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// The maximum number of retries after the first attempt.
+    pub max_retries: usize,
+    /// The delay before the first retry.
+    pub backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Creates a policy retrying up to `max_retries` times.
+    pub fn new(max_retries: usize) -> Self {
+        Self {
+            max_retries,
+            backoff: DEFAULT_RETRY_BACKOFF,
+        }
+    }
+
+    /// Sets the delay before the first retry.
+    pub fn backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Returns the delay before the retry following the given failed attempt, from 0.
+    pub fn delay(&self, attempt: usize) -> Duration {
+        self.backoff
+            .saturating_mul(2u32.saturating_pow(attempt.try_into().unwrap_or(u32::MAX)))
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 #[error("Tool error")]
 pub enum ToolError {
@@ -130,6 +226,19 @@ pub enum ToolError {
     Unknown(String),
     #[error("JsonError: {0}")]
     JsonError(#[from] serde_json::Error),
+    #[error("The tool call timed out after {0:?}")]
+    Timeout(Duration),
+}
+
+impl ToolError {
+    /// Whether a call failing with this error may succeed when retried, i.e. the tool
+    /// failed or timed out rather than being called with invalid arguments.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            ToolError::NormalError(_) | ToolError::Unknown(_) | ToolError::Timeout(_)
+        )
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,234 @@
+use crate::chat::{TokenUsage, ToolCall};
+use crate::context::ContextReport;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// The price of a model, in a currency unit per million tokens.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModelPrice {
+    /// The price of a million prompt tokens.
+    pub prompt: f64,
+    /// The price of a million completion tokens.
+    pub completion: f64,
+    /// The price of a million cached prompt tokens, the prompt price when not set.
+    #[serde(default)]
+    pub cached_prompt: Option<f64>,
+}
+
+impl ModelPrice {
+    /// Creates a price from the prices of a million prompt and completion tokens.
+    pub fn new(prompt: f64, completion: f64) -> Self {
+        Self {
+            prompt,
+            completion,
+            cached_prompt: None,
+        }
+    }
+
+    /// Sets the price of a million cached prompt tokens.
+    pub fn cached_prompt(mut self, cached_prompt: f64) -> Self {
+        self.cached_prompt = Some(cached_prompt);
+        self
+    }
+
+    /// Returns the cost of the token usage.
+    pub fn cost(&self, usage: &TokenUsage) -> f64 {
+        let cached = usage.tokens_cached.unwrap_or(0).min(usage.prompt_tokens) as f64;
+        let uncached = usage.prompt_tokens as f64 - cached;
+        (uncached * self.prompt
+            + cached * self.cached_prompt.unwrap_or(self.prompt)
+            + usage.completion_tokens as f64 * self.completion)
+            / 1_000_000.0
+    }
+}
+
+/// The prices of the models, by model name.
+///
+/// A model without its own price uses the price of the longest model name it starts
+/// with, e.g. `gpt-4o-2024-08-06` uses the price of `gpt-4o`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct PriceTable {
+    prices: HashMap<String, ModelPrice>,
+}
+
+impl PriceTable {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the price of a model.
+    pub fn price(mut self, model: impl ToString, price: ModelPrice) -> Self {
+        self.insert(model, price);
+        self
+    }
+
+    /// Sets the price of a model.
+    pub fn insert(&mut self, model: impl ToString, price: ModelPrice) {
+        self.prices.insert(model.to_string(), price);
+    }
+
+    /// Returns the price of a model.
+    pub fn get(&self, model: &str) -> Option<&ModelPrice> {
+        self.prices.get(model).or_else(|| {
+            self.prices
+                .iter()
+                .filter(|(name, _)| model.starts_with(name.as_str()))
+                .max_by_key(|(name, _)| name.len())
+                .map(|(_, price)| price)
+        })
+    }
+
+    /// Returns the cost of the token usage of a model, `None` when its price is unknown.
+    pub fn cost(&self, model: &str, usage: &TokenUsage) -> Option<f64> {
+        self.get(model).map(|price| price.cost(usage))
+    }
+}
+
+/// The usage of a single model call.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CallUsage {
+    /// The tokens used by the call, as reported by the model.
+    pub usage: TokenUsage,
+    /// The duration of the call.
+    pub latency: Duration,
+    /// The estimated cost of the call, `None` when the price of the model is unknown.
+    pub cost: Option<f64>,
+}
+
+/// The answer of an agent to a prompt, with the tool calls made and the usage of the
+/// model calls.
+#[derive(Debug, Clone, Default)]
+pub struct AgentResponse {
+    /// The final answer.
+    pub content: String,
+    /// The tool calls requested by the model, in order.
+    pub tool_calls: Vec<ToolCall>,
+    /// The usage of each model call, in order.
+    pub calls: Vec<CallUsage>,
+    /// The total tokens used by the model calls.
+    pub usage: TokenUsage,
+    /// The duration of the whole turn, including the tool calls.
+    pub latency: Duration,
+    /// The estimated cost of the turn, `None` when the price of the model is unknown.
+    pub cost: Option<f64>,
+    /// What was trimmed from the request to fit it into the context window, `None`
+    /// without a context policy or when the request tokens can't be counted.
+    pub context: Option<ContextReport>,
+}
+
+impl AgentResponse {
+    /// Adds the usage of a model call.
+    pub(crate) fn add_call(&mut self, call: CallUsage) {
+        add_usage(&mut self.usage, &call.usage);
+        self.cost = match (self.calls.is_empty(), self.cost, call.cost) {
+            (true, _, cost) => cost,
+            (false, Some(total), Some(cost)) => Some(total + cost),
+            _ => None,
+        };
+        self.calls.push(call);
+    }
+}
+
+/// Accumulated usage counters, e.g. of an agent or a session.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct UsageStats {
+    /// The number of turns, i.e. prompts answered or failed.
+    pub turns: u64,
+    /// The number of model calls.
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub cached_tokens: u64,
+    pub completion_tokens: u64,
+    pub total_tokens: u64,
+    /// The estimated cost of the model calls with a known price.
+    pub cost: f64,
+    /// The total duration of the model calls.
+    pub latency: Duration,
+}
+
+impl UsageStats {
+    /// Adds the usage of a model call.
+    pub fn record_call(&mut self, call: &CallUsage) {
+        self.requests += 1;
+        self.prompt_tokens += call.usage.prompt_tokens as u64;
+        self.cached_tokens += call.usage.tokens_cached.unwrap_or(0) as u64;
+        self.completion_tokens += call.usage.completion_tokens as u64;
+        self.total_tokens += call.usage.total_tokens as u64;
+        self.cost += call.cost.unwrap_or(0.0);
+        self.latency += call.latency;
+    }
+}
+
+/// A shared handle accumulating [`UsageStats`], updated after each model call.
+///
+/// Each agent has its own tracker, see [`crate::agent::Agent::usage`], and trackers can be
+/// shared by several agents to count the usage of a session or a user, see
+/// [`crate::agent::Agent::usage_tracker`].
+#[derive(Debug, Clone, Default)]
+pub struct UsageTracker {
+    stats: Arc<Mutex<UsageStats>>,
+}
+
+impl UsageTracker {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the accumulated usage.
+    pub fn stats(&self) -> UsageStats {
+        self.lock().clone()
+    }
+
+    /// Resets the counters, returning the accumulated usage.
+    pub fn reset(&self) -> UsageStats {
+        std::mem::take(&mut *self.lock())
+    }
+
+    pub(crate) fn record_call(&self, call: &CallUsage) {
+        self.lock().record_call(call);
+    }
+
+    pub(crate) fn record_turn(&self) {
+        self.lock().turns += 1;
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, UsageStats> {
+        self.stats.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+fn add_usage(total: &mut TokenUsage, usage: &TokenUsage) {
+    total.prompt_tokens += usage.prompt_tokens;
+    total.completion_tokens += usage.completion_tokens;
+    total.total_tokens += usage.total_tokens;
+    total.tokens_cached = match (total.tokens_cached, usage.tokens_cached) {
+        (None, None) => None,
+        (total, cached) => Some(total.unwrap_or(0) + cached.unwrap_or(0)),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_price_table() {
+        let prices = PriceTable::new()
+            .price("gpt-4o", ModelPrice::new(2.5, 10.0).cached_prompt(1.25))
+            .price("gpt-4o-mini", ModelPrice::new(0.15, 0.6));
+        let usage = TokenUsage {
+            tokens_cached: Some(200_000),
+            prompt_tokens: 1_000_000,
+            completion_tokens: 100_000,
+            total_tokens: 1_100_000,
+        };
+        assert_eq!(prices.cost("gpt-4o-2024-08-06", &usage), Some(3.25));
+        assert_eq!(prices.get("gpt-4o-mini-2024-07-18").unwrap().prompt, 0.15);
+        assert_eq!(prices.cost("llama", &usage), None);
+    }
+}
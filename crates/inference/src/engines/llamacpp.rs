@@ -1,7 +1,7 @@
 use std::{
     num::NonZeroU32,
     path::Path,
-    sync::{Mutex, OnceLock},
+    sync::{Arc, Mutex, OnceLock},
 };
 
 use crate::errors::InferenceError;
@@ -39,7 +39,9 @@ unsafe impl Send for ContextWrapper {} // LlamaContext has a NonNull which is !S
 unsafe impl Sync for ContextWrapper {} // LlamaContext has a NonNull which is !Sync
 
 pub struct LlamaEngine {
-    model: LocalLLMModel,
+    model: Arc<LocalLLMModel>,
+    /// The index of the llama.cpp context used for completions.
+    context: usize,
 }
 
 impl LlamaEngine {
@@ -64,12 +66,26 @@ impl LlamaEngine {
             .set(backend)
             .map_err(|err| InferenceError::General(err.to_string()))?;
         Ok(LlamaEngine {
-            model: GgufLoader::default()
-                .local_quant_file_path(model_path.as_ref())
-                .load()
-                .map_err(|err| InferenceError::General(err.to_string()))?,
+            model: Arc::new(
+                GgufLoader::default()
+                    .local_quant_file_path(model_path.as_ref())
+                    .load()
+                    .map_err(|err| InferenceError::General(err.to_string()))?,
+            ),
+            context: 0,
         })
     }
+
+    /// Returns one engine per llama.cpp context, they share the model weights and can run
+    /// completions in parallel, e.g. as the replicas of an inference server.
+    pub fn contexts(self) -> Vec<LlamaEngine> {
+        (0..NUM_CONTEXTS)
+            .map(|context| LlamaEngine {
+                model: self.model.clone(),
+                context,
+            })
+            .collect()
+    }
 }
 
 fn load_model<P: AsRef<Path>>(
@@ -95,7 +111,7 @@ impl Completion for LlamaEngine {
         request: CompletionRequest,
     ) -> Result<Self::Response, CompletionError> {
         let model = LLAMA_MODEL.get().unwrap();
-        let mut llama_context = LLAMA_CONTEXTS[0].get().unwrap().lock().unwrap();
+        let mut llama_context = LLAMA_CONTEXTS[self.context].get().unwrap().lock().unwrap();
         let input = self
             .model
             .chat_template
@@ -3,7 +3,7 @@ use std::{num::NonZeroUsize, path::Path};
 use anyhow::Result;
 
 use alith_core::{
-    chat::{Completion, CompletionError, Request as CompletionRequest},
+    chat::{Completion, CompletionError, Message, Request as CompletionRequest},
     client::CompletionResponse,
     interface::requests::completion::{
         CompletionFinishReason, GenerationSettings, TimingUsage, TokenUsage,
@@ -11,11 +11,11 @@ use alith_core::{
 };
 use anyhow::bail;
 use mistralrs::{
-    AutoDeviceMapParams, DefaultSchedulerMethod, DeviceMapSetting, Function, GGUFLoaderBuilder,
-    GGUFSpecificConfig, MistralRsBuilder, Model, ModelDType, NormalLoaderBuilder,
-    NormalSpecificConfig, PagedAttentionConfig, RequestBuilder, SchedulerConfig, TextMessageRole,
-    TokenSource, Tool, ToolChoice, ToolType, Topology, best_device, initialize_logging,
-    paged_attn_supported,
+    AutoDeviceMapParams, CalledFunction, DefaultSchedulerMethod, DeviceMapSetting, Function,
+    GGUFLoaderBuilder, GGUFSpecificConfig, MistralRsBuilder, Model, ModelDType,
+    NormalLoaderBuilder, NormalSpecificConfig, PagedAttentionConfig, RequestBuilder,
+    SchedulerConfig, TextMessageRole, TokenSource, Tool, ToolCallResponse, ToolCallType,
+    ToolChoice, ToolType, Topology, best_device, initialize_logging, paged_attn_supported,
 };
 
 pub struct MistralRsEngine {
@@ -49,18 +49,13 @@ impl Completion for MistralRsEngine {
             messages = messages.set_sampler_topp(top_p as f64);
         }
         for m in &request.history {
-            match m.role.as_str() {
-                "system" => {
-                    messages = messages.add_message(TextMessageRole::System, m.content.clone())
-                }
-                "user" => messages = messages.add_message(TextMessageRole::User, m.content.clone()),
-                "assistant" => {
-                    messages = messages.add_message(TextMessageRole::Assistant, m.content.clone())
-                }
-                _ => continue, // Just skip unknown roles
-            };
+            messages = add_message(messages, m);
         }
         messages = messages.add_message(TextMessageRole::User, request.effective_prompt());
+        // Add the tool calls and tool results of the current turn
+        for m in &request.tool_messages {
+            messages = add_message(messages, m);
+        }
         let mut tools = vec![];
         for tool in &request.tools {
             tools.push(Tool {
@@ -132,6 +127,38 @@ impl Completion for MistralRsEngine {
     }
 }
 
+/// Adds a history or tool message, keeping the tool calls of assistant messages and the
+/// tool call ID of tool messages so that the model can link each result to its call.
+fn add_message(messages: RequestBuilder, m: &Message) -> RequestBuilder {
+    match m.role.as_str() {
+        "system" => messages.add_message(TextMessageRole::System, m.content.clone()),
+        "user" => messages.add_message(TextMessageRole::User, m.content.clone()),
+        "assistant" if !m.tool_calls.is_empty() => messages.add_message_with_tool_call(
+            TextMessageRole::Assistant,
+            m.content.clone(),
+            m.tool_calls
+                .iter()
+                .enumerate()
+                .map(|(index, call)| ToolCallResponse {
+                    index,
+                    id: call.id.clone(),
+                    tp: ToolCallType::Function,
+                    function: CalledFunction {
+                        name: call.function.name.clone(),
+                        arguments: call.function.arguments.clone(),
+                    },
+                })
+                .collect(),
+        ),
+        "assistant" => messages.add_message(TextMessageRole::Assistant, m.content.clone()),
+        "tool" => match &m.tool_call_id {
+            Some(tool_call_id) => messages.add_tool_message(m.content.clone(), tool_call_id),
+            None => messages,
+        },
+        _ => messages, // Just skip unknown roles
+    }
+}
+
 /// Configure a text GGUF model with the various parameters for loading, running, and other inference behaviors.
 pub struct GgufModelBuilder {
     // Loading model
@@ -14,6 +14,8 @@ pub enum InferenceError {
     MistralRs(#[from] mistralrs::MistralRsError),
     #[error("Model load error: {0}")]
     ModelLoad(String),
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
     #[error("General error: {0}")]
     General(String),
     /// JSON error (e.g.: serialization, deserialization, etc.)
@@ -1,50 +1,242 @@
 use std::sync::Arc;
 
 use alith_core::chat::{
-    Completion, Message, Request, ResponseContent, ResponseTokenUsage, ResponseToolCalls,
+    Completion, CompletionChunk, Message, Request, ResponseContent, ResponseStream,
+    ResponseTokenUsage, ResponseToolCalls,
 };
+use alith_core::embeddings::{Embeddings, EmbeddingsData, EmbeddingsError};
 use alith_core::interface::llms::api::openai::completion::{
-    ChatChoice, ChatCompletionResponseMessage, CompletionRequestMessage, CompletionUsage,
-    FinishReason, OpenAICompletionRequest, OpenAICompletionResponse, Role,
+    ChatChoice, ChatChunkChoice, ChatCompletionResponseMessage, ChatCompletionStreamDelta,
+    CompletionRequestMessage, CompletionUsage, FinishReason, FunctionStreamDelta,
+    OpenAICompletionChunk, OpenAICompletionRequest, OpenAICompletionResponse, Role, StreamOptions,
+    ToolCallStreamDelta,
 };
 use alith_core::interface::requests::completion::tool::{Function, ToolCall};
 use alith_core::tool::ToolDefinition;
 use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use base64::{Engine, prelude::BASE64_STANDARD};
 use bytes::Bytes;
 use chrono::{Timelike, Utc};
-use http::{Method, Response, StatusCode};
-use http_body_util::{BodyExt, Full, combinators::BoxBody};
-use hyper::{body::Incoming, service::service_fn};
+use futures_util::{StreamExt, future::try_join_all};
+use http::{HeaderValue, Method, Response, StatusCode, header::RETRY_AFTER};
+use http_body_util::{BodyExt, Full, StreamBody, combinators::BoxBody};
+use hyper::{
+    body::{Frame, Incoming},
+    service::service_fn,
+};
 use hyper_util::rt::{TokioExecutor, TokioIo};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
 use serde_json::{Value, json};
 use std::convert::Infallible;
+use std::future::Future;
+use std::time::Duration;
 use tokio::{
     net::TcpListener,
-    sync::{RwLock, oneshot},
+    sync::{mpsc, oneshot},
 };
 use tokio_graceful::Shutdown;
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::errors::InferenceError;
+
+pub mod auth;
+pub mod cors;
+pub mod scheduler;
+
+#[cfg(feature = "lazai")]
+pub use auth::LazAIValidator;
+pub use auth::{ApiKeys, AuthError, Authenticator};
+pub use cors::Cors;
+pub use scheduler::SchedulerError;
+
+use scheduler::Scheduler;
 
 pub const DEFAULT_ADDR: &str = "127.0.0.1:8000";
+pub const DEFAULT_MODEL_ID: &str = "alith";
+pub const DEFAULT_QUEUE_SIZE: usize = 64;
+pub const V1_MODELS: &str = "/v1/models";
 pub const V1_CHAT_COMPLETIONS: &str = "/v1/chat/completions";
+pub const V1_COMPLETIONS: &str = "/v1/completions";
+pub const V1_EMBEDDINGS: &str = "/v1/embeddings";
 
 /// Run an inference server with the given model and address.
 pub async fn run(
     addr: Option<String>,
     model: impl Completion + Send + Sync + 'static,
 ) -> Result<()> {
-    let addr = addr.unwrap_or(DEFAULT_ADDR.to_string());
-    let server = Arc::new(Server {
-        model: RwLock::new(model),
-    });
-    let listener = TcpListener::bind(&addr).await?;
-    let stop_server = server.run(listener).await?;
-    shutdown_signal().await?;
-    let _ = stop_server.send(());
-    Ok(())
+    let mut builder = ServerBuilder::new(model);
+    if let Some(addr) = addr {
+        builder = builder.addr(addr);
+    }
+    builder.run().await
+}
+
+/// Builds and runs an OpenAI compatible inference server.
+///
+/// ```no_run
+/// # use alith_core::{chat::Completion, llm::EmbeddingsModel};
+/// use alith_inference::serve::ServerBuilder;
+///
+/// # async fn serve(
+/// #     model: impl Completion + Send + Sync + 'static,
+/// #     embeddings: EmbeddingsModel,
+/// # ) -> anyhow::Result<()> {///
+/// ServerBuilder::new(model)
+///     .addr("0.0.0.0:8000")
+///     .model_id("qwen2.5-1.5b-instruct")
+///     .embeddings(embeddings)
+///     .run()
+///     .await
+/// # }
+/// ```
+pub struct ServerBuilder<M: Completion + Send + Sync + 'static> {
+    addr: String,
+    model_id: String,
+    models: Vec<M>,
+    concurrency: Option<usize>,
+    queue_size: usize,
+    timeout: Option<Duration>,
+    embeddings: Option<Box<dyn DynEmbeddings>>,
+    authenticators: Vec<Box<dyn Authenticator>>,
+    cors: Cors,
+}
+
+impl<M: Completion + Send + Sync + 'static> ServerBuilder<M> {
+    /// Creates a server builder for the given completion model.
+    pub fn new(model: M) -> Self {
+        Self::with_replicas([model])
+    }
+
+    /// Creates a server builder serving requests in parallel on the given model replicas,
+    /// e.g. the contexts of a llama.cpp engine. Each replica runs one request at a time.
+    pub fn with_replicas(models: impl IntoIterator<Item = M>) -> Self {
+        Self {
+            addr: DEFAULT_ADDR.to_string(),
+            model_id: DEFAULT_MODEL_ID.to_string(),
+            models: models.into_iter().collect(),
+            concurrency: None,
+            queue_size: DEFAULT_QUEUE_SIZE,
+            timeout: None,
+            embeddings: None,
+            authenticators: Vec::new(),
+            cors: Cors::default(),
+        }
+    }
+
+    /// Sets the address to listen on, [`DEFAULT_ADDR`] by default.
+    pub fn addr(mut self, addr: impl Into<String>) -> Self {
+        self.addr = addr.into();
+        self
+    }
+
+    /// Sets the model ID listed by `/v1/models`, [`DEFAULT_MODEL_ID`] by default.
+    pub fn model_id(mut self, model_id: impl Into<String>) -> Self {
+        self.model_id = model_id.into();
+        self
+    }
+
+    /// Limits the number of requests running at the same time, at most one per replica.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = Some(concurrency);
+        self
+    }
+
+    /// Sets the number of requests waiting for a free replica, [`DEFAULT_QUEUE_SIZE`] by
+    /// default. Requests beyond it are rejected with a 429 status.
+    pub fn queue_size(mut self, queue_size: usize) -> Self {
+        self.queue_size = queue_size;
+        self
+    }
+
+    /// Fails requests still queued or running after `timeout` with a 503 status. For
+    /// streaming requests, the timeout applies until the stream starts.
+    ///
+    /// The timeout can't interrupt a generation already running on an engine generating
+    /// synchronously, e.g. llama.cpp, which answers even if it ends after the timeout.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Serves `/v1/embeddings` with the given embeddings model.
+    pub fn embeddings<E: Embeddings + 'static>(mut self, embeddings: E) -> Self {
+        self.embeddings = Some(Box::new(embeddings));
+        self
+    }
+
+    /// Adds an authenticator, requests must pass all the authenticators to be served.
+    pub fn authenticator(mut self, authenticator: impl Authenticator + 'static) -> Self {
+        self.authenticators.push(Box::new(authenticator));
+        self
+    }
+
+    /// Only serves requests with one of the given keys as their bearer token.
+    pub fn api_keys<I, S>(self, keys: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.authenticator(ApiKeys::new(keys))
+    }
+
+    /// Only serves requests signed by LazAI users with an inference account on this node.
+    #[cfg(feature = "lazai")]
+    pub fn lazai(self, client: alith_lazai::Client) -> Self {
+        self.authenticator(LazAIValidator::new(client))
+    }
+
+    /// Sets the CORS policy, [`Cors::permissive`] by default.
+    pub fn cors(mut self, cors: Cors) -> Self {
+        self.cors = cors;
+        self
+    }
+
+    /// Runs the server until a Ctrl-C signal is received.
+    pub async fn run(self) -> Result<()> {
+        if self.models.is_empty() {
+            return Err(anyhow!("The server needs at least one model replica"));
+        }
+        let server = Arc::new(Server {
+            scheduler: Scheduler::new(self.models, self.concurrency, self.queue_size, self.timeout),
+            model_id: self.model_id,
+            embeddings: self.embeddings,
+            authenticators: self.authenticators,
+            cors: self.cors,
+            created: Utc::now().timestamp(),
+        });
+        let listener = TcpListener::bind(&self.addr).await?;
+        let stop_server = server.run(listener).await?;
+        shutdown_signal().await?;
+        let _ = stop_server.send(());
+        Ok(())
+    }
+}
+
+/// An object safe view of [`Embeddings`], so that the server isn't generic over it.
+#[async_trait]
+trait DynEmbeddings: Send + Sync {
+    async fn embed_texts(&self, input: Vec<String>)
+    -> Result<Vec<EmbeddingsData>, EmbeddingsError>;
+}
+
+#[async_trait]
+impl<E: Embeddings> DynEmbeddings for E {
+    async fn embed_texts(
+        &self,
+        input: Vec<String>,
+    ) -> Result<Vec<EmbeddingsData>, EmbeddingsError> {
+        Embeddings::embed_texts(self, input).await
+    }
 }
 
 struct Server<M: Completion + Send + Sync + 'static> {
-    model: RwLock<M>,
+    scheduler: Scheduler<M>,
+    model_id: String,
+    embeddings: Option<Box<dyn DynEmbeddings>>,
+    authenticators: Vec<Box<dyn Authenticator>>,
+    cors: Cors,
+    created: i64,
 }
 
 impl<M: Completion + Send + Sync> Server<M> {
@@ -87,47 +279,81 @@ impl<M: Completion + Send + Sync> Server<M> {
         let method = req.method().clone();
         let uri = req.uri().clone();
         let path = uri.path();
+        let headers = req.headers().clone();
 
         if method == Method::OPTIONS {
             let mut res = Response::default();
             *res.status_mut() = StatusCode::NO_CONTENT;
-            set_access_control_headers(&mut res);
+            self.cors.apply(&headers, &mut res);
             return Ok(res);
         }
 
+        for authenticator in &self.authenticators {
+            if let Err(err) = authenticator.authenticate(&headers).await {
+                let mut res = ret_err(&err, err.error_type());
+                *res.status_mut() = err.status();
+                self.cors.apply(&headers, &mut res);
+                return Ok(res);
+            }
+        }
+
         let mut status = StatusCode::OK;
-        let res = if path == V1_CHAT_COMPLETIONS {
-            self.chat_completions(req).await
-        } else {
-            // TODO: other APIs
-            status = StatusCode::NOT_FOUND;
-            Err(anyhow!("Not Found"))
+        let res = match (&method, path) {
+            (&Method::GET, V1_MODELS) => self.models(),
+            (&Method::POST, V1_CHAT_COMPLETIONS) => self.chat_completions(req).await,
+            (&Method::POST, V1_COMPLETIONS) => self.completions(req).await,
+            (&Method::POST, V1_EMBEDDINGS) if self.embeddings.is_some() => {
+                self.embeddings(req).await
+            }
+            _ => {
+                status = StatusCode::NOT_FOUND;
+                Err(anyhow!("Not Found"))
+            }
         };
         let mut res = match res {
             Ok(res) => res,
-            Err(err) => {
-                if status == StatusCode::OK {
-                    status = StatusCode::BAD_REQUEST;
+            Err(err) => match err.downcast_ref::<SchedulerError>() {
+                Some(SchedulerError::QueueFull) => {
+                    status = StatusCode::TOO_MANY_REQUESTS;
+                    let mut res = ret_err(err, "rate_limit_error");
+                    res.headers_mut()
+                        .insert(RETRY_AFTER, HeaderValue::from_static("1"));
+                    res
                 }
-                ret_err(err)
-            }
+                Some(SchedulerError::Timeout(_)) => {
+                    status = StatusCode::SERVICE_UNAVAILABLE;
+                    ret_err(err, "server_error")
+                }
+                _ => {
+                    if status == StatusCode::OK {
+                        status = StatusCode::BAD_REQUEST;
+                    }
+                    ret_err(err, "invalid_request_error")
+                }
+            },
         };
         *res.status_mut() = status;
-        set_access_control_headers(&mut res);
+        self.cors.apply(&headers, &mut res);
         Ok(res)
     }
 
+    fn models(&self) -> Result<Response<BoxBody<Bytes, Infallible>>> {
+        json_response(&ModelList {
+            object: "list",
+            data: vec![ModelObject {
+                id: self.model_id.clone(),
+                object: "model",
+                created: self.created,
+                owned_by: DEFAULT_MODEL_ID,
+            }],
+        })
+    }
+
     async fn chat_completions(
         &self,
         req: hyper::Request<Incoming>,
     ) -> Result<Response<BoxBody<Bytes, Infallible>>> {
-        let req_body = req.collect().await?.to_bytes();
-        let req_body: Value = serde_json::from_slice(&req_body)
-            .map_err(|err| anyhow!("Invalid request json, {err}"))?;
-
-        let req_body: OpenAICompletionRequest = serde_json::from_value(req_body)
-            .map_err(|err| anyhow!("Invalid request body, {err}"))?;
-
+        let req_body: OpenAICompletionRequest = read_json(req).await?;
         let OpenAICompletionRequest {
             model,
             messages,
@@ -135,38 +361,41 @@ impl<M: Completion + Send + Sync> Server<M> {
             top_p,
             max_tokens,
             tools,
+            stream,
+            stream_options,
+            response_format,
             ..
         } = req_body;
 
         let id = generate_completion_id();
         let created = Utc::now().timestamp();
-        let result = self
-            .model
-            .write()
-            .await
-            .completion(Request {
-                prompt: "".to_string(),
-                preamble: "".to_string(),
-                history: unsafe {
-                    std::mem::transmute::<Vec<CompletionRequestMessage>, Vec<Message>>(
-                        messages.clone(),
-                    )
-                },
-                max_tokens,
-                temperature,
-                top_p,
-                tools: tools
-                    .unwrap_or_default()
-                    .iter()
-                    .map(|tool| ToolDefinition {
-                        name: tool.function.name.clone(),
-                        description: tool.function.description.clone(),
-                        parameters: tool.function.parameters.clone(),
-                    })
-                    .collect(),
-                ..Default::default()
-            })
-            .await?;
+        let request = Request {
+            max_tokens,
+            temperature,
+            top_p,
+            response_format,
+            tools: tools
+                .unwrap_or_default()
+                .iter()
+                .map(|tool| ToolDefinition {
+                    name: tool.function.name.clone(),
+                    description: tool.function.description.clone(),
+                    parameters: tool.function.parameters.clone(),
+                })
+                .collect(),
+            ..chat_request(messages)?
+        };
+        if stream.unwrap_or_default() {
+            let response = self.scheduler.completion_stream(request).await?;
+            let chunks = ChatCompletionChunks {
+                id,
+                created: created as u32,
+                model,
+                include_usage: stream_options.is_some_and(|options| options.include_usage),
+            };
+            return chunks.response(response);
+        }
+        let result = self.scheduler.completion(request).await?;
         let toolcalls = result.toolcalls();
         let usage = result.token_usage();
         let choice = ChatChoice {
@@ -210,15 +439,556 @@ impl<M: Completion + Send + Sync> Server<M> {
                 total_tokens: usage.total_tokens,
             }),
         };
-        let bytes = Bytes::from(
-            serde_json::to_string(&resp)
-                .map_err(|err| anyhow!("Failed to serialize response, {err}"))?,
-        );
-        let res = Response::builder()
-            .header("Content-Type", "application/json")
-            .body(Full::new(bytes).boxed())?;
-        Ok(res)
+        json_response(&resp)
+    }
+
+    async fn completions(
+        &self,
+        req: hyper::Request<Incoming>,
+    ) -> Result<Response<BoxBody<Bytes, Infallible>>> {
+        let TextCompletionRequest {
+            model,
+            prompt,
+            max_tokens,
+            temperature,
+            top_p,
+            stream,
+            stream_options,
+        } = read_json(req).await?;
+        let prompts = prompt.into_vec();
+        if prompts.is_empty() {
+            return Err(invalid_request("prompt must not be empty").into());
+        }
+        let request = |prompt: String| Request {
+            prompt,
+            max_tokens,
+            temperature,
+            top_p,
+            ..Default::default()
+        };
+
+        let id = generate_text_completion_id();
+        let created = Utc::now().timestamp() as u32;
+        if stream {
+            let [prompt] = <[String; 1]>::try_from(prompts)
+                .map_err(|_| invalid_request("only a single prompt can be streamed"))?;
+            let response = self.scheduler.completion_stream(request(prompt)).await?;
+            let chunks = TextCompletionChunks {
+                id,
+                created,
+                model,
+                include_usage: stream_options.is_some_and(|options| options.include_usage),
+            };
+            return chunks.response(response);
+        }
+        // Schedule all the prompts at once, so they run in parallel on the free replicas.
+        let results = try_join_all(
+            prompts
+                .into_iter()
+                .map(|prompt| self.scheduler.completion(request(prompt))),
+        )
+        .await?;
+        let mut choices = Vec::with_capacity(results.len());
+        let mut usage = CompletionUsage {
+            prompt_tokens: 0,
+            completion_tokens: 0,
+            total_tokens: 0,
+        };
+        for (index, result) in results.into_iter().enumerate() {
+            let token_usage = result.token_usage();
+            usage.prompt_tokens += token_usage.prompt_tokens;
+            usage.completion_tokens += token_usage.completion_tokens;
+            usage.total_tokens += token_usage.total_tokens;
+            choices.push(TextChoice {
+                text: result.content(),
+                index: index as u32,
+                logprobs: None,
+                finish_reason: Some(FinishReason::Stop),
+            });
+        }
+        json_response(&TextCompletionResponse {
+            id,
+            object: "text_completion",
+            created,
+            model,
+            choices,
+            usage: Some(usage),
+        })
+    }
+
+    async fn embeddings(
+        &self,
+        req: hyper::Request<Incoming>,
+    ) -> Result<Response<BoxBody<Bytes, Infallible>>> {
+        let EmbeddingsRequestBody {
+            model,
+            input,
+            encoding_format,
+        } = read_json(req).await?;
+        let input = input.into_vec();
+        if input.is_empty() {
+            return Err(invalid_request("input must not be empty").into());
+        }
+        let embeddings = self
+            .embeddings
+            .as_ref()
+            .ok_or_else(|| anyhow!("Embeddings are not enabled"))?;
+        let count = input.len();
+        let data = embeddings.embed_texts(input).await?;
+        if data.len() != count {
+            return Err(anyhow!(
+                "Expected {count} embeddings from the model, got {}",
+                data.len()
+            ));
+        }
+        json_response(&EmbeddingList {
+            object: "list",
+            data: data
+                .into_iter()
+                .enumerate()
+                .map(|(index, data)| EmbeddingObject {
+                    object: "embedding",
+                    embedding: encoding_format.encode(data.vec),
+                    index,
+                })
+                .collect(),
+            model,
+            // The embeddings trait doesn't report token counts.
+            usage: EmbeddingUsage {
+                prompt_tokens: 0,
+                total_tokens: 0,
+            },
+        })
+    }
+}
+
+/// A string or an array of strings, as accepted by the `prompt` and `input` fields.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum TextInput {
+    Text(String),
+    Texts(Vec<String>),
+}
+
+impl TextInput {
+    fn into_vec(self) -> Vec<String> {
+        match self {
+            TextInput::Text(text) => vec![text],
+            TextInput::Texts(texts) => texts,
+        }
+    }
+}
+
+/// The body of a `/v1/completions` request.
+#[derive(Debug, Deserialize)]
+struct TextCompletionRequest {
+    model: String,
+    prompt: TextInput,
+    max_tokens: Option<usize>,
+    temperature: Option<f32>,
+    top_p: Option<f32>,
+    #[serde(default)]
+    stream: bool,
+    stream_options: Option<StreamOptions>,
+}
+
+/// The response of a `/v1/completions` request, also used for its streamed chunks.
+#[derive(Debug, Serialize)]
+struct TextCompletionResponse {
+    id: String,
+    object: &'static str,
+    created: u32,
+    model: String,
+    choices: Vec<TextChoice>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    usage: Option<CompletionUsage>,
+}
+
+#[derive(Debug, Serialize)]
+struct TextChoice {
+    text: String,
+    index: u32,
+    logprobs: Option<Value>,
+    finish_reason: Option<FinishReason>,
+}
+
+/// The body of a `/v1/embeddings` request.
+#[derive(Debug, Deserialize)]
+struct EmbeddingsRequestBody {
+    model: String,
+    input: TextInput,
+    #[serde(default)]
+    encoding_format: EncodingFormat,
+}
+
+#[derive(Debug, Default, Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum EncodingFormat {
+    #[default]
+    Float,
+    Base64,
+}
+
+impl EncodingFormat {
+    fn encode(self, vec: Vec<f64>) -> EmbeddingVector {
+        match self {
+            EncodingFormat::Float => EmbeddingVector::Float(vec),
+            EncodingFormat::Base64 => {
+                // Base64 embeddings are little-endian `f32` arrays.
+                let bytes: Vec<u8> = vec
+                    .into_iter()
+                    .flat_map(|v| (v as f32).to_le_bytes())
+                    .collect();
+                EmbeddingVector::Base64(BASE64_STANDARD.encode(bytes))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct EmbeddingList {
+    object: &'static str,
+    data: Vec<EmbeddingObject>,
+    model: String,
+    usage: EmbeddingUsage,
+}
+
+#[derive(Debug, Serialize)]
+struct EmbeddingObject {
+    object: &'static str,
+    embedding: EmbeddingVector,
+    index: usize,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum EmbeddingVector {
+    Float(Vec<f64>),
+    Base64(String),
+}
+
+#[derive(Debug, Serialize)]
+struct EmbeddingUsage {
+    prompt_tokens: usize,
+    total_tokens: usize,
+}
+
+/// The response of a `/v1/models` request.
+#[derive(Debug, Serialize)]
+struct ModelList {
+    object: &'static str,
+    data: Vec<ModelObject>,
+}
+
+#[derive(Debug, Serialize)]
+struct ModelObject {
+    id: String,
+    object: &'static str,
+    created: i64,
+    owned_by: &'static str,
+}
+
+/// Converts the messages of a chat completion request into a [`Request`].
+///
+/// The last user message becomes the prompt, the messages before it the history and the
+/// assistant tool calls and tool results after it the tool messages of the current turn.
+/// Other messages can't follow the last user message.
+fn chat_request(messages: Vec<CompletionRequestMessage>) -> Result<Request, InferenceError> {
+    let mut converted = Vec::with_capacity(messages.len());
+    let mut tool_call_ids = Vec::new();
+    for message in messages {
+        if message.role != "assistant" && message.tool_calls.is_some() {
+            return Err(invalid_request(format!(
+                "{} messages can't contain tool calls",
+                message.role
+            )));
+        }
+        if message.role != "tool" && message.tool_call_id.is_some() {
+            return Err(invalid_request(format!(
+                "{} messages can't contain a tool call id",
+                message.role
+            )));
+        }
+        let message = match message.role.as_str() {
+            "system" | "developer" => Message::new("system", message.content),
+            "user" => Message::new("user", message.content),
+            "assistant" => {
+                let tool_calls = message.tool_calls.unwrap_or_default();
+                for call in &tool_calls {
+                    if call.id.is_empty() || call.function.name.is_empty() {
+                        return Err(invalid_request(
+                            "tool calls must contain an id and a function name",
+                        ));
+                    }
+                }
+                tool_call_ids = tool_calls.iter().map(|call| call.id.clone()).collect();
+                Message::assistant_with_tool_calls(
+                    message.content,
+                    tool_calls.iter().map(Into::into).collect(),
+                )
+            }
+            "tool" => {
+                let Some(tool_call_id) = message.tool_call_id else {
+                    return Err(invalid_request("tool messages must contain a tool call id"));
+                };
+                if !tool_call_ids.contains(&tool_call_id) {
+                    return Err(invalid_request(format!(
+                        "tool message answers unknown tool call {tool_call_id}"
+                    )));
+                }
+                Message::tool_result(message.content, tool_call_id)
+            }
+            role => return Err(invalid_request(format!("unsupported message role {role}"))),
+        };
+        converted.push(message);
+    }
+    let Some(prompt_index) = converted.iter().rposition(|message| message.role == "user") else {
+        return Err(invalid_request("messages must contain a user message"));
+    };
+    let tool_messages = converted.split_off(prompt_index + 1);
+    for (index, message) in tool_messages.iter().enumerate() {
+        if message.role != "tool" && message.tool_calls.is_empty() {
+            return Err(invalid_request(format!(
+                "messages[{}]: only assistant tool calls and tool results can follow the last user message, not a {} message",
+                prompt_index + 1 + index,
+                message.role
+            )));
+        }
+    }
+    let prompt = converted
+        .pop()
+        .map(|message| message.content)
+        .unwrap_or_default();
+    Ok(Request {
+        prompt,
+        history: converted,
+        tool_messages,
+        ..Default::default()
+    })
+}
+
+#[inline]
+fn invalid_request(message: impl ToString) -> InferenceError {
+    InferenceError::InvalidRequest(message.to_string())
+}
+
+/// Writes a completion response stream as `chat.completion.chunk` server-sent events.
+struct ChatCompletionChunks {
+    id: String,
+    created: u32,
+    model: String,
+    include_usage: bool,
+}
+
+impl ChatCompletionChunks {
+    fn response(self, response: ResponseStream) -> Result<Response<BoxBody<Bytes, Infallible>>> {
+        sse_response(|tx| async move {
+            let _ = self.forward(response, &tx).await;
+        })
     }
+
+    async fn forward(
+        &self,
+        mut response: ResponseStream,
+        tx: &mpsc::Sender<Bytes>,
+    ) -> std::result::Result<(), mpsc::error::SendError<Bytes>> {
+        tx.send(self.event(
+            vec![self.choice(
+                ChatCompletionStreamDelta {
+                    role: Some(Role::Assistant),
+                    content: Some(String::new()),
+                    tool_calls: None,
+                },
+                None,
+            )],
+            None,
+        ))
+        .await?;
+        let mut finish_reason = FinishReason::Stop;
+        let mut usage = None;
+        while let Some(chunk) = response.next().await {
+            let delta = match chunk {
+                Ok(CompletionChunk::Text(text)) => ChatCompletionStreamDelta {
+                    content: Some(text),
+                    ..Default::default()
+                },
+                Ok(CompletionChunk::ToolCall(call)) => {
+                    finish_reason = FinishReason::ToolCalls;
+                    ChatCompletionStreamDelta {
+                        tool_calls: Some(vec![ToolCallStreamDelta {
+                            index: call.index,
+                            r#type: call.id.as_ref().map(|_| "function".to_string()),
+                            id: call.id,
+                            function: Some(FunctionStreamDelta {
+                                name: call.name,
+                                arguments: Some(call.arguments),
+                            }),
+                        }]),
+                        ..Default::default()
+                    }
+                }
+                Ok(CompletionChunk::Usage(token_usage)) => {
+                    usage = Some(CompletionUsage {
+                        prompt_tokens: token_usage.prompt_tokens,
+                        completion_tokens: token_usage.completion_tokens,
+                        total_tokens: token_usage.total_tokens,
+                    });
+                    continue;
+                }
+                Err(err) => {
+                    tx.send(sse_error(err)).await?;
+                    return tx.send(sse_data("[DONE]")).await;
+                }
+            };
+            tx.send(self.event(vec![self.choice(delta, None)], None))
+                .await?;
+        }
+        tx.send(self.event(
+            vec![self.choice(Default::default(), Some(finish_reason))],
+            None,
+        ))
+        .await?;
+        if self.include_usage {
+            tx.send(self.event(vec![], usage)).await?;
+        }
+        tx.send(sse_data("[DONE]")).await
+    }
+
+    fn choice(
+        &self,
+        delta: ChatCompletionStreamDelta,
+        finish_reason: Option<FinishReason>,
+    ) -> ChatChunkChoice {
+        ChatChunkChoice {
+            index: 0,
+            delta,
+            finish_reason,
+        }
+    }
+
+    fn event(&self, choices: Vec<ChatChunkChoice>, usage: Option<CompletionUsage>) -> Bytes {
+        let chunk = OpenAICompletionChunk {
+            id: self.id.clone(),
+            object: "chat.completion.chunk".to_string(),
+            created: self.created,
+            model: self.model.clone(),
+            choices,
+            usage,
+        };
+        // Serializing the chunk types can't fail, they only contain strings and numbers.
+        sse_data(&serde_json::to_string(&chunk).unwrap_or_default())
+    }
+}
+
+/// Writes a completion response stream as `text_completion` server-sent events.
+struct TextCompletionChunks {
+    id: String,
+    created: u32,
+    model: String,
+    include_usage: bool,
+}
+
+impl TextCompletionChunks {
+    fn response(self, response: ResponseStream) -> Result<Response<BoxBody<Bytes, Infallible>>> {
+        sse_response(|tx| async move {
+            let _ = self.forward(response, &tx).await;
+        })
+    }
+
+    async fn forward(
+        &self,
+        mut response: ResponseStream,
+        tx: &mpsc::Sender<Bytes>,
+    ) -> std::result::Result<(), mpsc::error::SendError<Bytes>> {
+        let mut usage = None;
+        while let Some(chunk) = response.next().await {
+            match chunk {
+                Ok(CompletionChunk::Text(text)) => {
+                    tx.send(self.event(text, None, None)).await?;
+                }
+                Ok(CompletionChunk::Usage(token_usage)) => {
+                    usage = Some(CompletionUsage {
+                        prompt_tokens: token_usage.prompt_tokens,
+                        completion_tokens: token_usage.completion_tokens,
+                        total_tokens: token_usage.total_tokens,
+                    });
+                }
+                // No tools are sent with text completion requests.
+                Ok(CompletionChunk::ToolCall(_)) => {}
+                Err(err) => {
+                    tx.send(sse_error(err)).await?;
+                    return tx.send(sse_data("[DONE]")).await;
+                }
+            }
+        }
+        tx.send(self.event(String::new(), Some(FinishReason::Stop), None))
+            .await?;
+        if self.include_usage {
+            tx.send(self.event(String::new(), None, usage)).await?;
+        }
+        tx.send(sse_data("[DONE]")).await
+    }
+
+    fn event(
+        &self,
+        text: String,
+        finish_reason: Option<FinishReason>,
+        usage: Option<CompletionUsage>,
+    ) -> Bytes {
+        let chunk = TextCompletionResponse {
+            id: self.id.clone(),
+            object: "text_completion",
+            created: self.created,
+            model: self.model.clone(),
+            // The usage chunk has no choices, like in chat completion streams.
+            choices: if usage.is_some() {
+                vec![]
+            } else {
+                vec![TextChoice {
+                    text,
+                    index: 0,
+                    logprobs: None,
+                    finish_reason,
+                }]
+            },
+            usage,
+        };
+        sse_data(&serde_json::to_string(&chunk).unwrap_or_default())
+    }
+}
+
+/// Responds with server-sent events sent by `forward` into a channel.
+fn sse_response<F, Fut>(forward: F) -> Result<Response<BoxBody<Bytes, Infallible>>>
+where
+    F: FnOnce(mpsc::Sender<Bytes>) -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel::<Bytes>(32);
+    // Forward the model stream from a task, so the body doesn't hold the model stream.
+    // The task stops when the client disconnects and the receiver is dropped.
+    tokio::spawn(forward(tx));
+    let body = StreamBody::new(ReceiverStream::new(rx).map(|bytes| Ok(Frame::data(bytes))));
+    let res = Response::builder()
+        .header("Content-Type", "text/event-stream")
+        .header("Cache-Control", "no-cache")
+        .body(BodyExt::boxed(body))?;
+    Ok(res)
+}
+
+#[inline]
+fn sse_error(err: impl std::fmt::Display) -> Bytes {
+    let data = json!({
+        "error": {
+            "message": err.to_string(),
+            "type": "server_error",
+        },
+    });
+    sse_data(&data.to_string())
+}
+
+#[inline]
+fn sse_data(data: &str) -> Bytes {
+    Bytes::from(format!("data: {data}\n\n"))
 }
 
 #[inline]
@@ -227,27 +997,37 @@ fn generate_completion_id() -> String {
 }
 
 #[inline]
-fn set_access_control_headers(res: &mut Response<BoxBody<Bytes, Infallible>>) {
-    res.headers_mut().insert(
-        hyper::header::ACCESS_CONTROL_ALLOW_ORIGIN,
-        hyper::header::HeaderValue::from_static("*"),
-    );
-    res.headers_mut().insert(
-        hyper::header::ACCESS_CONTROL_ALLOW_METHODS,
-        hyper::header::HeaderValue::from_static("GET,POST,PUT,PATCH,DELETE"),
-    );
-    res.headers_mut().insert(
-        hyper::header::ACCESS_CONTROL_ALLOW_HEADERS,
-        hyper::header::HeaderValue::from_static("Content-Type,Authorization"),
+fn generate_text_completion_id() -> String {
+    format!("cmpl-{}", Utc::now().nanosecond())
+}
+
+/// Reads and deserializes a JSON request body.
+async fn read_json<T: DeserializeOwned>(req: hyper::Request<Incoming>) -> Result<T> {
+    let req_body = req.collect().await?.to_bytes();
+    let req_body: Value =
+        serde_json::from_slice(&req_body).map_err(|err| anyhow!("Invalid request json, {err}"))?;
+    let req_body =
+        serde_json::from_value(req_body).map_err(|err| anyhow!("Invalid request body, {err}"))?;
+    Ok(req_body)
+}
+
+fn json_response<T: Serialize>(body: &T) -> Result<Response<BoxBody<Bytes, Infallible>>> {
+    let bytes = Bytes::from(
+        serde_json::to_string(body)
+            .map_err(|err| anyhow!("Failed to serialize response, {err}"))?,
     );
+    let res = Response::builder()
+        .header("Content-Type", "application/json")
+        .body(Full::new(bytes).boxed())?;
+    Ok(res)
 }
 
 #[inline]
-fn ret_err<T: std::fmt::Display>(err: T) -> Response<BoxBody<Bytes, Infallible>> {
+fn ret_err<T: std::fmt::Display>(err: T, error_type: &str) -> Response<BoxBody<Bytes, Infallible>> {
     let data = json!({
         "error": {
             "message": err.to_string(),
-            "type": "invalid_request_error",
+            "type": error_type,
         },
     });
     Response::builder()
@@ -260,3 +1040,326 @@ async fn shutdown_signal() -> Result<()> {
     tokio::signal::ctrl_c().await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(value: Value) -> Vec<CompletionRequestMessage> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_chat_request_keeps_roles_and_tool_calls() {
+        let request = chat_request(messages(json!([
+            {"role": "system", "content": "Be brief."},
+            {"role": "user", "content": "Hi"},
+            {"role": "assistant", "content": "Hello!"},
+            {"role": "user", "content": [
+                {"type": "text", "text": "What's the weather"},
+                {"type": "text", "text": "in Paris?"},
+            ]},
+            {"role": "assistant", "content": null, "tool_calls": [{
+                "id": "call_1",
+                "type": "function",
+                "function": {"name": "weather", "arguments": "{\"city\":\"Paris\"}"},
+            }]},
+            {"role": "tool", "content": "Sunny", "tool_call_id": "call_1"},
+        ])))
+        .unwrap();
+
+        assert_eq!(request.prompt, "What's the weather\nin Paris?");
+        let history: Vec<_> = request
+            .history
+            .iter()
+            .map(|m| (m.role.as_str(), m.content.as_str()))
+            .collect();
+        assert_eq!(
+            history,
+            [
+                ("system", "Be brief."),
+                ("user", "Hi"),
+                ("assistant", "Hello!")
+            ]
+        );
+        assert_eq!(request.tool_messages.len(), 2);
+        let call = &request.tool_messages[0];
+        assert_eq!(call.role, "assistant");
+        assert_eq!(call.content, "");
+        assert_eq!(call.tool_calls[0].id, "call_1");
+        assert_eq!(call.tool_calls[0].function.name, "weather");
+        let result = &request.tool_messages[1];
+        assert_eq!(result.role, "tool");
+        assert_eq!(result.content, "Sunny");
+        assert_eq!(result.tool_call_id.as_deref(), Some("call_1"));
+    }
+
+    #[test]
+    fn test_chat_request_rejects_malformed_messages() {
+        for value in [
+            json!([]),
+            json!([{"role": "system", "content": "Be brief."}]),
+            json!([{"role": "robot", "content": "Hi"}]),
+            json!([{"role": "user", "content": "Hi"}, {"role": "tool", "content": "Sunny"}]),
+            json!([
+                {"role": "user", "content": "Hi"},
+                {"role": "tool", "content": "Sunny", "tool_call_id": "call_1"},
+            ]),
+            json!([{"role": "user", "content": "Hi", "tool_call_id": "call_1"}]),
+        ] {
+            let err = chat_request(messages(value.clone())).unwrap_err();
+            assert!(
+                matches!(err, InferenceError::InvalidRequest(_)),
+                "{value} should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn test_chat_request_rejects_a_trailing_system_message() {
+        let err = chat_request(messages(json!([
+            {"role": "user", "content": "Hi"},
+            {"role": "system", "content": "Be brief."},
+        ])))
+        .unwrap_err();
+        assert!(err.to_string().contains("messages[1]"), "{err}");
+        assert!(err.to_string().contains("system message"), "{err}");
+    }
+
+    #[test]
+    fn test_chat_request_rejects_a_trailing_assistant_answer() {
+        let err = chat_request(messages(json!([
+            {"role": "user", "content": "What's the weather in Paris?"},
+            {"role": "assistant", "content": null, "tool_calls": [{
+                "id": "call_1",
+                "type": "function",
+                "function": {"name": "weather", "arguments": "{}"},
+            }]},
+            {"role": "tool", "content": "Sunny", "tool_call_id": "call_1"},
+            {"role": "assistant", "content": "It's sunny."},
+        ])))
+        .unwrap_err();
+        assert!(err.to_string().contains("messages[3]"), "{err}");
+        assert!(err.to_string().contains("assistant message"), "{err}");
+    }
+
+    #[test]
+    fn test_base64_embedding_encoding() {
+        let EmbeddingVector::Base64(encoded) = EncodingFormat::Base64.encode(vec![1.0, -0.5])
+        else {
+            panic!("expected a base64 embedding");
+        };
+        let bytes = BASE64_STANDARD.decode(encoded).unwrap();
+        let floats: Vec<f32> = bytes
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        assert_eq!(floats, [1.0, -0.5]);
+    }
+
+    #[test]
+    fn test_message_content_rejects_unsupported_parts() {
+        let result = serde_json::from_value::<Vec<CompletionRequestMessage>>(json!([
+            {"role": "user", "content": [
+                {"type": "image_url", "image_url": {"url": "https://example.com/a.png"}},
+            ]},
+        ]));
+        assert!(result.is_err());
+    }
+
+    /// An engine streaming "Hello" with its usage, or a tool call when asked for the weather.
+    struct Streaming;
+
+    struct Text(String);
+
+    impl ResponseContent for Text {
+        fn content(&self) -> String {
+            self.0.clone()
+        }
+    }
+
+    impl ResponseToolCalls for Text {
+        fn toolcalls(&self) -> Vec<alith_core::chat::ToolCall> {
+            Vec::new()
+        }
+    }
+
+    impl ResponseTokenUsage for Text {
+        fn token_usage(&self) -> alith_core::chat::TokenUsage {
+            alith_core::chat::TokenUsage {
+                tokens_cached: None,
+                prompt_tokens: 0,
+                completion_tokens: 0,
+                total_tokens: 0,
+            }
+        }
+    }
+
+    impl Completion for Streaming {
+        type Response = Text;
+
+        async fn completion(
+            &mut self,
+            request: Request,
+        ) -> Result<Text, alith_core::chat::CompletionError> {
+            Ok(Text(request.prompt))
+        }
+
+        async fn completion_stream(
+            &mut self,
+            request: Request,
+        ) -> Result<ResponseStream, alith_core::chat::CompletionError> {
+            let chunks = if request.prompt.contains("weather") {
+                vec![CompletionChunk::ToolCall(alith_core::chat::ToolCallChunk {
+                    index: 0,
+                    id: Some("call_1".to_string()),
+                    name: Some("weather".to_string()),
+                    arguments: "{}".to_string(),
+                })]
+            } else {
+                vec![
+                    CompletionChunk::Text("Hel".to_string()),
+                    CompletionChunk::Text("lo".to_string()),
+                    CompletionChunk::Usage(alith_core::chat::TokenUsage {
+                        tokens_cached: None,
+                        prompt_tokens: 3,
+                        completion_tokens: 2,
+                        total_tokens: 5,
+                    }),
+                ]
+            };
+            Ok(Box::pin(futures_util::stream::iter(
+                chunks.into_iter().map(Ok),
+            )))
+        }
+    }
+
+    /// Posts a request to a server running the [`Streaming`] engine and returns the data of
+    /// the server-sent events of the response.
+    async fn post_events(path: &str, body: Value) -> Vec<String> {
+        let server = Arc::new(Server {
+            scheduler: Scheduler::new(vec![Streaming], None, 0, None),
+            model_id: DEFAULT_MODEL_ID.to_string(),
+            embeddings: None,
+            authenticators: Vec::new(),
+            cors: Cors::permissive(),
+            created: 0,
+        });
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let _stop_server = server.run(listener).await.unwrap();
+
+        let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let (mut sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+            .await
+            .unwrap();
+        tokio::spawn(conn);
+        let req = hyper::Request::post(path)
+            .header("Host", addr.to_string())
+            .header("Content-Type", "application/json")
+            .body(Full::new(Bytes::from(body.to_string())))
+            .unwrap();
+        let res = sender.send_request(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()["Content-Type"], "text/event-stream");
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        String::from_utf8(body.to_vec())
+            .unwrap()
+            .split_terminator("\n\n")
+            .map(|event| event.strip_prefix("data: ").unwrap().to_string())
+            .collect()
+    }
+
+    fn chunks(events: &[String]) -> Vec<Value> {
+        assert_eq!(events.last().map(String::as_str), Some("[DONE]"));
+        events[..events.len() - 1]
+            .iter()
+            .map(|event| serde_json::from_str(event).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_chat_completion_stream() {
+        let events = post_events(
+            V1_CHAT_COMPLETIONS,
+            json!({
+                "model": "alith",
+                "messages": [{"role": "user", "content": "Hi"}],
+                "stream": true,
+                "stream_options": {"include_usage": true},
+            }),
+        )
+        .await;
+        let chunks = chunks(&events);
+        assert_eq!(chunks.len(), 5);
+        for chunk in &chunks {
+            assert_eq!(chunk["object"], "chat.completion.chunk");
+            assert_eq!(chunk["model"], "alith");
+            assert_eq!(chunk["id"], chunks[0]["id"]);
+        }
+        // The first delta only carries the role.
+        assert_eq!(
+            chunks[0]["choices"][0]["delta"],
+            json!({"role": "assistant", "content": ""})
+        );
+        assert_eq!(chunks[0]["choices"][0]["finish_reason"], Value::Null);
+        assert_eq!(chunks[1]["choices"][0]["delta"], json!({"content": "Hel"}));
+        assert_eq!(chunks[2]["choices"][0]["delta"], json!({"content": "lo"}));
+        assert_eq!(chunks[3]["choices"][0]["delta"], json!({}));
+        assert_eq!(chunks[3]["choices"][0]["finish_reason"], "stop");
+        assert_eq!(chunks[4]["choices"], json!([]));
+        assert_eq!(chunks[4]["usage"]["total_tokens"], 5);
+    }
+
+    #[tokio::test]
+    async fn test_chat_completion_stream_of_tool_calls() {
+        let events = post_events(
+            V1_CHAT_COMPLETIONS,
+            json!({
+                "model": "alith",
+                "messages": [{"role": "user", "content": "What's the weather?"}],
+                "stream": true,
+            }),
+        )
+        .await;
+        let chunks = chunks(&events);
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(
+            chunks[1]["choices"][0]["delta"]["tool_calls"],
+            json!([{
+                "index": 0,
+                "id": "call_1",
+                "type": "function",
+                "function": {"name": "weather", "arguments": "{}"},
+            }])
+        );
+        // Without `include_usage`, the last chunk is the one finishing the choice.
+        assert_eq!(chunks[2]["choices"][0]["finish_reason"], "tool_calls");
+        assert!(chunks[2].get("usage").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_text_completion_stream() {
+        let events = post_events(
+            V1_COMPLETIONS,
+            json!({
+                "model": "alith",
+                "prompt": "Hi",
+                "stream": true,
+                "stream_options": {"include_usage": true},
+            }),
+        )
+        .await;
+        let chunks = chunks(&events);
+        assert_eq!(chunks.len(), 4);
+        for chunk in &chunks {
+            assert_eq!(chunk["object"], "text_completion");
+        }
+        assert_eq!(chunks[0]["choices"][0]["text"], "Hel");
+        assert_eq!(chunks[1]["choices"][0]["text"], "lo");
+        assert_eq!(chunks[2]["choices"][0]["text"], "");
+        assert_eq!(chunks[2]["choices"][0]["finish_reason"], "stop");
+        assert_eq!(chunks[3]["choices"], json!([]));
+        assert_eq!(chunks[3]["usage"]["completion_tokens"], 2);
+    }
+}
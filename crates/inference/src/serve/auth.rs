@@ -0,0 +1,141 @@
+use async_trait::async_trait;
+use http::{HeaderMap, StatusCode, header::AUTHORIZATION};
+use std::collections::HashSet;
+use thiserror::Error;
+
+/// Authenticates the requests of the inference server before they are routed.
+#[async_trait]
+pub trait Authenticator: Send + Sync {
+    /// Returns an error when the request with the given headers isn't allowed.
+    async fn authenticate(&self, headers: &HeaderMap) -> Result<(), AuthError>;
+}
+
+#[derive(Error, Debug)]
+pub enum AuthError {
+    /// The request has no or invalid credentials, answered with a 401 status.
+    #[error("{0}")]
+    Unauthorized(String),
+    /// The credentials are valid but not allowed to call the server, answered with a 403 status.
+    #[error("{0}")]
+    Forbidden(String),
+    /// The credentials couldn't be checked, answered with a 500 status.
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl AuthError {
+    pub(crate) fn status(&self) -> StatusCode {
+        match self {
+            AuthError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AuthError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AuthError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub(crate) fn error_type(&self) -> &'static str {
+        match self {
+            AuthError::Unauthorized(_) => "authentication_error",
+            AuthError::Forbidden(_) => "permission_error",
+            AuthError::Internal(_) => "server_error",
+        }
+    }
+}
+
+/// Accepts requests carrying one of a static set of keys as an `Authorization: Bearer` token.
+#[derive(Debug, Clone, Default)]
+pub struct ApiKeys {
+    keys: HashSet<String>,
+}
+
+impl ApiKeys {
+    pub fn new<I, S>(keys: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            keys: keys.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+#[async_trait]
+impl Authenticator for ApiKeys {
+    async fn authenticate(&self, headers: &HeaderMap) -> Result<(), AuthError> {
+        let key = headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or_else(|| {
+                AuthError::Unauthorized("Missing bearer token in the Authorization header".into())
+            })?;
+        if self.keys.contains(key.trim()) {
+            Ok(())
+        } else {
+            Err(AuthError::Unauthorized("Invalid API key".into()))
+        }
+    }
+}
+
+/// Validates the LazAI user, nonce and signature headers of inference requests with
+/// [`alith_lazai::Client::validate_request`].
+#[cfg(feature = "lazai")]
+pub struct LazAIValidator {
+    client: alith_lazai::Client,
+}
+
+#[cfg(feature = "lazai")]
+impl LazAIValidator {
+    pub fn new(client: alith_lazai::Client) -> Self {
+        Self { client }
+    }
+}
+
+#[cfg(feature = "lazai")]
+#[async_trait]
+impl Authenticator for LazAIValidator {
+    async fn authenticate(&self, headers: &HeaderMap) -> Result<(), AuthError> {
+        use alith_lazai::{ClientError, NONCE_HEADER, RequestType, SIGNATURE_HEADER, USER_HEADER};
+        use std::collections::HashMap;
+
+        // Header names are case insensitive, but the client looks them up with their exact names.
+        let mut lazai_headers = HashMap::new();
+        for name in [USER_HEADER, NONCE_HEADER, SIGNATURE_HEADER] {
+            let value = headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .ok_or_else(|| AuthError::Unauthorized(format!("Missing {name} header")))?;
+            lazai_headers.insert(name.to_string(), value.to_string());
+        }
+        self.client
+            .validate_request(lazai_headers, RequestType::Inference)
+            .await
+            .map_err(|err| match err {
+                ClientError::ValidationError(message) => AuthError::Forbidden(message),
+                err => AuthError::Internal(err.to_string()),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::HeaderValue;
+
+    #[tokio::test]
+    async fn test_api_keys() {
+        let keys = ApiKeys::new(["sk-1", "sk-2"]);
+        let mut headers = HeaderMap::new();
+        assert!(matches!(
+            keys.authenticate(&headers).await,
+            Err(AuthError::Unauthorized(_))
+        ));
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer sk-3"));
+        assert!(matches!(
+            keys.authenticate(&headers).await,
+            Err(AuthError::Unauthorized(_))
+        ));
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer sk-2"));
+        assert!(keys.authenticate(&headers).await.is_ok());
+    }
+}
@@ -0,0 +1,135 @@
+use bytes::Bytes;
+use http::{
+    HeaderMap, HeaderValue, Response,
+    header::{
+        ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN,
+        ACCESS_CONTROL_MAX_AGE, ORIGIN, VARY,
+    },
+};
+use http_body_util::combinators::BoxBody;
+use std::convert::Infallible;
+
+/// The CORS policy of the inference server.
+///
+/// The default policy allows any origin, like [`Cors::permissive`].
+#[derive(Debug, Clone)]
+pub struct Cors {
+    allow_origins: Option<Vec<String>>,
+    allow_methods: String,
+    allow_headers: String,
+    max_age: Option<u64>,
+    enabled: bool,
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        Self::permissive()
+    }
+}
+
+impl Cors {
+    /// Allows requests from any origin.
+    pub fn permissive() -> Self {
+        Self {
+            allow_origins: None,
+            allow_methods: "GET,POST,OPTIONS".to_string(),
+            allow_headers:
+                "Content-Type,Authorization,X-LazAI-User,X-LazAI-Nonce,X-LazAI-Signature"
+                    .to_string(),
+            max_age: None,
+            enabled: true,
+        }
+    }
+
+    /// Sends no CORS headers, so browsers only allow same origin requests.
+    pub fn disabled() -> Self {
+        Self {
+            enabled: false,
+            ..Self::permissive()
+        }
+    }
+
+    /// Only allows requests from the given origins, e.g. `https://example.com`.
+    pub fn allow_origins<I, S>(mut self, origins: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.allow_origins = Some(origins.into_iter().map(Into::into).collect());
+        self
+    }
+
+    pub fn allow_methods<I, S>(mut self, methods: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.allow_methods = join(methods);
+        self
+    }
+
+    pub fn allow_headers<I, S>(mut self, headers: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.allow_headers = join(headers);
+        self
+    }
+
+    /// Sets how long browsers may cache the preflight response, in seconds.
+    pub fn max_age(mut self, seconds: u64) -> Self {
+        self.max_age = Some(seconds);
+        self
+    }
+
+    /// Adds the CORS headers for a request with the given headers to the response.
+    pub(crate) fn apply(
+        &self,
+        req_headers: &HeaderMap,
+        res: &mut Response<BoxBody<Bytes, Infallible>>,
+    ) {
+        if !self.enabled {
+            return;
+        }
+        let headers = res.headers_mut();
+        let origin = match &self.allow_origins {
+            None => HeaderValue::from_static("*"),
+            Some(origins) => {
+                headers.append(VARY, HeaderValue::from_static("Origin"));
+                match req_headers.get(ORIGIN) {
+                    Some(origin)
+                        if origins
+                            .iter()
+                            .any(|allowed| allowed.as_bytes() == origin.as_bytes()) =>
+                    {
+                        origin.clone()
+                    }
+                    _ => return,
+                }
+            }
+        };
+        headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+        if let Ok(methods) = HeaderValue::from_str(&self.allow_methods) {
+            headers.insert(ACCESS_CONTROL_ALLOW_METHODS, methods);
+        }
+        if let Ok(allow_headers) = HeaderValue::from_str(&self.allow_headers) {
+            headers.insert(ACCESS_CONTROL_ALLOW_HEADERS, allow_headers);
+        }
+        if let Some(max_age) = self.max_age {
+            headers.insert(ACCESS_CONTROL_MAX_AGE, HeaderValue::from(max_age));
+        }
+    }
+}
+
+fn join<I, S>(values: I) -> String
+where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+{
+    values
+        .into_iter()
+        .map(Into::into)
+        .collect::<Vec<_>>()
+        .join(",")
+}
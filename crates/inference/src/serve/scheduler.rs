@@ -0,0 +1,296 @@
+use alith_core::chat::{Completion, CompletionError, Request, ResponseStream};
+use futures_util::{StreamExt, stream};
+use std::sync::{Arc, Mutex};
+use std::task::Poll;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::runtime::{Handle, RuntimeFlavor};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::Instant;
+
+#[derive(Error, Debug)]
+pub enum SchedulerError {
+    /// The queue is full, answered with a 429 status.
+    #[error("The server is overloaded, too many requests are queued")]
+    QueueFull,
+    /// The request waited or ran longer than the timeout, answered with a 503 status.
+    #[error("The request timed out after {0:?}")]
+    Timeout(Duration),
+    #[error(transparent)]
+    Completion(#[from] CompletionError),
+}
+
+/// Runs completion requests on a pool of model replicas.
+///
+/// Each replica serves one request at a time, so up to `concurrency` requests run in
+/// parallel on distinct replicas, e.g. on the contexts of a llama.cpp engine. Up to
+/// `queue_size` more requests wait for a free replica, the others are rejected.
+///
+/// The engines may generate synchronously, e.g. llama.cpp, so the model calls run with
+/// [`tokio::task::block_in_place`] on a multi-threaded runtime. The timeout is only
+/// checked while waiting for a replica and between the awaits of the engine: it can't
+/// interrupt a synchronous generation that is already running.
+pub(crate) struct Scheduler<M> {
+    models: Arc<Mutex<Vec<M>>>,
+    queue: Arc<Semaphore>,
+    running: Arc<Semaphore>,
+    timeout: Option<Duration>,
+}
+
+impl<M: Completion + Send + Sync + 'static> Scheduler<M> {
+    pub(crate) fn new(
+        models: Vec<M>,
+        concurrency: Option<usize>,
+        queue_size: usize,
+        timeout: Option<Duration>,
+    ) -> Self {
+        let concurrency = concurrency.map_or(models.len(), |c| c.clamp(1, models.len()));
+        Self {
+            models: Arc::new(Mutex::new(models)),
+            queue: Arc::new(Semaphore::new(concurrency + queue_size)),
+            running: Arc::new(Semaphore::new(concurrency)),
+            timeout,
+        }
+    }
+
+    pub(crate) async fn completion(&self, request: Request) -> Result<M::Response, SchedulerError> {
+        let deadline = self.deadline();
+        let mut model = self.acquire(deadline).await?;
+        let response = self
+            .until(deadline, blocking(model.get().completion(request)))
+            .await?;
+        Ok(response?)
+    }
+
+    /// Starts a streaming completion, the timeout doesn't apply to reading the stream.
+    ///
+    /// The replica is kept by the stream, and returned to the pool when the stream ends
+    /// or is dropped.
+    pub(crate) async fn completion_stream(
+        &self,
+        request: Request,
+    ) -> Result<ResponseStream, SchedulerError> {
+        let deadline = self.deadline();
+        let mut model = self.acquire(deadline).await?;
+        let response = self
+            .until(deadline, blocking(model.get().completion_stream(request)))
+            .await??;
+        let mut model = Some(model);
+        let release = stream::poll_fn(move |_| {
+            model.take();
+            Poll::Ready(None)
+        });
+        Ok(Box::pin(response.chain(release)))
+    }
+
+    async fn acquire(&self, deadline: Option<Instant>) -> Result<ModelGuard<M>, SchedulerError> {
+        let queued = self
+            .queue
+            .clone()
+            .try_acquire_owned()
+            .map_err(|_| SchedulerError::QueueFull)?;
+        // The semaphores are never closed.
+        let running = self
+            .until(deadline, self.running.clone().acquire_owned())
+            .await?
+            .expect("scheduler semaphore closed");
+        let model = self
+            .models
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .pop()
+            .expect("a model replica for each running request");
+        Ok(ModelGuard {
+            model: Some(model),
+            models: self.models.clone(),
+            _permits: (queued, running),
+        })
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.timeout.map(|timeout| Instant::now() + timeout)
+    }
+
+    async fn until<F: Future>(
+        &self,
+        deadline: Option<Instant>,
+        future: F,
+    ) -> Result<F::Output, SchedulerError> {
+        match deadline {
+            Some(deadline) => tokio::time::timeout_at(deadline, future)
+                .await
+                .map_err(|_| SchedulerError::Timeout(self.timeout.unwrap_or_default())),
+            None => Ok(future.await),
+        }
+    }
+}
+
+/// Runs a model call, blocking the worker thread in place on a multi-threaded runtime so
+/// that a synchronous engine doesn't starve the other tasks of the worker.
+async fn blocking<F: Future>(future: F) -> F::Output {
+    match Handle::current().runtime_flavor() {
+        RuntimeFlavor::MultiThread => {
+            tokio::task::block_in_place(|| Handle::current().block_on(future))
+        }
+        _ => future.await,
+    }
+}
+
+/// A model replica taken from the pool, returned to it when dropped.
+struct ModelGuard<M> {
+    model: Option<M>,
+    models: Arc<Mutex<Vec<M>>>,
+    _permits: (OwnedSemaphorePermit, OwnedSemaphorePermit),
+}
+
+impl<M> ModelGuard<M> {
+    fn get(&mut self) -> &mut M {
+        // Only taken in `drop`.
+        self.model.as_mut().unwrap()
+    }
+}
+
+impl<M> Drop for ModelGuard<M> {
+    fn drop(&mut self) {
+        if let Some(model) = self.model.take() {
+            self.models
+                .lock()
+                .unwrap_or_else(|err| err.into_inner())
+                .push(model);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alith_core::chat::{
+        CompletionChunk, ResponseContent, ResponseTokenUsage, ResponseToolCalls, TokenUsage,
+        ToolCall,
+    };
+
+    struct SlowModel;
+
+    struct Text(String);
+
+    impl ResponseContent for Text {
+        fn content(&self) -> String {
+            self.0.clone()
+        }
+    }
+
+    impl ResponseToolCalls for Text {
+        fn toolcalls(&self) -> Vec<ToolCall> {
+            Vec::new()
+        }
+    }
+
+    impl ResponseTokenUsage for Text {
+        fn token_usage(&self) -> TokenUsage {
+            TokenUsage {
+                tokens_cached: None,
+                prompt_tokens: 0,
+                completion_tokens: 0,
+                total_tokens: 0,
+            }
+        }
+    }
+
+    impl Completion for SlowModel {
+        type Response = Text;
+
+        async fn completion(&mut self, request: Request) -> Result<Text, CompletionError> {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            Ok(Text(request.prompt))
+        }
+
+        async fn completion_stream(
+            &mut self,
+            request: Request,
+        ) -> Result<ResponseStream, CompletionError> {
+            Ok(Box::pin(stream::iter([Ok(CompletionChunk::Text(
+                request.prompt,
+            ))])))
+        }
+    }
+
+    fn request(prompt: &str) -> Request {
+        Request::new(prompt.to_string(), String::new())
+    }
+
+    #[tokio::test]
+    async fn test_rejects_requests_beyond_the_queue() {
+        let scheduler = Scheduler::new(vec![SlowModel], None, 1, None);
+        let (first, second, third) = tokio::join!(
+            scheduler.completion(request("1")),
+            scheduler.completion(request("2")),
+            scheduler.completion(request("3")),
+        );
+        assert_eq!(first.unwrap().content(), "1");
+        assert_eq!(second.unwrap().content(), "2");
+        assert!(matches!(third, Err(SchedulerError::QueueFull)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_runs_replicas_concurrently() {
+        let scheduler = Scheduler::new(vec![SlowModel, SlowModel], None, 0, None);
+        // The clock is paused and only advances to the next timer, so the two requests
+        // take 50ms on two replicas and 100ms on one.
+        let start = Instant::now();
+        let (first, second) = tokio::join!(
+            scheduler.completion(request("1")),
+            scheduler.completion(request("2")),
+        );
+        assert!(first.is_ok() && second.is_ok());
+        assert_eq!(start.elapsed(), Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn test_streams_keep_their_replica() {
+        let scheduler = Scheduler::new(vec![SlowModel], None, 1, None);
+        let first = scheduler.completion_stream(request("1")).await.unwrap();
+        // The only replica is still streaming the first response, so the second request
+        // waits in the queue and the third one is rejected.
+        let queued = scheduler.completion_stream(request("2"));
+        tokio::pin!(queued);
+        assert!(futures_util::poll!(&mut queued).is_pending());
+        assert!(matches!(
+            scheduler.completion_stream(request("3")).await,
+            Err(SchedulerError::QueueFull)
+        ));
+        let chunks: Vec<_> = first.collect().await;
+        assert_eq!(chunks.len(), 1);
+        let second: Vec<_> = queued.await.unwrap().collect().await;
+        assert_eq!(second.len(), 1);
+
+        // A dropped stream returns its replica too.
+        drop(scheduler.completion_stream(request("4")).await.unwrap());
+        assert!(scheduler.completion(request("5")).await.is_ok());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_runs_blocking_on_a_multi_threaded_runtime() {
+        let scheduler = Scheduler::new(vec![SlowModel], None, 0, None);
+        assert_eq!(
+            scheduler.completion(request("1")).await.unwrap().content(),
+            "1"
+        );
+        let chunks: Vec<_> = scheduler
+            .completion_stream(request("2"))
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        assert_eq!(chunks.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_times_out_and_returns_the_replica() {
+        let scheduler = Scheduler::new(vec![SlowModel], None, 0, Some(Duration::from_millis(10)));
+        assert!(matches!(
+            scheduler.completion(request("1")).await,
+            Err(SchedulerError::Timeout(_))
+        ));
+        assert!(scheduler.completion_stream(request("2")).await.is_ok());
+    }
+}
@@ -18,8 +18,9 @@ pub use llms::{
 };
 pub use requests::{
     completion::{
-        CompletionError, CompletionFinishReason, CompletionRequest, CompletionResponse,
-        TimingUsage, TokenUsage, ToolChoice, ToolDefinition,
+        CompletionChunk, CompletionError, CompletionFinishReason, CompletionRequest,
+        CompletionResponse, CompletionStream, JsonSchemaFormat, ResponseFormat, StreamedCompletion,
+        TimingUsage, TokenUsage, ToolCallChunk, ToolChoice, ToolDefinition,
     },
     embeddings::{EmbeddingsData, EmbeddingsError, EmbeddingsRequest, EmbeddingsResponse},
     logit_bias::{LogitBias, LogitBiasTrait},
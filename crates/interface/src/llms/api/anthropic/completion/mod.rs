@@ -1,4 +1,7 @@
 mod req;
 mod res;
+mod stream;
 pub use req::AnthropicCompletionRequest;
 pub use res::AnthropicCompletionResponse;
+pub use stream::AnthropicStreamEvent;
+pub(crate) use stream::anthropic_completion_stream;
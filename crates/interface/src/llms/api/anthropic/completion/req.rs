@@ -1,4 +1,6 @@
-use crate::requests::completion::{error::CompletionError, request::CompletionRequest};
+use crate::requests::completion::{
+    error::CompletionError, request::CompletionRequest, tool::ToolCall,
+};
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Serialize, Default, Debug, Deserialize)]
@@ -52,6 +54,10 @@ pub struct AnthropicCompletionRequest {
     /// The tools for the request, default: None
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tools: Option<Vec<Tool>>,
+
+    /// Whether to incrementally stream the response using server-sent events.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream: Option<bool>,
 }
 
 impl AnthropicCompletionRequest {
@@ -69,10 +75,62 @@ impl AnthropicCompletionRequest {
                     })?;
 
                     match role.as_str() {
-                        "user" | "assistant" => messages.push(CompletionRequestMessage {
+                        "user" => messages.push(CompletionRequestMessage {
                             role: role.to_string(),
-                            content: content.to_string(),
+                            content: CompletionRequestContent::Text(content.to_string()),
                         }),
+                        "assistant" => {
+                            let content = match m.get("tool_calls") {
+                                Some(tool_calls) => {
+                                    let tool_calls: Vec<ToolCall> =
+                                        serde_json::from_str(tool_calls)?;
+                                    let mut blocks = Vec::new();
+                                    if !content.is_empty() {
+                                        blocks.push(CompletionRequestContentBlock::Text {
+                                            text: content.to_string(),
+                                        });
+                                    }
+                                    for call in tool_calls {
+                                        blocks.push(CompletionRequestContentBlock::ToolUse {
+                                            id: call.id,
+                                            name: call.function.name,
+                                            input: serde_json::from_str(&call.function.arguments)?,
+                                        });
+                                    }
+                                    CompletionRequestContent::Blocks(blocks)
+                                }
+                                None => CompletionRequestContent::Text(content.to_string()),
+                            };
+                            messages.push(CompletionRequestMessage {
+                                role: role.to_string(),
+                                content,
+                            })
+                        }
+                        "tool" => {
+                            let block = CompletionRequestContentBlock::ToolResult {
+                                tool_use_id: m
+                                    .get("tool_call_id")
+                                    .ok_or_else(|| {
+                                        CompletionError::RequestBuilderError(
+                                            "Tool call id not found".to_string(),
+                                        )
+                                    })?
+                                    .to_string(),
+                                content: content.to_string(),
+                            };
+                            // Tool results are sent back as user content blocks, and
+                            // consecutive results share a single user message.
+                            match messages.last_mut() {
+                                Some(CompletionRequestMessage {
+                                    role,
+                                    content: CompletionRequestContent::Blocks(blocks),
+                                }) if *role == "user" => blocks.push(block),
+                                _ => messages.push(CompletionRequestMessage {
+                                    role: "user".to_string(),
+                                    content: CompletionRequestContent::Blocks(vec![block]),
+                                }),
+                            }
+                        }
                         "system" => system_prompt = Some(content.to_string()),
                         _ => {
                             return Err(CompletionError::RequestBuilderError(format!(
@@ -116,6 +174,15 @@ impl AnthropicCompletionRequest {
             } else {
                 None
             },
+            stream: None,
+        })
+    }
+
+    /// Creates a request that streams the response back as server-sent events.
+    pub fn new_stream(req: &CompletionRequest) -> crate::Result<Self, CompletionError> {
+        Ok(AnthropicCompletionRequest {
+            stream: Some(true),
+            ..Self::new(req)?
         })
     }
 }
@@ -149,7 +216,34 @@ fn top_p(value: Option<f32>) -> crate::Result<Option<f32>, CompletionError> {
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct CompletionRequestMessage {
     pub role: String,
-    pub content: String,
+    pub content: CompletionRequestContent,
+}
+
+/// The content of an input message, either a single string or a list of content blocks.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum CompletionRequestContent {
+    Text(String),
+    Blocks(Vec<CompletionRequestContentBlock>),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CompletionRequestContentBlock {
+    Text {
+        text: String,
+    },
+    /// A tool call previously made by the assistant.
+    ToolUse {
+        id: String,
+        name: String,
+        input: serde_json::Value,
+    },
+    /// The result of a tool call, sent back in a user message.
+    ToolResult {
+        tool_use_id: String,
+        content: String,
+    },
 }
 
 #[derive(Clone, Serialize, Default, Debug, Deserialize)]
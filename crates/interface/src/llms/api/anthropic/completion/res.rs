@@ -34,10 +34,30 @@ impl CompletionResponse {
 
         let content = res
             .content
-            .first()
-            .ok_or_else(|| CompletionError::ResponseContentEmpty)?
-            .text()
-            .to_owned();
+            .iter()
+            .map(|c| c.text())
+            .collect::<Vec<_>>()
+            .join("");
+        let tool_calls = res
+            .content
+            .iter()
+            .filter_map(|c| match c {
+                CompletionContent::ToolUse {
+                    name,
+                    input,
+                    id,
+                    r#type: _,
+                } => Some(serde_json::to_string(input).map(|arguments| ToolCall {
+                    id: id.to_owned(),
+                    r#type: "function".to_owned(),
+                    function: Function {
+                        name: name.to_owned(),
+                        arguments,
+                    },
+                })),
+                _ => None,
+            })
+            .collect::<Result<Vec<_>, _>>()?;
 
         Ok(Self {
             id: res.id.to_owned(),
@@ -49,24 +69,10 @@ impl CompletionResponse {
             generation_settings: GenerationSettings::new_from_anthropic(req, &res),
             timing_usage: TimingUsage::new_from_generic(req.start_time),
             token_usage: TokenUsage::new_from_anthropic(&res),
-            tool_calls: match res.content.as_slice() {
-                [
-                    CompletionContent::ToolUse {
-                        name,
-                        input,
-                        id,
-                        r#type,
-                    },
-                    ..,
-                ] => Some(vec![ToolCall {
-                    id: id.to_owned(),
-                    r#type: r#type.to_owned(),
-                    function: Function {
-                        name: name.to_owned(),
-                        arguments: serde_json::to_string(input)?,
-                    },
-                }]),
-                _ => None,
+            tool_calls: if tool_calls.is_empty() {
+                None
+            } else {
+                Some(tool_calls)
             },
         })
     }
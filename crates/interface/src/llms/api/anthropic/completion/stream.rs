@@ -0,0 +1,245 @@
+use crate::llms::api::{
+    error::{ApiError, ClientError, map_deserialization_error},
+    sse::SseStream,
+};
+use crate::requests::completion::*;
+use futures::{StreamExt, stream};
+use serde::Deserialize;
+
+/// Converts the server-sent events of an Anthropic streaming response into completion chunks.
+pub(crate) fn anthropic_completion_stream(events: SseStream) -> CompletionStream {
+    Box::pin(
+        events
+            .scan(0u32, |input_tokens, event| {
+                let chunks = event
+                    .map_err(CompletionError::ClientError)
+                    .and_then(|event| AnthropicStreamEvent::parse(&event.data))
+                    .and_then(|event| event.chunks(input_tokens));
+                std::future::ready(Some(chunks))
+            })
+            .flat_map(|chunks| {
+                stream::iter(match chunks {
+                    Ok(chunks) => chunks.into_iter().map(Ok).collect(),
+                    Err(e) => vec![Err(e)],
+                })
+            }),
+    )
+}
+
+/// An event of an Anthropic streaming response.
+///
+/// See [streaming messages](https://docs.anthropic.com/en/api/messages-streaming) for details.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AnthropicStreamEvent {
+    MessageStart {
+        message: StreamMessage,
+    },
+    ContentBlockStart {
+        index: usize,
+        content_block: StreamContentBlock,
+    },
+    ContentBlockDelta {
+        index: usize,
+        delta: StreamContentDelta,
+    },
+    ContentBlockStop {
+        index: usize,
+    },
+    MessageDelta {
+        usage: StreamUsage,
+    },
+    MessageStop,
+    Ping,
+    Error {
+        error: ApiError,
+    },
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct StreamMessage {
+    pub id: String,
+    pub usage: StreamUsage,
+}
+
+/// Cumulative token counts, `input_tokens` is only sent in the `message_start` event.
+#[derive(Debug, Deserialize, Clone)]
+pub struct StreamUsage {
+    #[serde(default)]
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StreamContentBlock {
+    Text {
+        text: String,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+    },
+    #[serde(other)]
+    Unsupported,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StreamContentDelta {
+    TextDelta {
+        text: String,
+    },
+    InputJsonDelta {
+        partial_json: String,
+    },
+    #[serde(other)]
+    Unsupported,
+}
+
+impl AnthropicStreamEvent {
+    fn parse(data: &str) -> Result<Self, CompletionError> {
+        Ok(
+            serde_json::from_str(data)
+                .map_err(|e| map_deserialization_error(e, data.as_bytes()))?,
+        )
+    }
+
+    fn chunks(self, input_tokens: &mut u32) -> Result<Vec<CompletionChunk>, CompletionError> {
+        let chunk = match self {
+            AnthropicStreamEvent::MessageStart { message } => {
+                *input_tokens = message.usage.input_tokens;
+                None
+            }
+            AnthropicStreamEvent::ContentBlockStart {
+                index,
+                content_block,
+            } => match content_block {
+                StreamContentBlock::Text { text } if !text.is_empty() => {
+                    Some(CompletionChunk::Text(text))
+                }
+                StreamContentBlock::ToolUse { id, name } => {
+                    Some(CompletionChunk::ToolCall(ToolCallChunk {
+                        index,
+                        id: Some(id),
+                        name: Some(name),
+                        arguments: String::new(),
+                    }))
+                }
+                _ => None,
+            },
+            AnthropicStreamEvent::ContentBlockDelta { index, delta } => match delta {
+                StreamContentDelta::TextDelta { text } => Some(CompletionChunk::Text(text)),
+                StreamContentDelta::InputJsonDelta { partial_json } => {
+                    Some(CompletionChunk::ToolCall(ToolCallChunk {
+                        index,
+                        id: None,
+                        name: None,
+                        arguments: partial_json,
+                    }))
+                }
+                StreamContentDelta::Unsupported => None,
+            },
+            AnthropicStreamEvent::MessageDelta { usage } => {
+                Some(CompletionChunk::Usage(TokenUsage {
+                    tokens_cached: None,
+                    prompt_tokens: *input_tokens,
+                    completion_tokens: usage.output_tokens,
+                    total_tokens: *input_tokens + usage.output_tokens,
+                }))
+            }
+            AnthropicStreamEvent::Error { error } => {
+                return Err(ClientError::ApiError(error).into());
+            }
+            AnthropicStreamEvent::ContentBlockStop { .. }
+            | AnthropicStreamEvent::MessageStop
+            | AnthropicStreamEvent::Ping => None,
+        };
+        Ok(chunk.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llms::api::sse::sse_stream;
+
+    /// A response streaming some text and a tool use, with the usage in `message_delta`.
+    const FIXTURE: &str = r#"event: message_start
+data: {"type":"message_start","message":{"id":"msg_1","type":"message","role":"assistant","content":[],"model":"claude-sonnet-4-0","usage":{"input_tokens":25,"output_tokens":1}}}
+
+event: content_block_start
+data: {"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}
+
+event: ping
+data: {"type":"ping"}
+
+event: content_block_delta
+data: {"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Let me "}}
+
+event: content_block_delta
+data: {"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"check."}}
+
+event: content_block_stop
+data: {"type":"content_block_stop","index":0}
+
+event: content_block_start
+data: {"type":"content_block_start","index":1,"content_block":{"type":"tool_use","id":"toolu_1","name":"weather","input":{}}}
+
+event: content_block_delta
+data: {"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":""}}
+
+event: content_block_delta
+data: {"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"{\"city\": "}}
+
+event: content_block_delta
+data: {"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"\"Paris\"}"}}
+
+event: content_block_stop
+data: {"type":"content_block_stop","index":1}
+
+event: message_delta
+data: {"type":"message_delta","delta":{"stop_reason":"tool_use","stop_sequence":null},"usage":{"output_tokens":40}}
+
+event: message_stop
+data: {"type":"message_stop"}
+
+"#;
+
+    async fn collect(fixture: &'static str) -> Vec<Result<CompletionChunk, CompletionError>> {
+        let bytes = stream::iter([Ok::<_, reqwest::Error>(bytes::Bytes::from(fixture))]);
+        anthropic_completion_stream(sse_stream(bytes))
+            .collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_content_block_deltas_are_accumulated() {
+        let mut completion = StreamedCompletion::new();
+        for chunk in collect(FIXTURE).await {
+            completion.push(&chunk.unwrap());
+        }
+        assert_eq!(completion.content, "Let me check.");
+        let calls = completion.tool_calls();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].id, "toolu_1");
+        assert_eq!(calls[0].function.name, "weather");
+        assert_eq!(calls[0].function.arguments, r#"{"city": "Paris"}"#);
+        // The input tokens of `message_start` are added to the output tokens of `message_delta`.
+        let usage = completion.token_usage.unwrap();
+        assert_eq!(usage.prompt_tokens, 25);
+        assert_eq!(usage.completion_tokens, 40);
+        assert_eq!(usage.total_tokens, 65);
+    }
+
+    #[tokio::test]
+    async fn test_error_event_fails_the_stream() {
+        let chunks = collect(
+            "event: error\ndata: {\"type\":\"error\",\"error\":{\"type\":\"overloaded_error\",\"message\":\"Overloaded\"}}\n\n",
+        )
+        .await;
+        assert_eq!(chunks.len(), 1);
+        let err = chunks[0].as_ref().unwrap_err();
+        assert!(err.to_string().contains("Overloaded"), "{err}");
+    }
+}
@@ -7,10 +7,11 @@ use super::{
 };
 use crate::requests::completion::{
     error::CompletionError, request::CompletionRequest, response::CompletionResponse,
+    stream::CompletionStream,
 };
 use alith_devices::logging::LoggingConfig;
 use alith_models::api_model::ApiLLMModel;
-use completion::AnthropicCompletionRequest;
+use completion::{AnthropicCompletionRequest, anthropic_completion_stream};
 use reqwest::header::HeaderMap;
 use secrecy::{ExposeSecret, SecretString};
 
@@ -41,13 +42,32 @@ impl AnthropicBackend {
     ) -> crate::Result<CompletionResponse, CompletionError> {
         match self
             .client
-            .post("/messages", AnthropicCompletionRequest::new(request)?)
+            .post(
+                "/messages",
+                AnthropicCompletionRequest::new(request)?,
+                request.cancellation.as_ref(),
+            )
             .await
         {
             Err(e) => Err(CompletionError::ClientError(e)),
             Ok(res) => Ok(CompletionResponse::new_from_anthropic(request, res)?),
         }
     }
+
+    pub(crate) async fn completion_stream_request(
+        &self,
+        request: &CompletionRequest,
+    ) -> crate::Result<CompletionStream, CompletionError> {
+        let events = self
+            .client
+            .post_stream(
+                "/messages",
+                AnthropicCompletionRequest::new_stream(request)?,
+                request.cancellation.as_ref(),
+            )
+            .await?;
+        Ok(anthropic_completion_stream(events))
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -2,9 +2,11 @@ use super::error::map_serialization_error;
 use super::{
     config::ApiConfigTrait,
     error::{ClientError, WrappedError, map_deserialization_error},
+    sse::{SseStream, sse_stream},
 };
 use bytes::Bytes;
 use serde::{Serialize, de::DeserializeOwned};
+use tokio_util::sync::CancellationToken;
 
 #[derive(Debug, Clone)]
 pub struct ApiClient<C: ApiConfigTrait> {
@@ -25,7 +27,14 @@ impl<C: ApiConfigTrait> ApiClient<C> {
     }
 
     /// Make a POST request to {path} and deserialize the response body
-    pub async fn post<I, O>(&self, path: &str, request: I) -> Result<O, ClientError>
+    ///
+    /// The request is aborted with [`ClientError::Cancelled`] when the token is cancelled.
+    pub async fn post<I, O>(
+        &self,
+        path: &str,
+        request: I,
+        cancellation: Option<&CancellationToken>,
+    ) -> Result<O, ClientError>
     where
         I: Serialize + std::fmt::Debug,
         O: DeserializeOwned,
@@ -43,7 +52,7 @@ impl<C: ApiConfigTrait> ApiClient<C> {
                 .body(serialized_request);
             Ok(request_builder.build()?)
         };
-        self.execute(request_maker).await
+        self.execute(request_maker, cancellation).await
     }
 
     /// Make a GET request to {path} and deserialize the response body
@@ -61,7 +70,37 @@ impl<C: ApiConfigTrait> ApiClient<C> {
             // crate::trace!("Serialized post request: {:?}", request_builder); // This will log API keys!
             Ok(request_builder.build()?)
         };
-        self.execute(request_maker).await
+        self.execute(request_maker, None).await
+    }
+
+    /// Make a streaming POST request to {path} and decode the response body as server-sent events
+    ///
+    /// The request is aborted with [`ClientError::Cancelled`] when the token is cancelled
+    /// before the response starts.
+    pub async fn post_stream<I>(
+        &self,
+        path: &str,
+        request: I,
+        cancellation: Option<&CancellationToken>,
+    ) -> Result<SseStream, ClientError>
+    where
+        I: Serialize + std::fmt::Debug,
+    {
+        let request_maker = || async {
+            let serialized_request =
+                serde_json::to_string(&request).map_err(map_serialization_error)?;
+            crate::trace!("Serialized post stream request: {}", serialized_request);
+            let request_builder = self
+                .http_client
+                .post(self.config.url(path))
+                .headers(self.config.headers())
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header(reqwest::header::ACCEPT, "text/event-stream")
+                .body(serialized_request);
+            Ok(request_builder.build()?)
+        };
+        let response = self.execute_response(request_maker, cancellation).await?;
+        Ok(sse_stream(response.bytes_stream()))
     }
 
     /// Execute a HTTP request and retry on rate limit
@@ -69,14 +108,38 @@ impl<C: ApiConfigTrait> ApiClient<C> {
     /// request_maker serves one purpose: to be able to create request again
     /// to retry API call after getting rate limited. request_maker is async because
     /// reqwest::multipart::Form is created by async calls to read files for uploads.
-    async fn execute_raw<M, Fut>(&self, request_maker: M) -> Result<Bytes, ClientError>
+    ///
+    /// Cancelling the token aborts the request, including the retries and the body download.
+    async fn execute_raw<M, Fut>(
+        &self,
+        request_maker: M,
+        cancellation: Option<&CancellationToken>,
+    ) -> Result<Bytes, ClientError>
+    where
+        M: Fn() -> Fut,
+        Fut: core::future::Future<Output = Result<reqwest::Request, ClientError>>,
+    {
+        cancellable(cancellation, async {
+            let response = self.execute_response(request_maker, None).await?;
+            response.bytes().await.map_err(ClientError::Reqwest)
+        })
+        .await
+    }
+
+    /// Execute a HTTP request, retry on rate limit and return the successful response
+    /// without reading its body.
+    async fn execute_response<M, Fut>(
+        &self,
+        request_maker: M,
+        cancellation: Option<&CancellationToken>,
+    ) -> Result<reqwest::Response, ClientError>
     where
         M: Fn() -> Fut,
         Fut: core::future::Future<Output = Result<reqwest::Request, ClientError>>,
     {
         let client = self.http_client.clone();
 
-        backoff::future::retry(self.backoff.clone(), || async {
+        let retry = backoff::future::retry(self.backoff.clone(), || async {
             let request = request_maker().await.map_err(backoff::Error::Permanent)?;
             let response = client
                 .execute(request)
@@ -85,46 +148,46 @@ impl<C: ApiConfigTrait> ApiClient<C> {
                 .map_err(backoff::Error::Permanent)?;
 
             let status = response.status();
+            if status.is_success() {
+                return Ok(response);
+            }
+
             let bytes = response
                 .bytes()
                 .await
                 .map_err(ClientError::Reqwest)
                 .map_err(backoff::Error::Permanent)?;
 
-            // Deserialize response body from either error object or actual response object
-            if !status.is_success() {
-                let wrapped_error: WrappedError = serde_json::from_slice(bytes.as_ref())
-                    .map_err(|e| map_deserialization_error(e, bytes.as_ref()))
-                    .map_err(backoff::Error::Permanent)?;
-
-                if status.as_u16() == 429
-                    // API returns 429 also when:
-                    // "You exceeded your current quota, please check your plan and billing details."
-                    && wrapped_error.error.r#type != Some("insufficient_quota".to_string())
-                {
-                    // Rate limited retry...
-                    tracing::warn!("Rate limited: {}", wrapped_error.error.message);
-                    return Err(backoff::Error::Transient {
-                        err: ClientError::ApiError(wrapped_error.error),
-                        retry_after: None,
-                    });
-                } else if status.as_u16() == 503 {
-                    return Err(backoff::Error::Transient {
-                        err: ClientError::ServiceUnavailable {
-                            message: wrapped_error.error.message,
-                        },
-                        retry_after: None,
-                    });
-                } else {
-                    return Err(backoff::Error::Permanent(ClientError::ApiError(
-                        wrapped_error.error,
-                    )));
-                }
-            }
+            // Deserialize response body from the error object
+            let wrapped_error: WrappedError = serde_json::from_slice(bytes.as_ref())
+                .map_err(|e| map_deserialization_error(e, bytes.as_ref()))
+                .map_err(backoff::Error::Permanent)?;
 
-            Ok(bytes)
-        })
-        .await
+            if status.as_u16() == 429
+                // API returns 429 also when:
+                // "You exceeded your current quota, please check your plan and billing details."
+                && wrapped_error.error.r#type != Some("insufficient_quota".to_string())
+            {
+                // Rate limited retry...
+                tracing::warn!("Rate limited: {}", wrapped_error.error.message);
+                Err(backoff::Error::Transient {
+                    err: ClientError::ApiError(wrapped_error.error),
+                    retry_after: None,
+                })
+            } else if status.as_u16() == 503 {
+                Err(backoff::Error::Transient {
+                    err: ClientError::ServiceUnavailable {
+                        message: wrapped_error.error.message,
+                    },
+                    retry_after: None,
+                })
+            } else {
+                Err(backoff::Error::Permanent(ClientError::ApiError(
+                    wrapped_error.error,
+                )))
+            }
+        });
+        cancellable(cancellation, retry).await
     }
 
     /// Execute a HTTP request and retry on rate limit
@@ -132,13 +195,17 @@ impl<C: ApiConfigTrait> ApiClient<C> {
     /// request_maker serves one purpose: to be able to create request again
     /// to retry API call after getting rate limited. request_maker is async because
     /// reqwest::multipart::Form is created by async calls to read files for uploads.
-    async fn execute<O, M, Fut>(&self, request_maker: M) -> Result<O, ClientError>
+    async fn execute<O, M, Fut>(
+        &self,
+        request_maker: M,
+        cancellation: Option<&CancellationToken>,
+    ) -> Result<O, ClientError>
     where
         O: DeserializeOwned,
         M: Fn() -> Fut,
         Fut: core::future::Future<Output = Result<reqwest::Request, ClientError>>,
     {
-        let bytes = self.execute_raw(request_maker).await?;
+        let bytes = self.execute_raw(request_maker, cancellation).await?;
 
         // Deserialize once into a generic Value
         let value: serde_json::Value =
@@ -155,3 +222,17 @@ impl<C: ApiConfigTrait> ApiClient<C> {
         Ok(response)
     }
 }
+
+/// Runs the request until it completes or the token is cancelled, dropping it on cancellation.
+async fn cancellable<T>(
+    cancellation: Option<&CancellationToken>,
+    request: impl core::future::Future<Output = Result<T, ClientError>>,
+) -> Result<T, ClientError> {
+    match cancellation {
+        Some(cancellation) => tokio::select! {
+            _ = cancellation.cancelled() => Err(ClientError::Cancelled),
+            result = request => result,
+        },
+        None => request.await,
+    }
+}
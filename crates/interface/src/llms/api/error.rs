@@ -24,6 +24,9 @@ pub enum ClientError {
     /// or when builder fails to build request before making API call
     #[error("invalid args: {0}")]
     InvalidArgument(String),
+    /// The request was cancelled before the API answered
+    #[error("the request was cancelled")]
+    Cancelled,
 }
 
 /// Wrapper to deserialize the error object nested in "error" JSON key
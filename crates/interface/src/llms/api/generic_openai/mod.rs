@@ -1,11 +1,12 @@
 use super::{
     client::ApiClient,
     config::{ApiConfig, ApiConfigTrait},
-    openai::completion::OpenAICompletionRequest,
+    openai::completion::{OpenAICompletionRequest, openai_completion_stream},
 };
 use crate::requests::{
     completion::{
         error::CompletionError, request::CompletionRequest, response::CompletionResponse,
+        stream::CompletionStream,
     },
     embeddings::{EmbeddingsError, EmbeddingsRequest, EmbeddingsResponse},
 };
@@ -41,6 +42,7 @@ impl GenericApiBackend {
             .post(
                 &self.client.config.completion_path,
                 OpenAICompletionRequest::new(request)?,
+                request.cancellation.as_ref(),
             )
             .await
         {
@@ -49,6 +51,21 @@ impl GenericApiBackend {
         }
     }
 
+    pub(crate) async fn completion_stream_request(
+        &self,
+        request: &CompletionRequest,
+    ) -> crate::Result<CompletionStream, CompletionError> {
+        let events = self
+            .client
+            .post_stream(
+                &self.client.config.completion_path,
+                OpenAICompletionRequest::new_stream(request)?,
+                request.cancellation.as_ref(),
+            )
+            .await?;
+        Ok(openai_completion_stream(events))
+    }
+
     pub(crate) async fn embeddings_request(
         &self,
         request: &EmbeddingsRequest,
@@ -61,6 +78,7 @@ impl GenericApiBackend {
                     "input": request.input,
                     "model": request.model,
                 }),
+                None,
             )
             .await
         {
@@ -1,8 +1,16 @@
 mod req;
 mod res;
+mod stream;
 
-pub use req::{CompletionRequestMessage, OpenAICompletionRequest, OpenAIToolDefinition};
+pub use req::{
+    CompletionRequestMessage, OpenAICompletionRequest, OpenAIToolDefinition, StreamOptions,
+};
 pub use res::{
     ChatChoice, ChatCompletionResponseMessage, CompletionUsage, FinishReason,
     OpenAICompletionResponse, Role,
 };
+pub(crate) use stream::openai_completion_stream;
+pub use stream::{
+    ChatChunkChoice, ChatCompletionStreamDelta, FunctionStreamDelta, OpenAICompletionChunk,
+    ToolCallStreamDelta,
+};
@@ -1,4 +1,7 @@
-use crate::requests::{completion::*, stop_sequence::StopSequences};
+use crate::requests::{
+    completion::{tool::ToolCall, *},
+    stop_sequence::StopSequences,
+};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
@@ -69,6 +72,20 @@ pub struct OpenAICompletionRequest {
     /// Whether to stream back partial progress.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stream: Option<bool>, // default: false
+
+    /// Options for the streaming response, only used when `stream` is true.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<StreamOptions>,
+
+    /// The format of the output, e.g. a JSON schema, default: None
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<ResponseFormat>,
+}
+
+#[derive(Clone, Serialize, Debug, Deserialize)]
+pub struct StreamOptions {
+    /// Whether to send the token usage in a last chunk before `data: [DONE]`.
+    pub include_usage: bool,
 }
 
 #[derive(Clone, Serialize, Debug, Deserialize)]
@@ -120,6 +137,19 @@ impl OpenAICompletionRequest {
                 None
             },
             stream: None,
+            stream_options: None,
+            response_format: req.response_format.clone(),
+        })
+    }
+
+    /// Creates a request that streams the response back as server-sent events.
+    pub fn new_stream(req: &CompletionRequest) -> crate::Result<Self, CompletionError> {
+        Ok(OpenAICompletionRequest {
+            stream: Some(true),
+            stream_options: Some(StreamOptions {
+                include_usage: true,
+            }),
+            ..Self::new(req)?
         })
     }
 }
@@ -127,13 +157,20 @@ impl OpenAICompletionRequest {
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct CompletionRequestMessage {
     pub role: String,
+    /// The text of the message. Array content is read by joining its text parts, and a
+    /// null or missing content, e.g. for assistant tool calls, is read as empty.
+    #[serde(default, deserialize_with = "deserialize_content")]
     pub content: String,
+    /// The tool calls generated by the model, only for assistant messages.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<ToolCall>>,
+    /// Tool call that this message is responding to, only for tool messages.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
 }
 
 impl CompletionRequestMessage {
-    pub fn new(
-        message: &std::collections::HashMap<String, String>,
-    ) -> crate::Result<Self, CompletionError> {
+    pub fn new(message: &HashMap<String, String>) -> crate::Result<Self, CompletionError> {
         let role = message
             .get("role")
             .ok_or_else(|| CompletionError::RequestBuilderError("Role not found".to_string()))?;
@@ -142,9 +179,35 @@ impl CompletionRequestMessage {
             .ok_or_else(|| CompletionError::RequestBuilderError("Content not found".to_string()))?;
 
         match role.as_str() {
-            "system" | "user" | "assistant" => Ok(CompletionRequestMessage {
+            "system" | "user" => Ok(CompletionRequestMessage {
                 role: role.to_string(),
                 content: content.to_string(),
+                tool_calls: None,
+                tool_call_id: None,
+            }),
+            "assistant" => Ok(CompletionRequestMessage {
+                role: role.to_string(),
+                content: content.to_string(),
+                tool_calls: message
+                    .get("tool_calls")
+                    .map(|tool_calls| serde_json::from_str(tool_calls))
+                    .transpose()?,
+                tool_call_id: None,
+            }),
+            "tool" => Ok(CompletionRequestMessage {
+                role: role.to_string(),
+                content: content.to_string(),
+                tool_calls: None,
+                tool_call_id: Some(
+                    message
+                        .get("tool_call_id")
+                        .ok_or_else(|| {
+                            CompletionError::RequestBuilderError(
+                                "Tool call id not found".to_string(),
+                            )
+                        })?
+                        .to_string(),
+                ),
             }),
             _ => Err(CompletionError::RequestBuilderError(format!(
                 "Role {} not supported",
@@ -154,6 +217,40 @@ impl CompletionRequestMessage {
     }
 }
 
+fn deserialize_content<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::de::Error;
+    use serde_json::Value;
+
+    match Option::<Value>::deserialize(deserializer)? {
+        None => Ok(String::new()),
+        Some(Value::String(text)) => Ok(text),
+        Some(Value::Array(parts)) => {
+            let mut texts = Vec::with_capacity(parts.len());
+            for part in parts {
+                match part.get("type").and_then(Value::as_str) {
+                    Some("text") => match part.get("text").and_then(Value::as_str) {
+                        Some(text) => texts.push(text.to_string()),
+                        None => return Err(D::Error::custom("text content part without text")),
+                    },
+                    Some(ty) => {
+                        return Err(D::Error::custom(format!(
+                            "unsupported content part type {ty}"
+                        )));
+                    }
+                    None => return Err(D::Error::custom("content part without type")),
+                }
+            }
+            Ok(texts.join("\n"))
+        }
+        Some(_) => Err(D::Error::custom(
+            "message content must be a string or an array of content parts",
+        )),
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 #[serde(untagged)]
 pub enum Stop {
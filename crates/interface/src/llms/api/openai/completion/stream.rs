@@ -0,0 +1,203 @@
+use super::{CompletionUsage, FinishReason, Role};
+use crate::llms::api::{
+    error::{ClientError, WrappedError, map_deserialization_error},
+    sse::SseStream,
+};
+use crate::requests::completion::*;
+use futures::{StreamExt, stream};
+use serde::{Deserialize, Serialize};
+
+/// Converts the server-sent events of an OpenAI compatible streaming response into
+/// completion chunks.
+pub(crate) fn openai_completion_stream(events: SseStream) -> CompletionStream {
+    Box::pin(
+        events
+            .take_while(|event| {
+                std::future::ready(!matches!(event, Ok(event) if event.data.trim() == "[DONE]"))
+            })
+            .flat_map(|event| {
+                let chunks = event
+                    .map_err(CompletionError::ClientError)
+                    .and_then(|event| OpenAICompletionChunk::parse(&event.data))
+                    .map(|chunk| chunk.chunks());
+                stream::iter(match chunks {
+                    Ok(chunks) => chunks.into_iter().map(Ok).collect(),
+                    Err(e) => vec![Err(e)],
+                })
+            }),
+    )
+}
+
+/// Represents a streamed chunk of a chat completion response.
+#[derive(Debug, Deserialize, Clone, PartialEq, Serialize)]
+pub struct OpenAICompletionChunk {
+    /// A unique identifier for the chat completion. Each chunk has the same ID.
+    pub id: String,
+    /// The object type, which is always `chat.completion.chunk`.
+    #[serde(default)]
+    pub object: String,
+    /// The Unix timestamp (in seconds) of when the chat completion was created. Each chunk has the same timestamp.
+    #[serde(default)]
+    pub created: u32,
+    /// The model used for the chat completion.
+    #[serde(default)]
+    pub model: String,
+    /// A list of chat completion choices. Empty in the last chunk carrying the usage.
+    #[serde(default)]
+    pub choices: Vec<ChatChunkChoice>,
+    /// Usage statistics, only sent in the last chunk when `stream_options.include_usage` is set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<CompletionUsage>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct ChatChunkChoice {
+    /// The index of the choice in the list of choices.
+    pub index: u32,
+    pub delta: ChatCompletionStreamDelta,
+    pub finish_reason: Option<FinishReason>,
+}
+
+/// A chat completion delta generated by streamed model responses.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Default)]
+pub struct ChatCompletionStreamDelta {
+    /// The role of the author of this message, only sent in the first chunk.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub role: Option<Role>,
+    /// The contents of the chunk message.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    /// The tool call fragments of the chunk message.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<ToolCallStreamDelta>>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct ToolCallStreamDelta {
+    pub index: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// The type of the tool, only sent with the first fragment of a call.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub r#type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub function: Option<FunctionStreamDelta>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct FunctionStreamDelta {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arguments: Option<String>,
+}
+
+impl OpenAICompletionChunk {
+    fn parse(data: &str) -> Result<Self, CompletionError> {
+        let value: serde_json::Value = serde_json::from_str(data)
+            .map_err(|e| map_deserialization_error(e, data.as_bytes()))?;
+        if value.get("error").is_some() {
+            let wrapped_error: WrappedError = serde_json::from_value(value)
+                .map_err(|e| map_deserialization_error(e, data.as_bytes()))?;
+            return Err(ClientError::ApiError(wrapped_error.error).into());
+        }
+        Ok(serde_json::from_value(value)
+            .map_err(|e| map_deserialization_error(e, data.as_bytes()))?)
+    }
+
+    fn chunks(self) -> Vec<CompletionChunk> {
+        let mut chunks = Vec::new();
+        if let Some(choice) = self.choices.into_iter().next() {
+            if let Some(content) = choice.delta.content.filter(|c| !c.is_empty()) {
+                chunks.push(CompletionChunk::Text(content));
+            }
+            for call in choice.delta.tool_calls.unwrap_or_default() {
+                let (name, arguments) = match call.function {
+                    Some(function) => (function.name, function.arguments.unwrap_or_default()),
+                    None => (None, String::new()),
+                };
+                chunks.push(CompletionChunk::ToolCall(ToolCallChunk {
+                    index: call.index,
+                    id: call.id,
+                    name,
+                    arguments,
+                }));
+            }
+        }
+        if let Some(usage) = self.usage {
+            chunks.push(CompletionChunk::Usage(TokenUsage {
+                tokens_cached: None,
+                prompt_tokens: usage.prompt_tokens,
+                completion_tokens: usage.completion_tokens,
+                total_tokens: usage.total_tokens,
+            }));
+        }
+        chunks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llms::api::sse::sse_stream;
+
+    /// A response streaming some text, two tool calls interleaved by index and the usage.
+    const FIXTURE: &str = r#"data: {"id":"c1","object":"chat.completion.chunk","created":1,"model":"gpt-4o","choices":[{"index":0,"delta":{"role":"assistant","content":""},"finish_reason":null}]}
+
+data: {"id":"c1","object":"chat.completion.chunk","created":1,"model":"gpt-4o","choices":[{"index":0,"delta":{"content":"Let me check."},"finish_reason":null}]}
+
+data: {"id":"c1","object":"chat.completion.chunk","created":1,"model":"gpt-4o","choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"id":"call_a","type":"function","function":{"name":"weather","arguments":""}}]},"finish_reason":null}]}
+
+data: {"id":"c1","object":"chat.completion.chunk","created":1,"model":"gpt-4o","choices":[{"index":0,"delta":{"tool_calls":[{"index":1,"id":"call_b","type":"function","function":{"name":"time","arguments":"{\"tz\":"}}]},"finish_reason":null}]}
+
+data: {"id":"c1","object":"chat.completion.chunk","created":1,"model":"gpt-4o","choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"function":{"arguments":"{\"city\":"}}]},"finish_reason":null}]}
+
+data: {"id":"c1","object":"chat.completion.chunk","created":1,"model":"gpt-4o","choices":[{"index":0,"delta":{"tool_calls":[{"index":1,"function":{"arguments":"\"UTC\"}"}}]},"finish_reason":null}]}
+
+data: {"id":"c1","object":"chat.completion.chunk","created":1,"model":"gpt-4o","choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"function":{"arguments":"\"Paris\"}"}}]},"finish_reason":null}]}
+
+data: {"id":"c1","object":"chat.completion.chunk","created":1,"model":"gpt-4o","choices":[{"index":0,"delta":{},"finish_reason":"tool_calls"}]}
+
+data: {"id":"c1","object":"chat.completion.chunk","created":1,"model":"gpt-4o","choices":[],"usage":{"prompt_tokens":12,"completion_tokens":30,"total_tokens":42}}
+
+data: [DONE]
+
+"#;
+
+    async fn collect(fixture: &'static str) -> Vec<Result<CompletionChunk, CompletionError>> {
+        let bytes = stream::iter([Ok::<_, reqwest::Error>(bytes::Bytes::from(fixture))]);
+        openai_completion_stream(sse_stream(bytes)).collect().await
+    }
+
+    #[tokio::test]
+    async fn test_tool_call_deltas_are_accumulated_by_index() {
+        let mut completion = StreamedCompletion::new();
+        for chunk in collect(FIXTURE).await {
+            completion.push(&chunk.unwrap());
+        }
+        assert_eq!(completion.content, "Let me check.");
+        let calls = completion.tool_calls();
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].id, "call_a");
+        assert_eq!(calls[0].function.name, "weather");
+        assert_eq!(calls[0].function.arguments, r#"{"city":"Paris"}"#);
+        assert_eq!(calls[1].id, "call_b");
+        assert_eq!(calls[1].function.name, "time");
+        assert_eq!(calls[1].function.arguments, r#"{"tz":"UTC"}"#);
+        let usage = completion.token_usage.unwrap();
+        assert_eq!(usage.prompt_tokens, 12);
+        assert_eq!(usage.completion_tokens, 30);
+        assert_eq!(usage.total_tokens, 42);
+    }
+
+    #[tokio::test]
+    async fn test_error_event_fails_the_stream() {
+        let chunks = collect(
+            "data: {\"error\":{\"message\":\"Rate limit reached\",\"type\":\"requests\",\"param\":null,\"code\":null}}\n\n",
+        )
+        .await;
+        assert_eq!(chunks.len(), 1);
+        let err = chunks[0].as_ref().unwrap_err();
+        assert!(err.to_string().contains("Rate limit reached"), "{err}");
+    }
+}
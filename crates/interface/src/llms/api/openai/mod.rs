@@ -8,12 +8,13 @@ use super::{
 use crate::requests::{
     completion::{
         error::CompletionError, request::CompletionRequest, response::CompletionResponse,
+        stream::CompletionStream,
     },
     embeddings::{EmbeddingsError, EmbeddingsRequest, EmbeddingsResponse},
 };
 use alith_devices::logging::LoggingConfig;
 use alith_models::api_model::ApiLLMModel;
-use completion::OpenAICompletionRequest;
+use completion::{OpenAICompletionRequest, openai_completion_stream};
 use reqwest::header::{AUTHORIZATION, HeaderMap, HeaderValue};
 use secrecy::{ExposeSecret, SecretString};
 use serde_json::json;
@@ -46,7 +47,11 @@ impl OpenAIBackend {
     ) -> crate::Result<CompletionResponse, CompletionError> {
         match self
             .client
-            .post("/chat/completions", OpenAICompletionRequest::new(request)?)
+            .post(
+                "/chat/completions",
+                OpenAICompletionRequest::new(request)?,
+                request.cancellation.as_ref(),
+            )
             .await
         {
             Err(e) => Err(CompletionError::ClientError(e)),
@@ -54,6 +59,21 @@ impl OpenAIBackend {
         }
     }
 
+    pub(crate) async fn completion_stream_request(
+        &self,
+        request: &CompletionRequest,
+    ) -> crate::Result<CompletionStream, CompletionError> {
+        let events = self
+            .client
+            .post_stream(
+                "/chat/completions",
+                OpenAICompletionRequest::new_stream(request)?,
+                request.cancellation.as_ref(),
+            )
+            .await?;
+        Ok(openai_completion_stream(events))
+    }
+
     pub(crate) async fn embeddings_request(
         &self,
         request: &EmbeddingsRequest,
@@ -66,6 +86,7 @@ impl OpenAIBackend {
                     "input": request.input,
                     "model": request.model,
                 }),
+                None,
             )
             .await
         {
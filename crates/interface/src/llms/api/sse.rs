@@ -0,0 +1,170 @@
+use super::error::ClientError;
+use bytes::Bytes;
+use futures::{Stream, StreamExt, stream};
+use std::collections::VecDeque;
+use std::pin::Pin;
+
+/// A stream of server-sent events read from a streaming API response.
+pub type SseStream = Pin<Box<dyn Stream<Item = Result<SseEvent, ClientError>> + Send>>;
+
+/// A single server-sent event.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SseEvent {
+    /// The event name set by an `event:` field, if any.
+    pub event: Option<String>,
+    /// The payload of the event; multiple `data:` fields are joined with newlines.
+    pub data: String,
+}
+
+/// Incrementally decodes server-sent events from chunks of bytes.
+///
+/// Chunks may split lines or UTF-8 characters at arbitrary positions, so incomplete
+/// lines are buffered until their line ending arrives.
+#[derive(Debug, Default)]
+pub(crate) struct SseDecoder {
+    buffer: Vec<u8>,
+    event: Option<String>,
+    data: Option<String>,
+}
+
+impl SseDecoder {
+    /// Feeds a chunk of bytes and returns the events completed by it.
+    pub(crate) fn push(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend_from_slice(chunk);
+        let mut events = Vec::new();
+        while let Some(pos) = self.buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\n', '\r']);
+            if let Some(event) = self.process_line(line) {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    /// Flushes the event left when the stream ends without a trailing blank line.
+    pub(crate) fn finish(&mut self) -> Option<SseEvent> {
+        if !self.buffer.is_empty() {
+            let line = String::from_utf8_lossy(&std::mem::take(&mut self.buffer)).into_owned();
+            if let Some(event) = self.process_line(line.trim_end_matches('\r')) {
+                return Some(event);
+            }
+        }
+        self.dispatch()
+    }
+
+    fn process_line(&mut self, line: &str) -> Option<SseEvent> {
+        if line.is_empty() {
+            return self.dispatch();
+        }
+        if line.starts_with(':') {
+            // Comment line, used by servers as a keep-alive.
+            return None;
+        }
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        match field {
+            "event" => self.event = Some(value.to_owned()),
+            "data" => match &mut self.data {
+                Some(data) => {
+                    data.push('\n');
+                    data.push_str(value);
+                }
+                None => self.data = Some(value.to_owned()),
+            },
+            _ => (),
+        }
+        None
+    }
+
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        let event = self.event.take();
+        self.data.take().map(|data| SseEvent { event, data })
+    }
+}
+
+/// Converts a stream of response body chunks into a stream of server-sent events.
+pub(crate) fn sse_stream<S>(bytes: S) -> SseStream
+where
+    S: Stream<Item = Result<Bytes, reqwest::Error>> + Send + 'static,
+{
+    struct State<S> {
+        bytes: Pin<Box<S>>,
+        decoder: SseDecoder,
+        pending: VecDeque<SseEvent>,
+        done: bool,
+    }
+
+    let state = State {
+        bytes: Box::pin(bytes),
+        decoder: SseDecoder::default(),
+        pending: VecDeque::new(),
+        done: false,
+    };
+    Box::pin(stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(event) = state.pending.pop_front() {
+                return Some((Ok(event), state));
+            }
+            if state.done {
+                return None;
+            }
+            match state.bytes.next().await {
+                Some(Ok(chunk)) => state.pending.extend(state.decoder.push(&chunk)),
+                Some(Err(e)) => {
+                    state.done = true;
+                    return Some((Err(ClientError::Reqwest(e)), state));
+                }
+                None => {
+                    state.done = true;
+                    state.pending.extend(state.decoder.finish());
+                }
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_events_split_across_chunks() {
+        let mut decoder = SseDecoder::default();
+        assert!(decoder.push(b"event: message_start\nda").is_empty());
+        let events = decoder.push(b"ta: {\"a\":1}\r\n\r\n: ping\n\ndata: x\ndata: y\n\n");
+        assert_eq!(
+            events,
+            vec![
+                SseEvent {
+                    event: Some("message_start".to_string()),
+                    data: "{\"a\":1}".to_string(),
+                },
+                SseEvent {
+                    event: None,
+                    data: "x\ny".to_string(),
+                },
+            ]
+        );
+        assert!(decoder.push(b"data: [DONE]").is_empty());
+        assert_eq!(
+            decoder.finish(),
+            Some(SseEvent {
+                event: None,
+                data: "[DONE]".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_decode_split_utf8_character() {
+        let mut decoder = SseDecoder::default();
+        let bytes = "data: héllo\n\n".as_bytes();
+        assert!(decoder.push(&bytes[..8]).is_empty());
+        let events = decoder.push(&bytes[8..]);
+        assert_eq!(events[0].data, "héllo");
+    }
+}
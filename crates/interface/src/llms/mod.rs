@@ -1,6 +1,7 @@
 use crate::requests::{
     completion::{
         error::CompletionError, request::CompletionRequest, response::CompletionResponse,
+        stream::CompletionStream,
     },
     embeddings::{EmbeddingsError, EmbeddingsRequest, EmbeddingsResponse},
     logit_bias::LogitBias,
@@ -32,6 +33,17 @@ impl LLMBackend {
         }
     }
 
+    pub(crate) async fn completion_stream_request(
+        &self,
+        request: &CompletionRequest,
+    ) -> crate::Result<CompletionStream, CompletionError> {
+        match self {
+            LLMBackend::OpenAI(b) => b.completion_stream_request(request).await,
+            LLMBackend::Anthropic(b) => b.completion_stream_request(request).await,
+            LLMBackend::GenericApi(b) => b.completion_stream_request(request).await,
+        }
+    }
+
     pub(crate) async fn embeddings_request(
         &self,
         request: &EmbeddingsRequest,
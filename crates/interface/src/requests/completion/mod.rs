@@ -1,10 +1,14 @@
 pub mod error;
 pub mod request;
 pub mod response;
+pub mod response_format;
+pub mod stream;
 pub mod tool;
 
 pub use super::res_components::{GenerationSettings, TimingUsage, TokenUsage};
 pub use error::CompletionError;
 pub use request::CompletionRequest;
 pub use response::{CompletionFinishReason, CompletionResponse};
+pub use response_format::{JsonSchemaFormat, ResponseFormat};
+pub use stream::{CompletionChunk, CompletionStream, StreamedCompletion, ToolCallChunk};
 pub use tool::{ToolChoice, ToolDefinition};
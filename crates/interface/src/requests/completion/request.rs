@@ -1,4 +1,7 @@
-use super::{ToolChoice, ToolDefinition, error::CompletionError, response::CompletionResponse};
+use super::{
+    ResponseFormat, ToolChoice, ToolDefinition, error::CompletionError,
+    response::CompletionResponse, stream::CompletionStream,
+};
 use crate::{
     llms::LLMBackend,
     requests::{
@@ -8,6 +11,7 @@ use crate::{
 };
 use alith_prompt::LLMPrompt;
 use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
 
 pub struct CompletionRequest {
     pub start_time: std::time::Instant,
@@ -20,6 +24,9 @@ pub struct CompletionRequest {
     pub llm_interface_errors: Vec<CompletionError>,
     pub tools: Vec<ToolDefinition>,
     pub tool_choice: ToolChoice,
+    pub response_format: Option<ResponseFormat>,
+    /// Cancels the HTTP request of the completion when cancelled.
+    pub cancellation: Option<CancellationToken>,
 }
 
 impl Clone for CompletionRequest {
@@ -35,6 +42,8 @@ impl Clone for CompletionRequest {
             llm_interface_errors: Vec::new(),
             tools: Vec::new(),
             tool_choice: ToolChoice::Auto,
+            response_format: self.response_format.clone(),
+            cancellation: self.cancellation.clone(),
         }
     }
 }
@@ -52,6 +61,8 @@ impl CompletionRequest {
             llm_interface_errors: Vec::new(),
             tools: Vec::new(),
             tool_choice: ToolChoice::default(),
+            response_format: None,
+            cancellation: None,
         }
     }
 
@@ -60,23 +71,12 @@ impl CompletionRequest {
         self.stop_sequences.sequences.clear();
         self.grammar_string = None;
         self.logit_bias = None;
+        self.response_format = None;
+        self.cancellation = None;
     }
 
     pub async fn request(&mut self) -> crate::Result<CompletionResponse, CompletionError> {
-        self.llm_interface_errors.clear();
-        self.start_time = std::time::Instant::now();
-        self.backend
-            .build_logit_bias(&mut self.logit_bias)
-            .map_err(|e| CompletionError::RequestBuilderError(e.to_string()))?;
-
-        let total_prompt_tokens = self
-            .backend
-            .get_total_prompt_tokens(&self.prompt)
-            .map_err(|e| CompletionError::RequestBuilderError(e.to_string()))?;
-
-        self.config
-            .set_max_tokens_for_request(total_prompt_tokens as u64)
-            .map_err(CompletionError::RequestTokenLimitError)?;
+        let total_prompt_tokens = self.prepare_request()?;
 
         let mut retry_count: u8 = 0;
 
@@ -160,6 +160,36 @@ impl CompletionRequest {
         }
     }
 
+    /// Sends the request and streams the response back as it is generated.
+    ///
+    /// Unlike [`CompletionRequest::request`], a streamed request is not retried and
+    /// required stop sequences are not checked, because the chunks are handed to the
+    /// caller as soon as they arrive.
+    pub async fn stream(&mut self) -> crate::Result<CompletionStream, CompletionError> {
+        self.prepare_request()?;
+        tracing::info!("{}", self);
+        self.backend.completion_stream_request(self).await
+    }
+
+    /// Resets the request state and sets the token limit, returning the number of prompt tokens.
+    fn prepare_request(&mut self) -> crate::Result<usize, CompletionError> {
+        self.llm_interface_errors.clear();
+        self.start_time = std::time::Instant::now();
+        self.backend
+            .build_logit_bias(&mut self.logit_bias)
+            .map_err(|e| CompletionError::RequestBuilderError(e.to_string()))?;
+
+        let total_prompt_tokens = self
+            .backend
+            .get_total_prompt_tokens(&self.prompt)
+            .map_err(|e| CompletionError::RequestBuilderError(e.to_string()))?;
+
+        self.config
+            .set_max_tokens_for_request(total_prompt_tokens as u64)
+            .map_err(CompletionError::RequestTokenLimitError)?;
+        Ok(total_prompt_tokens)
+    }
+
     pub fn set_base_req_stop_sequences(
         &mut self,
         stop_word_done: &Option<String>,
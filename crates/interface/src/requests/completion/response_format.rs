@@ -0,0 +1,25 @@
+use serde::{Deserialize, Serialize};
+
+/// The format of the model output, sent as the OpenAI `response_format` parameter.
+#[derive(Clone, Debug, Deserialize, Serialize, Default, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ResponseFormat {
+    #[default]
+    Text,
+    /// Any valid JSON object.
+    JsonObject,
+    /// A JSON value matching the schema.
+    JsonSchema { json_schema: JsonSchemaFormat },
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct JsonSchemaFormat {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub schema: serde_json::Value,
+    /// Whether the output must follow the schema exactly, only a subset of JSON Schema
+    /// is supported in this mode.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strict: Option<bool>,
+}
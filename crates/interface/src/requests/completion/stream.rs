@@ -0,0 +1,96 @@
+use super::{
+    error::CompletionError,
+    tool::{Function, ToolCall},
+};
+use crate::requests::res_components::TokenUsage;
+use futures::Stream;
+use std::collections::BTreeMap;
+use std::pin::Pin;
+
+/// A stream of incremental completion chunks.
+pub type CompletionStream =
+    Pin<Box<dyn Stream<Item = Result<CompletionChunk, CompletionError>> + Send>>;
+
+/// An incremental piece of a streamed completion.
+#[derive(Debug, Clone)]
+pub enum CompletionChunk {
+    /// A piece of the generated text.
+    Text(String),
+    /// A piece of a tool call requested by the model.
+    ToolCall(ToolCallChunk),
+    /// The token usage of the request, sent once near the end of the stream.
+    Usage(TokenUsage),
+}
+
+/// A fragment of a tool call.
+///
+/// The first fragment of a call carries its `id` and `name`; the following fragments
+/// with the same `index` append to its `arguments`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ToolCallChunk {
+    /// The position of the tool call in the response.
+    pub index: usize,
+    /// The unique identifier of the tool call.
+    pub id: Option<String>,
+    /// The name of the called function.
+    pub name: Option<String>,
+    /// A fragment of the JSON arguments of the function.
+    pub arguments: String,
+}
+
+/// Accumulates the chunks of a streamed completion into the full response.
+#[derive(Debug, Clone, Default)]
+pub struct StreamedCompletion {
+    /// The generated text.
+    pub content: String,
+    /// The token usage, if the backend reported it.
+    pub token_usage: Option<TokenUsage>,
+    tool_calls: BTreeMap<usize, ToolCall>,
+}
+
+impl StreamedCompletion {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a chunk to the accumulated response.
+    pub fn push(&mut self, chunk: &CompletionChunk) {
+        match chunk {
+            CompletionChunk::Text(text) => self.content.push_str(text),
+            CompletionChunk::ToolCall(chunk) => {
+                let call = self
+                    .tool_calls
+                    .entry(chunk.index)
+                    .or_insert_with(|| ToolCall {
+                        id: String::new(),
+                        r#type: "function".to_owned(),
+                        function: Function::default(),
+                    });
+                if let Some(id) = &chunk.id {
+                    call.id.push_str(id);
+                }
+                if let Some(name) = &chunk.name {
+                    call.function.name.push_str(name);
+                }
+                call.function.arguments.push_str(&chunk.arguments);
+            }
+            CompletionChunk::Usage(usage) => self.token_usage = Some(usage.clone()),
+        }
+    }
+
+    /// Returns the tool calls accumulated so far, in the order of their index.
+    ///
+    /// Calls streamed without any argument fragment get empty JSON object arguments.
+    pub fn tool_calls(&self) -> Vec<ToolCall> {
+        self.tool_calls
+            .values()
+            .cloned()
+            .map(|mut call| {
+                if call.function.arguments.is_empty() {
+                    call.function.arguments = "{}".to_owned();
+                }
+                call
+            })
+            .collect()
+    }
+}
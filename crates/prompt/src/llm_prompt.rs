@@ -175,6 +175,37 @@ impl LLMPrompt {
         Ok(self.last_message())
     }
 
+    /// Adds a tool result message to the prompt.
+    ///
+    /// Must follow an assistant message (carrying the tool calls) or another tool message.
+    /// Returns an error if attempting to add a tool message anywhere else.
+    ///
+    /// # Returns
+    ///
+    /// A reference to the newly created message for setting content, or an error if validation fails.
+    pub fn add_tool_message(&self) -> Result<Arc<PromptMessage>, crate::Error> {
+        {
+            let mut messages = self.messages();
+
+            match messages.last() {
+                Some(last)
+                    if last.message_type == PromptMessageType::Assistant
+                        || last.message_type == PromptMessageType::Tool => {}
+                _ => crate::bail!(
+                    "Tool message must follow an assistant message or another tool message."
+                ),
+            };
+
+            let message = Arc::new(PromptMessage::new(
+                PromptMessageType::Tool,
+                &self.concatenator,
+            ));
+            messages.push(message);
+        }
+        self.clear_built_prompt();
+        Ok(self.last_message())
+    }
+
     /// Sets a prefix to be added before generation for local LLMs.
     ///
     /// This is typically used to prime the model's response.
@@ -340,21 +371,30 @@ impl LLMPrompt {
                     (Some(PromptMessageType::User), PromptMessageType::Assistant) => {}
                     (Some(PromptMessageType::Assistant), PromptMessageType::User) => {}
                     (Some(PromptMessageType::System), PromptMessageType::User) => {}
+                    (Some(PromptMessageType::Assistant), PromptMessageType::Tool) => {}
+                    (Some(PromptMessageType::Tool), PromptMessageType::Tool) => {}
+                    (Some(PromptMessageType::Tool), PromptMessageType::Assistant) => {}
+                    (Some(PromptMessageType::Tool), PromptMessageType::User) => {}
                     _ => panic!(
-                        "Messages must alternate between User and Assistant after the first message (which can be System)."
+                        "Messages must alternate between User and Assistant after the first message (which can be System), with Tool messages only following an Assistant message."
                     ),
                 }
             }
             last_message_type = Some(message_type.clone());
 
-            if let Some(built_message_string) = &*message.built_prompt_message() {
-                built_prompt_messages.push(HashMap::from([
-                    ("role".to_string(), message.message_type.as_str().to_owned()),
-                    ("content".to_string(), built_message_string.to_owned()),
-                ]));
-            } else {
-                crate::bail!("message.built_content is empty and skipped");
-            }
+            let attributes = message.get_attributes();
+            // Assistant messages carrying only tool calls have no content.
+            let built_message_string = match &*message.built_prompt_message() {
+                Some(built_message_string) => built_message_string.to_owned(),
+                None if !attributes.is_empty() => String::new(),
+                None => crate::bail!("message.built_content is empty and skipped"),
+            };
+            let mut built_message = HashMap::from([
+                ("role".to_string(), message.message_type.as_str().to_owned()),
+                ("content".to_string(), built_message_string),
+            ]);
+            built_message.extend(attributes);
+            built_prompt_messages.push(built_message);
         }
 
         *self.built_prompt_messages.lock().unwrap_or_else(|e| {
@@ -1,5 +1,6 @@
 use super::TextConcatenator;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::sync::{Arc, Mutex, MutexGuard};
 
 /// Represents the type of message in a prompt sequence.
@@ -19,6 +20,9 @@ pub enum PromptMessageType {
     Assistant,
     /// A function calling message.
     Function,
+    /// A tool result message. Must follow an assistant message with tool calls
+    /// or another tool message.
+    Tool,
 }
 
 impl PromptMessageType {
@@ -28,6 +32,7 @@ impl PromptMessageType {
             PromptMessageType::User => "user",
             PromptMessageType::Assistant => "assistant",
             PromptMessageType::Function => "function",
+            PromptMessageType::Tool => "tool",
         }
     }
 }
@@ -80,6 +85,10 @@ pub struct PromptMessage {
     pub built_prompt_message: Mutex<Option<String>>,
     pub message_type: PromptMessageType,
     pub concatenator: TextConcatenator,
+    /// Extra key-value pairs emitted alongside `role` and `content` when the
+    /// message is built, e.g. `tool_calls` or `tool_call_id`.
+    #[serde(default)]
+    pub attributes: Mutex<HashMap<String, String>>,
 }
 
 impl PromptMessage {
@@ -89,9 +98,25 @@ impl PromptMessage {
             built_prompt_message: None.into(),
             message_type,
             concatenator: concatenator.clone(),
+            attributes: HashMap::new().into(),
         }
     }
 
+    /// Sets an extra attribute on the message, replacing any existing value for the key.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The attribute name, e.g. `tool_call_id`
+    /// * `value` - The attribute value
+    ///
+    /// # Returns
+    ///
+    /// A reference to self for method chaining
+    pub fn set_attribute<K: Into<String>, V: Into<String>>(&self, key: K, value: V) -> &Self {
+        self.attributes().insert(key.into(), value.into());
+        self
+    }
+
     // Setter methods
     //
 
@@ -210,6 +235,11 @@ impl PromptMessage {
         }
     }
 
+    /// Retrieves a copy of the extra attributes of the message.
+    pub fn get_attributes(&self) -> HashMap<String, String> {
+        self.attributes().clone()
+    }
+
     // Builder methods
     //
 
@@ -235,6 +265,12 @@ impl PromptMessage {
             .unwrap_or_else(|e| panic!("PromptMessage Error - content not available: {:?}", e))
     }
 
+    fn attributes(&self) -> MutexGuard<'_, HashMap<String, String>> {
+        self.attributes
+            .lock()
+            .unwrap_or_else(|e| panic!("PromptMessage Error - attributes not available: {:?}", e))
+    }
+
     pub(crate) fn built_prompt_message(&self) -> MutexGuard<'_, Option<String>> {
         self.built_prompt_message.lock().unwrap_or_else(|e| {
             panic!(
@@ -252,6 +288,7 @@ impl Clone for PromptMessage {
             built_prompt_message: self.built_prompt_message().clone().into(),
             message_type: self.message_type.clone(),
             concatenator: self.concatenator.clone(),
+            attributes: self.attributes().clone().into(),
         }
     }
 }
@@ -263,6 +300,7 @@ impl std::fmt::Display for PromptMessage {
             PromptMessageType::User => "User",
             PromptMessageType::Assistant => "Assistant",
             PromptMessageType::Function => "Function",
+            PromptMessageType::Tool => "Tool",
         };
         let message = match &*self.built_prompt_message() {
             Some(built_message_string) => {
@@ -1,4 +1,4 @@
-use alith_core::store::DocumentId;
+use alith_core::store::{DocumentId, Filter, Metadata, StorageDocument};
 pub use chromadb::client::{
     ChromaAuthMethod, ChromaClient, ChromaClientOptions, ChromaTokenHeader,
 };
@@ -12,6 +12,7 @@ use alith_core::{
     store::{Storage, TopNResults, VectorStoreError},
 };
 use async_trait::async_trait;
+use serde_json::{Value, json};
 
 pub const DEFAULT_CHROMADB_COLLECTION_NAME: &str = "alith";
 pub const DEFAULT_CHROMADB_DIM: i64 = 768;
@@ -68,6 +69,29 @@ impl<E: Embeddings> ChromaStorage<E> {
         Self::from_documents(client, embeddings, documents).await
     }
 
+    /// Embeds a document and upserts it under the given ID.
+    async fn upsert_document(
+        &self,
+        id: &str,
+        document: StorageDocument,
+    ) -> Result<(), VectorStoreError> {
+        let embeddings = self.generate_embed_vector(&document.text).await?;
+        // Chroma rejects empty metadata.
+        let metadatas = (!document.metadata.is_empty()).then(|| vec![document.metadata]);
+        let collection_entries = CollectionEntries {
+            ids: vec![id],
+            embeddings: Some(vec![embeddings]),
+            metadatas,
+            documents: Some(vec![document.text.as_str()]),
+        };
+
+        self.collection
+            .upsert(collection_entries, None)
+            .await
+            .map_err(|err| VectorStoreError::CustomError(err.to_string()))?;
+        Ok(())
+    }
+
     /// Generate the embed vector for the Chroma store.
     pub async fn generate_embed_vector(&self, value: &str) -> Result<Vec<f32>, VectorStoreError> {
         let vec = self
@@ -83,31 +107,77 @@ impl<E: Embeddings> ChromaStorage<E> {
 
 #[async_trait]
 impl<E: Embeddings> Storage for ChromaStorage<E> {
-    async fn save(&self, value: String) -> Result<(), VectorStoreError> {
-        let embeddings = self.generate_embed_vector(&value).await?;
-        let id = hash_id(&value);
-        let collection_entries = CollectionEntries {
-            ids: vec![id.as_str()],
-            embeddings: Some(vec![embeddings]),
-            metadatas: None,
-            documents: Some(vec![value.as_str()]),
-        };
+    /// Saves the documents under the hashes of their text, so saving a text again
+    /// replaces its document.
+    async fn save_documents(
+        &self,
+        documents: Vec<StorageDocument>,
+    ) -> Result<Vec<DocumentId>, VectorStoreError> {
+        let mut ids = Vec::with_capacity(documents.len());
+        for document in documents {
+            let id = hash_id(&document.text);
+            self.upsert_document(&id, document).await?;
+            ids.push(DocumentId(id));
+        }
+        Ok(ids)
+    }
+
+    async fn upsert(
+        &self,
+        id: &DocumentId,
+        document: StorageDocument,
+    ) -> Result<(), VectorStoreError> {
+        self.upsert_document(&id.0, document).await
+    }
+
+    async fn get(&self, id: &DocumentId) -> Result<Option<StorageDocument>, VectorStoreError> {
+        let result = self
+            .collection
+            .get(GetOptions {
+                ids: vec![id.0.clone()],
+                include: Some(vec!["documents".to_string(), "metadatas".to_string()]),
+                ..Default::default()
+            })
+            .await
+            .map_err(|err| VectorStoreError::CustomError(err.to_string()))?;
+        if result.ids.is_empty() {
+            return Ok(None);
+        }
+        let text = result
+            .documents
+            .and_then(|docs| docs.into_iter().next().flatten())
+            .unwrap_or_default();
+        let metadata = result
+            .metadatas
+            .and_then(|metadatas| metadatas.into_iter().next().flatten())
+            .unwrap_or_else(Metadata::new);
+        Ok(Some(StorageDocument { text, metadata }))
+    }
 
+    async fn delete(&self, id: &DocumentId) -> Result<(), VectorStoreError> {
         self.collection
-            .upsert(collection_entries, None)
+            .delete(Some(vec![id.0.as_str()]), None, None)
             .await
             .map_err(|err| VectorStoreError::CustomError(err.to_string()))?;
         Ok(())
     }
 
-    async fn search(&self, query: &str, limit: usize, _threshold: f32) -> TopNResults {
+    async fn search_filtered(
+        &self,
+        query: &str,
+        limit: usize,
+        _threshold: f32,
+        filter: Option<&Filter>,
+    ) -> TopNResults {
         let query_vectors = self.generate_embed_vector(query).await?;
+        let where_metadata = filter.map(chroma_where).transpose()?.flatten();
         let result = self
             .collection
             .query(
                 QueryOptions {
                     query_embeddings: Some(vec![query_vectors]),
                     n_results: Some(limit),
+                    where_metadata,
                     ..Default::default()
                 },
                 None,
@@ -148,6 +218,53 @@ impl<E: Embeddings> Storage for ChromaStorage<E> {
     }
 }
 
+/// The metadata key of the condition matching no document.
+const MATCH_NOTHING_KEY: &str = "_match_nothing";
+
+/// Translates a filter into a Chroma `where` filter, `None` when it matches every document.
+fn chroma_where(filter: &Filter) -> Result<Option<Value>, VectorStoreError> {
+    let combine = |op: &str, mut filters: Vec<Value>| match filters.len() {
+        1 => filters.pop().unwrap(),
+        _ => json!({ op: filters }),
+    };
+    Ok(match filter {
+        Filter::Eq { key, value } => Some(json!({ key: { "$eq": value } })),
+        Filter::In { key, values } if !values.is_empty() => Some(json!({ key: { "$in": values } })),
+        Filter::Range { key, range } => {
+            let range = range.bounded();
+            let bounds = [
+                ("$gt", range.gt),
+                ("$gte", range.gte),
+                ("$lt", range.lt),
+                ("$lte", range.lte),
+            ];
+            let conditions: Vec<_> = bounds
+                .into_iter()
+                .filter_map(|(op, bound)| Some(json!({ key: { op: bound? } })))
+                .collect();
+            Some(combine("$and", conditions))
+        }
+        Filter::And(filters) => {
+            let filters = filters
+                .iter()
+                .map(chroma_where)
+                .collect::<Result<Vec<_>, _>>()?;
+            let filters: Vec<_> = filters.into_iter().flatten().collect();
+            (!filters.is_empty()).then(|| combine("$and", filters))
+        }
+        Filter::Or(filters) if !filters.is_empty() => filters
+            .iter()
+            .map(chroma_where)
+            .collect::<Result<Option<Vec<_>>, _>>()?
+            .map(|filters| combine("$or", filters)),
+        // Chroma rejects an empty `$in` or `$or`, so use a condition false for every
+        // document, which lacks the key or has another value.
+        Filter::In { .. } | Filter::Or(_) => Some(json!({
+            "$and": [{ MATCH_NOTHING_KEY: { "$eq": 0 } }, { MATCH_NOTHING_KEY: { "$ne": 0 } }]
+        })),
+    })
+}
+
 fn hash_id<S: AsRef<str>>(input: S) -> String {
     use blake3::Hasher;
     let mut hasher = Hasher::new();
@@ -155,3 +272,38 @@ fn hash_id<S: AsRef<str>>(input: S) -> String {
     let hash = hasher.finalize();
     hash.to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alith_core::store::Range;
+
+    #[test]
+    fn test_empty_filters_match_nothing() {
+        let nothing = json!({
+            "$and": [{ MATCH_NOTHING_KEY: { "$eq": 0 } }, { MATCH_NOTHING_KEY: { "$ne": 0 } }]
+        });
+        assert_eq!(
+            chroma_where(&Filter::Or(vec![])).unwrap(),
+            Some(nothing.clone())
+        );
+        let filter = Filter::In {
+            key: "tag".to_string(),
+            values: vec![],
+        };
+        assert_eq!(chroma_where(&filter).unwrap(), Some(nothing.clone()));
+        let filter = Filter::Or(vec![Filter::Or(vec![]), Filter::eq("tag", "a")]);
+        assert_eq!(
+            chroma_where(&filter).unwrap(),
+            Some(json!({ "$or": [nothing, { "tag": { "$eq": "a" } }] }))
+        );
+    }
+
+    #[test]
+    fn test_unbounded_ranges_match_numbers() {
+        assert_eq!(
+            chroma_where(&Filter::range("date", Range::default())).unwrap(),
+            Some(json!({ "date": { "$gte": f64::MIN } }))
+        );
+    }
+}
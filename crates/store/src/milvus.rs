@@ -1,12 +1,15 @@
-use alith_core::store::DocumentId;
+use alith_core::store::{DocumentId, Filter, Metadata, StorageDocument};
 pub use milvus::index::{IndexParams as MilvusIndexParams, IndexType as MilvusIndexType};
+use milvus::mutate::DeleteOptions;
 pub use milvus::options::LoadOptions as MilvusLoadOptions;
+use milvus::proto::schema::DataType;
 pub use milvus::query::QueryOptions as MilvusQueryOptions;
 use milvus::query::SearchOptions;
 pub use milvus::schema::{
     CollectionSchema as MilvusCollectionSchema,
     CollectionSchemaBuilder as MilvusCollectionSchemaBuilder,
 };
+use milvus::value::ValueVec;
 pub use milvus::{
     self, client::Client as MilvusClient, collection::Collection as MilvusCollection,
     data::FieldColumn as MilvusFieldColumn, error::Error as MilvusError,
@@ -19,15 +22,28 @@ use alith_core::{
     store::{Storage, TopNResults, VectorStoreError},
 };
 use async_trait::async_trait;
+use serde_json::Value;
+use uuid::Uuid;
 
 pub const DEFAULT_MILVUS_COLLECTION_SCHEMA_NAME: &str = "alith";
 pub const DEFAULT_MILVUS_ID_FIELD: &str = "id";
 pub const DEFAULT_MILVUS_VEC_FIELD: &str = "vector";
 pub const DEFAULT_MILVUS_TEXT_FIELD: &str = "text";
+pub const DEFAULT_MILVUS_METADATA_FIELD: &str = "metadata";
 pub const DEFAULT_MILVUS_DIM: i64 = 768;
 pub const DEFAULT_MILVUS_URL: &str = "localhost:19530";
 
 /// Milvus storage implementation.
+///
+/// The documents are stored in the `alith` collection, under an `id` chosen by the storage
+/// or the caller and with their metadata in a JSON `metadata` field.
+///
+/// # Migration
+///
+/// Collections created by older versions have an automatic `id` and no `metadata` field,
+/// and Milvus can't add fields to a collection. Query their texts if they must be kept,
+/// drop the collection with [`MilvusClient::drop_collection`], then save the texts again
+/// into a new storage.
 pub struct MilvusStorage<E: Embeddings> {
     client: MilvusClient,
     embeddings: Arc<E>,
@@ -36,6 +52,9 @@ pub struct MilvusStorage<E: Embeddings> {
 
 impl<E: Embeddings> MilvusStorage<E> {
     /// Creates a new instance of `MilvusStorage`.
+    ///
+    /// Fails when the collection exists with another schema, see the
+    /// [migration](MilvusStorage#migration).
     pub async fn from_documents(
         client: MilvusClient,
         embeddings: E,
@@ -46,7 +65,7 @@ impl<E: Embeddings> MilvusStorage<E> {
                 .add_field(MilvusFieldSchema::new_primary_int64(
                     DEFAULT_MILVUS_ID_FIELD,
                     "primary key field",
-                    true,
+                    false,
                 ))
                 .add_field(MilvusFieldSchema::new_float_vector(
                     DEFAULT_MILVUS_VEC_FIELD,
@@ -57,25 +76,30 @@ impl<E: Embeddings> MilvusStorage<E> {
                     DEFAULT_MILVUS_TEXT_FIELD,
                     "text field",
                 ))
+                .add_field(MilvusFieldSchema {
+                    dtype: DataType::Json,
+                    ..MilvusFieldSchema::new_string(DEFAULT_MILVUS_METADATA_FIELD, "metadata field")
+                })
                 .build()
                 .map_err(|err| VectorStoreError::DatastoreError(Box::new(err)))?;
 
         client
             .create_collection(collection.clone(), None)
             .await
-            .map_err(|err| VectorStoreError::DatastoreError(Box::new(err)))?;
+            .map_err(|err| {
+                VectorStoreError::DatastoreError(
+                    format!(
+                        "Failed to create the `{DEFAULT_MILVUS_COLLECTION_SCHEMA_NAME}` collection, \
+                         re-create it if it was created by an older version: {err}"
+                    )
+                    .into(),
+                )
+            })?;
 
-        for document in &documents {
-            let embed_column = MilvusFieldColumn::new(
-                collection.get_field(DEFAULT_MILVUS_VEC_FIELD).unwrap(),
-                document.f32_vec(),
-            );
-            let text_column = MilvusFieldColumn::new(
-                collection.get_field(DEFAULT_MILVUS_TEXT_FIELD).unwrap(),
-                vec![document.document.clone()],
-            );
+        for document in documents {
+            let columns = document_columns(&collection, new_id(), document, Metadata::new())?;
             client
-                .insert(collection.name(), vec![embed_column, text_column], None)
+                .insert(collection.name(), columns, None)
                 .await
                 .map_err(|err| VectorStoreError::DatastoreError(Box::new(err)))?;
         }
@@ -108,40 +132,138 @@ impl<E: Embeddings> MilvusStorage<E> {
             .unwrap_or_default();
         Ok(vec.iter().map(|&x| x as f32).collect())
     }
+
+    /// Embeds the documents and inserts them under the given IDs, first deleting the
+    /// documents with these IDs when `replace` is set.
+    async fn insert_documents(
+        &self,
+        documents: Vec<(i64, StorageDocument)>,
+        replace: bool,
+    ) -> Result<(), VectorStoreError> {
+        let texts = documents.iter().map(|(_, doc)| doc.text.clone()).collect();
+        let embeddings = self
+            .embeddings
+            .embed_texts(texts)
+            .await
+            .map_err(VectorStoreError::EmbeddingError)?;
+        if replace {
+            let ids: Vec<_> = documents.iter().map(|(id, _)| id.to_string()).collect();
+            self.client
+                .delete(
+                    self.collection.name(),
+                    &DeleteOptions::with_filter(format!(
+                        "{DEFAULT_MILVUS_ID_FIELD} in [{}]",
+                        ids.join(", ")
+                    )),
+                )
+                .await
+                .map_err(|e| VectorStoreError::DatastoreError(Box::new(e)))?;
+        }
+        for ((id, document), data) in documents.into_iter().zip(embeddings) {
+            let columns = document_columns(&self.collection, id, data, document.metadata)?;
+            self.client
+                .insert(self.collection.name(), columns, None)
+                .await
+                .map_err(|e| VectorStoreError::DatastoreError(Box::new(e)))?;
+        }
+        self.client
+            .flush(self.collection.name())
+            .await
+            .map_err(|e| VectorStoreError::DatastoreError(Box::new(e)))?;
+        Ok(())
+    }
 }
 
 #[async_trait]
 impl<E: Embeddings> Storage for MilvusStorage<E> {
-    async fn save(&self, value: String) -> Result<(), VectorStoreError> {
-        let embeddings = self.generate_embed_vector(&value).await?;
+    async fn save_documents(
+        &self,
+        documents: Vec<StorageDocument>,
+    ) -> Result<Vec<DocumentId>, VectorStoreError> {
+        let documents: Vec<_> = documents.into_iter().map(|doc| (new_id(), doc)).collect();
+        let ids = documents
+            .iter()
+            .map(|(id, _)| DocumentId(id.to_string()))
+            .collect();
+        self.insert_documents(documents, false).await?;
+        Ok(ids)
+    }
 
-        let embed_column = MilvusFieldColumn::new(
-            self.collection.get_field(DEFAULT_MILVUS_VEC_FIELD).unwrap(),
-            embeddings,
-        );
-        let text_column = MilvusFieldColumn::new(
-            self.collection
-                .get_field(DEFAULT_MILVUS_TEXT_FIELD)
-                .unwrap(),
-            vec![value],
-        );
-        self.client
-            .insert(
+    /// Upserts a document, its ID must be an integer.
+    async fn upsert(
+        &self,
+        id: &DocumentId,
+        document: StorageDocument,
+    ) -> Result<(), VectorStoreError> {
+        self.insert_documents(vec![(milvus_id(id)?, document)], true)
+            .await
+    }
+
+    async fn get(&self, id: &DocumentId) -> Result<Option<StorageDocument>, VectorStoreError> {
+        let columns = self
+            .client
+            .query(
                 self.collection.name(),
-                vec![embed_column, text_column],
-                None,
+                format!("{DEFAULT_MILVUS_ID_FIELD} == {}", milvus_id(id)?),
+                &MilvusQueryOptions::default().output_fields(vec![
+                    DEFAULT_MILVUS_TEXT_FIELD.to_string(),
+                    DEFAULT_MILVUS_METADATA_FIELD.to_string(),
+                ]),
             )
             .await
             .map_err(|e| VectorStoreError::DatastoreError(Box::new(e)))?;
+        let mut text = None;
+        let mut metadata = Metadata::new();
+        for column in columns {
+            match (column.name.as_str(), column.value) {
+                (DEFAULT_MILVUS_TEXT_FIELD, ValueVec::String(texts)) => {
+                    text = texts.into_iter().next()
+                }
+                (DEFAULT_MILVUS_METADATA_FIELD, ValueVec::Json(values)) => {
+                    if let Some(Value::Object(object)) = values
+                        .first()
+                        .and_then(|value| serde_json::from_slice(value).ok())
+                    {
+                        metadata = object;
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(text.map(|text| StorageDocument { text, metadata }))
+    }
+
+    async fn delete(&self, id: &DocumentId) -> Result<(), VectorStoreError> {
         self.client
-            .flush(self.collection.name())
+            .delete(
+                self.collection.name(),
+                &DeleteOptions::with_filter(format!(
+                    "{DEFAULT_MILVUS_ID_FIELD} == {}",
+                    milvus_id(id)?
+                )),
+            )
             .await
             .map_err(|e| VectorStoreError::DatastoreError(Box::new(e)))?;
         Ok(())
     }
 
-    async fn search(&self, query: &str, limit: usize, _threshold: f32) -> TopNResults {
+    async fn search_filtered(
+        &self,
+        query: &str,
+        limit: usize,
+        _threshold: f32,
+        filter: Option<&Filter>,
+    ) -> TopNResults {
         let query_vectors = self.generate_embed_vector(query).await?;
+        let mut options = SearchOptions::default()
+            .output_fields(vec![
+                DEFAULT_MILVUS_ID_FIELD.to_string(),
+                DEFAULT_MILVUS_TEXT_FIELD.to_string(),
+            ])
+            .limit(limit);
+        if let Some(expr) = filter.map(milvus_expr).transpose()?.flatten() {
+            options = options.expr(expr);
+        }
         let results = self
             .client
             .search(
@@ -151,12 +273,7 @@ impl<E: Embeddings> Storage for MilvusStorage<E> {
                     .map(|v| MilvusValue::Float(*v))
                     .collect(),
                 DEFAULT_MILVUS_VEC_FIELD,
-                &SearchOptions::default()
-                    .output_fields(vec![
-                        DEFAULT_MILVUS_ID_FIELD.to_string(),
-                        DEFAULT_MILVUS_TEXT_FIELD.to_string(),
-                    ])
-                    .limit(limit),
+                &options,
             )
             .await
             .map_err(|e| VectorStoreError::DatastoreError(Box::new(e)))?;
@@ -183,3 +300,143 @@ impl<E: Embeddings> Storage for MilvusStorage<E> {
         Ok(())
     }
 }
+
+/// Builds the columns inserting a document.
+fn document_columns(
+    collection: &MilvusCollectionSchema,
+    id: i64,
+    data: EmbeddingsData,
+    metadata: Metadata,
+) -> Result<Vec<MilvusFieldColumn>, VectorStoreError> {
+    let metadata = serde_json::to_vec(&Value::Object(metadata))?;
+    Ok(vec![
+        MilvusFieldColumn::new(
+            collection.get_field(DEFAULT_MILVUS_ID_FIELD).unwrap(),
+            vec![id],
+        ),
+        MilvusFieldColumn::new(
+            collection.get_field(DEFAULT_MILVUS_VEC_FIELD).unwrap(),
+            data.f32_vec(),
+        ),
+        MilvusFieldColumn::new(
+            collection.get_field(DEFAULT_MILVUS_TEXT_FIELD).unwrap(),
+            vec![data.document],
+        ),
+        MilvusFieldColumn::new(
+            collection.get_field(DEFAULT_MILVUS_METADATA_FIELD).unwrap(),
+            ValueVec::Json(vec![metadata]),
+        ),
+    ])
+}
+
+/// Returns a random positive document ID.
+fn new_id() -> i64 {
+    (Uuid::new_v4().as_u64_pair().0 >> 1) as i64
+}
+
+fn milvus_id(id: &DocumentId) -> Result<i64, VectorStoreError> {
+    id.0.parse()
+        .map_err(|e| VectorStoreError::DatastoreError(Box::new(e)))
+}
+
+/// Translates a filter into a boolean expression on the JSON metadata field, `None` when
+/// it matches every document.
+fn milvus_expr(filter: &Filter) -> Result<Option<String>, VectorStoreError> {
+    let key = |key: &str| {
+        format!(
+            "{DEFAULT_MILVUS_METADATA_FIELD}[{}]",
+            Value::String(key.to_string())
+        )
+    };
+    Ok(match filter {
+        Filter::Eq { key: k, value } => Some(format!("{} == {}", key(k), milvus_value(value)?)),
+        Filter::In { key: k, values } if !values.is_empty() => {
+            let values = values
+                .iter()
+                .map(milvus_value)
+                .collect::<Result<Vec<_>, _>>()?;
+            Some(format!("{} in [{}]", key(k), values.join(", ")))
+        }
+        Filter::Range { key: k, range } => {
+            let range = range.bounded();
+            let bounds = [
+                (">", range.gt),
+                (">=", range.gte),
+                ("<", range.lt),
+                ("<=", range.lte),
+            ];
+            let conditions: Vec<_> = bounds
+                .into_iter()
+                .filter_map(|(op, bound)| Some(format!("{} {op} {:?}", key(k), bound?)))
+                .collect();
+            Some(format!("({})", conditions.join(" and ")))
+        }
+        Filter::And(filters) => {
+            let exprs = filters
+                .iter()
+                .map(milvus_expr)
+                .collect::<Result<Vec<_>, _>>()?;
+            let exprs: Vec<_> = exprs.into_iter().flatten().collect();
+            (!exprs.is_empty()).then(|| format!("({})", exprs.join(" and ")))
+        }
+        Filter::Or(filters) if !filters.is_empty() => {
+            let exprs = filters
+                .iter()
+                .map(milvus_expr)
+                .collect::<Result<Option<Vec<_>>, _>>()?;
+            exprs.map(|exprs| format!("({})", exprs.join(" or ")))
+        }
+        // No value or filter can match, so use an expression false for every document.
+        Filter::In { .. } | Filter::Or(_) => Some(format!(
+            "({DEFAULT_MILVUS_ID_FIELD} < 0 and {DEFAULT_MILVUS_ID_FIELD} >= 0)"
+        )),
+    })
+}
+
+/// Formats a scalar value as a Milvus literal.
+fn milvus_value(value: &Value) -> Result<String, VectorStoreError> {
+    match value {
+        Value::String(_) | Value::Number(_) | Value::Bool(_) => Ok(value.to_string()),
+        _ => Err(VectorStoreError::SearchError(format!(
+            "Milvus can't compare with `{value}`"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alith_core::store::Range;
+
+    #[test]
+    fn test_empty_filters_match_nothing() {
+        let nothing = format!("({DEFAULT_MILVUS_ID_FIELD} < 0 and {DEFAULT_MILVUS_ID_FIELD} >= 0)");
+        assert_eq!(
+            milvus_expr(&Filter::Or(vec![])).unwrap(),
+            Some(nothing.clone())
+        );
+        let filter = Filter::In {
+            key: "tag".to_string(),
+            values: vec![],
+        };
+        assert_eq!(milvus_expr(&filter).unwrap(), Some(nothing.clone()));
+        let filter = Filter::Or(vec![Filter::Or(vec![]), Filter::eq("tag", "a")]);
+        assert_eq!(
+            milvus_expr(&filter).unwrap(),
+            Some(format!(
+                "({nothing} or {DEFAULT_MILVUS_METADATA_FIELD}[\"tag\"] == \"a\")"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_unbounded_ranges_match_numbers() {
+        assert_eq!(
+            milvus_expr(&Filter::range("date", Range::default())).unwrap(),
+            Some(format!(
+                "({DEFAULT_MILVUS_METADATA_FIELD}[\"date\"] >= {:?})",
+                f64::MIN
+            ))
+        );
+    }
+}
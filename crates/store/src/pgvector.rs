@@ -2,7 +2,10 @@ use std::{fmt::Display, sync::Arc};
 
 use alith_core::{
     embeddings::{Embeddings, EmbeddingsData},
-    store::{DocumentId, Storage, TopNResult, TopNResults, VectorStoreError},
+    store::{
+        DocumentId, Filter, Metadata, Storage, StorageDocument, TopNResult, TopNResults,
+        VectorStoreError,
+    },
 };
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
@@ -141,14 +144,54 @@ impl<E: Embeddings> PgVectorStorage<E> {
         &self,
         documents: Vec<EmbeddingsData>,
     ) -> Result<(), VectorStoreError> {
-        for doc in documents {
-            let id = Uuid::new_v4();
+        let documents = documents
+            .into_iter()
+            .map(|doc| (Uuid::new_v4(), doc, Metadata::new()))
+            .collect();
+        self.upsert_documents(documents).await
+    }
+
+    /// Embeds the documents and upserts them under the given IDs.
+    async fn embed_documents(
+        &self,
+        documents: Vec<(Uuid, StorageDocument)>,
+    ) -> Result<(), VectorStoreError> {
+        let texts = documents.iter().map(|(_, doc)| doc.text.clone()).collect();
+        let embeddings = self
+            .embeddings
+            .embed_texts(texts)
+            .await
+            .map_err(VectorStoreError::EmbeddingError)?;
+        let documents = documents
+            .into_iter()
+            .zip(embeddings)
+            .map(|((id, doc), data)| (id, data, doc.metadata))
+            .collect();
+        self.upsert_documents(documents).await
+    }
+
+    /// Replaces the rows of the documents in a transaction, with their metadata.
+    async fn upsert_documents(
+        &self,
+        documents: Vec<(Uuid, EmbeddingsData, Metadata)>,
+    ) -> Result<(), VectorStoreError> {
+        let mut tx = self
+            .pg_pool
+            .begin()
+            .await
+            .map_err(|e| VectorStoreError::DatastoreError(e.into()))?;
+        for (id, doc, metadata) in documents {
             let json_document = serde_json::to_value(&doc.document).unwrap();
             let embedding_text = doc.document;
             let embedding: Vec<f64> = doc.vec;
+            sqlx::query(self.delete_query().as_str())
+                .bind(id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| VectorStoreError::DatastoreError(e.into()))?;
             sqlx::query(
                     format!(
-                        "INSERT INTO {} (id, document, embedded_text, embedding) VALUES ($1, $2, $3, $4)",
+                        "INSERT INTO {} (id, document, embedded_text, embedding, metadata) VALUES ($1, $2, $3, $4, $5)",
                         self.table
                     )
                     .as_str(),
@@ -157,29 +200,47 @@ impl<E: Embeddings> PgVectorStorage<E> {
                 .bind(&json_document)
                 .bind(&embedding_text)
                 .bind(&embedding)
-                .execute(&self.pg_pool)
+                .bind(Value::Object(metadata))
+                .execute(&mut *tx)
                 .await
                 .map_err(|e| VectorStoreError::DatastoreError(e.into()))?;
         }
+        tx.commit()
+            .await
+            .map_err(|e| VectorStoreError::DatastoreError(e.into()))?;
 
         Ok(())
     }
 
-    fn search_query(&self, with_document: bool) -> String {
+    fn search_query(&self, with_document: bool, filter: Option<&str>) -> String {
         let document = if with_document { ", document" } else { "" };
+        let filter = filter
+            .map(|filter| format!("WHERE {filter} "))
+            .unwrap_or_default();
         format!(
             "
             SELECT id{}, distance FROM ( \
               SELECT DISTINCT ON (id) id{}, embedding {} $1 as distance \
               FROM {} \
-              ORDER BY id, distance \
+              {}ORDER BY id, distance \
             ) as d \
             ORDER BY distance \
             LIMIT $2",
-            document, document, self.distance_function, self.table
+            document, document, self.distance_function, self.table, filter
+        )
+    }
+
+    fn get_query(&self) -> String {
+        format!(
+            "SELECT document, metadata FROM {} WHERE id = $1 LIMIT 1",
+            self.table
         )
     }
 
+    fn delete_query(&self) -> String {
+        format!("DELETE FROM {} WHERE id = $1", self.table)
+    }
+
     fn reset_query(&self) -> String {
         format!("TRUNCATE {}", self.table)
     }
@@ -187,22 +248,82 @@ impl<E: Embeddings> PgVectorStorage<E> {
 
 #[async_trait]
 impl<E: Embeddings> Storage for PgVectorStorage<E> {
-    async fn save(&self, value: String) -> Result<(), VectorStoreError> {
-        let embeddings = self
-            .embeddings
-            .embed_texts(vec![value])
+    async fn save_documents(
+        &self,
+        documents: Vec<StorageDocument>,
+    ) -> Result<Vec<DocumentId>, VectorStoreError> {
+        let documents: Vec<_> = documents
+            .into_iter()
+            .map(|doc| (Uuid::new_v4(), doc))
+            .collect();
+        let ids = documents
+            .iter()
+            .map(|(id, _)| DocumentId(id.to_string()))
+            .collect();
+        self.embed_documents(documents).await?;
+        Ok(ids)
+    }
+
+    /// Upserts a document, its ID must be a UUID.
+    async fn upsert(
+        &self,
+        id: &DocumentId,
+        document: StorageDocument,
+    ) -> Result<(), VectorStoreError> {
+        self.embed_documents(vec![(uuid(id)?, document)]).await
+    }
+
+    async fn get(&self, id: &DocumentId) -> Result<Option<StorageDocument>, VectorStoreError> {
+        let row: Option<(Value, Option<Value>)> = sqlx::query_as(self.get_query().as_str())
+            .bind(uuid(id)?)
+            .fetch_optional(&self.pg_pool)
             .await
-            .map_err(VectorStoreError::EmbeddingError)?;
-        self.insert_documents(embeddings).await?;
+            .map_err(|e| VectorStoreError::DatastoreError(Box::new(e)))?;
+        row.map(|(document, metadata)| {
+            Ok::<_, VectorStoreError>(StorageDocument {
+                text: serde_json::from_value(document)?,
+                metadata: match metadata {
+                    Some(Value::Object(metadata)) => metadata,
+                    _ => Metadata::new(),
+                },
+            })
+        })
+        .transpose()
+    }
+
+    async fn delete(&self, id: &DocumentId) -> Result<(), VectorStoreError> {
+        sqlx::query(self.delete_query().as_str())
+            .bind(uuid(id)?)
+            .execute(&self.pg_pool)
+            .await
+            .map_err(|e| VectorStoreError::DatastoreError(Box::new(e)))?;
         Ok(())
     }
 
-    async fn search(&self, query: &str, limit: usize, _threshold: f32) -> TopNResults {
+    async fn search_filtered(
+        &self,
+        query: &str,
+        limit: usize,
+        _threshold: f32,
+        filter: Option<&Filter>,
+    ) -> TopNResults {
         let embedded_query = self.generate_query_vector(query).await?;
+        // The filter values are bound after the query vector and the limit.
+        let mut binds = Vec::new();
+        let filter = filter.map(|filter| sql_filter(filter, &mut binds, 3));
+        let search_query = self.search_query(true, filter.as_deref());
 
-        let rows: Vec<SearchResult> = sqlx::query_as(self.search_query(true).as_str())
+        let mut query = sqlx::query_as(search_query.as_str())
             .bind(embedded_query)
-            .bind(limit as i64)
+            .bind(limit as i64);
+        for bind in binds {
+            query = match bind {
+                FilterBind::Json(value) => query.bind(value),
+                FilterBind::Text(text) => query.bind(text),
+                FilterBind::Number(number) => query.bind(number),
+            };
+        }
+        let rows: Vec<SearchResult> = query
             .fetch_all(&self.pg_pool)
             .await
             .map_err(|e| VectorStoreError::DatastoreError(Box::new(e)))?;
@@ -221,3 +342,125 @@ impl<E: Embeddings> Storage for PgVectorStorage<E> {
         Ok(())
     }
 }
+
+fn uuid(id: &DocumentId) -> Result<Uuid, VectorStoreError> {
+    Uuid::parse_str(&id.0).map_err(|e| VectorStoreError::DatastoreError(Box::new(e)))
+}
+
+/// A value bound to a placeholder of a filter.
+enum FilterBind {
+    Json(Value),
+    Text(String),
+    Number(f64),
+}
+
+/// Translates a filter into a SQL condition on the `metadata` JSONB column, with the
+/// placeholders numbered from `first`.
+fn sql_filter(filter: &Filter, binds: &mut Vec<FilterBind>, first: usize) -> String {
+    let mut placeholder = |bind: FilterBind| {
+        binds.push(bind);
+        format!("${}", first + binds.len() - 1)
+    };
+    match filter {
+        Filter::Eq { key, value } => {
+            let mut object = Metadata::new();
+            object.insert(key.clone(), value.clone());
+            format!(
+                "metadata @> {}",
+                placeholder(FilterBind::Json(object.into()))
+            )
+        }
+        Filter::In { key, values } => sql_filter(
+            &Filter::Or(
+                values
+                    .iter()
+                    .map(|value| Filter::eq(key.as_str(), value.clone()))
+                    .collect(),
+            ),
+            binds,
+            first,
+        ),
+        Filter::Range { key, range } => {
+            let key = placeholder(FilterBind::Text(key.clone()));
+            // Non numeric values compare as NULL instead of failing the cast.
+            let value = format!(
+                "(CASE WHEN jsonb_typeof(metadata->{key}) = 'number' \
+                 THEN (metadata->>{key})::double precision END)"
+            );
+            let bounds = [
+                (">", range.gt),
+                (">=", range.gte),
+                ("<", range.lt),
+                ("<=", range.lte),
+            ];
+            let conditions: Vec<_> = bounds
+                .into_iter()
+                .filter_map(|(op, bound)| {
+                    let bound = placeholder(FilterBind::Number(bound?));
+                    Some(format!("{value} {op} {bound}"))
+                })
+                .collect();
+            match conditions.is_empty() {
+                true => format!("{value} IS NOT NULL"),
+                false => format!("({})", conditions.join(" AND ")),
+            }
+        }
+        Filter::And(filters) if filters.is_empty() => "TRUE".to_string(),
+        Filter::Or(filters) if filters.is_empty() => "FALSE".to_string(),
+        Filter::And(filters) | Filter::Or(filters) => {
+            let op = match filter {
+                Filter::And(_) => " AND ",
+                _ => " OR ",
+            };
+            let conditions: Vec<_> = filters
+                .iter()
+                .map(|filter| sql_filter(filter, binds, first))
+                .collect();
+            format!("({})", conditions.join(op))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alith_core::store::Range;
+    use serde_json::json;
+
+    #[test]
+    fn test_sql_filter() {
+        let filter = Filter::eq("tenant", "acme")
+            .and(Filter::range("date", Range::default().gte(1.0).lt(2.0)))
+            .and(Filter::any_of("tag", ["a", "b"]));
+        let mut binds = Vec::new();
+        // The query binds the embedding and the limit as `$1` and `$2`.
+        let sql = sql_filter(&filter, &mut binds, 3);
+        let date = "(CASE WHEN jsonb_typeof(metadata->$4) = 'number' \
+                    THEN (metadata->>$4)::double precision END)";
+        assert_eq!(
+            sql,
+            format!(
+                "(metadata @> $3 AND ({date} >= $5 AND {date} < $6) \
+                 AND (metadata @> $7 OR metadata @> $8))"
+            )
+        );
+        assert!(
+            matches!(&binds[0], FilterBind::Json(value) if *value == json!({"tenant": "acme"}))
+        );
+        assert!(matches!(&binds[1], FilterBind::Text(key) if key == "date"));
+        assert!(matches!(binds[2], FilterBind::Number(bound) if bound == 1.0));
+        assert!(matches!(binds[3], FilterBind::Number(bound) if bound == 2.0));
+        assert!(matches!(&binds[5], FilterBind::Json(value) if *value == json!({"tag": "b"})));
+        assert_eq!(binds.len(), 6);
+
+        let mut binds = Vec::new();
+        let sql = sql_filter(&Filter::range("date", Range::default()), &mut binds, 3);
+        assert_eq!(
+            sql,
+            "(CASE WHEN jsonb_typeof(metadata->$3) = 'number' \
+             THEN (metadata->>$3)::double precision END) IS NOT NULL"
+        );
+        let sql = sql_filter(&Filter::Or(vec![]), &mut Vec::new(), 3);
+        assert_eq!(sql, "FALSE");
+    }
+}
@@ -1,24 +1,24 @@
 use alith_core::{
     embeddings::{Embeddings, EmbeddingsData},
-    store::{DocumentId, Storage, TopNResults, VectorStoreError},
+    store::{
+        DocumentId, Filter, Metadata, Storage, StorageDocument, TopNResults, VectorStoreError,
+    },
 };
 use async_trait::async_trait;
 pub use qdrant_client::{
     Qdrant as QdrantClient, QdrantBuilder, QdrantError,
     qdrant::{
-        CreateCollectionBuilder, DeletePointsBuilder, Distance, PointId, PointStruct, Query,
-        QueryPoints, QueryPointsBuilder, UpsertPointsBuilder, VectorParamsBuilder,
+        Condition, CreateCollectionBuilder, DeletePointsBuilder, Distance, Filter as QdrantFilter,
+        GetPointsBuilder, PointId, PointStruct, PointsIdsList, Query, QueryPoints,
+        QueryPointsBuilder, Range as QdrantRange, UpsertPointsBuilder, VectorParamsBuilder,
         point_id::PointIdOptions,
     },
 };
-use serde_json::Map;
-use std::sync::{
-    Arc,
-    atomic::{AtomicUsize, Ordering},
-};
+use serde_json::{Map, Value};
+use std::sync::Arc;
+use uuid::Uuid;
 
 pub const DEFAULT_COLLECTION_NAME: &str = "alith";
-static ID: AtomicUsize = AtomicUsize::new(0);
 
 /// Qdrant storage implementation.
 pub struct QdrantStorage<E: Embeddings> {
@@ -68,32 +68,35 @@ impl<E: Embeddings> QdrantStorage<E> {
         Ok(vec.iter().map(|&x| x as f32).collect())
     }
 
-    /// Convert documents to points
+    /// Convert documents to points with random UUIDs.
     pub fn documents_to_points(
         documents: impl IntoIterator<Item = EmbeddingsData>,
     ) -> Vec<PointStruct> {
         documents
             .into_iter()
             .map(|data| {
-                let vec: Vec<f32> = data.vec.iter().map(|&x| x as f32).collect();
-                ID.fetch_add(1, Ordering::SeqCst);
-                let mut object = Map::new();
-                object.insert("document".to_string(), data.document.into());
-                PointStruct::new(ID.load(Ordering::SeqCst) as u64, vec, object)
+                let id = DocumentId(Uuid::new_v4().to_string());
+                document_to_point(&id, data, Metadata::new())
             })
             .collect()
     }
-}
 
-#[async_trait]
-impl<E: Embeddings> Storage for QdrantStorage<E> {
-    async fn save(&self, value: String) -> Result<(), VectorStoreError> {
+    /// Embeds the documents and upserts them under the given IDs.
+    async fn upsert_documents(
+        &self,
+        documents: Vec<(DocumentId, StorageDocument)>,
+    ) -> Result<(), VectorStoreError> {
+        let texts = documents.iter().map(|(_, doc)| doc.text.clone()).collect();
         let embeddings = self
             .embeddings
-            .embed_texts(vec![value])
+            .embed_texts(texts)
             .await
             .map_err(VectorStoreError::EmbeddingError)?;
-        let points = Self::documents_to_points(embeddings);
+        let points = documents
+            .into_iter()
+            .zip(embeddings)
+            .map(|((id, document), data)| document_to_point(&id, data, document.metadata))
+            .collect::<Vec<_>>();
 
         self.client
             .upsert_points(UpsertPointsBuilder::new(DEFAULT_COLLECTION_NAME, points))
@@ -102,14 +105,88 @@ impl<E: Embeddings> Storage for QdrantStorage<E> {
 
         Ok(())
     }
+}
+
+#[async_trait]
+impl<E: Embeddings> Storage for QdrantStorage<E> {
+    async fn save_documents(
+        &self,
+        documents: Vec<StorageDocument>,
+    ) -> Result<Vec<DocumentId>, VectorStoreError> {
+        let documents: Vec<_> = documents
+            .into_iter()
+            .map(|doc| (DocumentId(Uuid::new_v4().to_string()), doc))
+            .collect();
+        let ids = documents.iter().map(|(id, _)| id.clone()).collect();
+        self.upsert_documents(documents).await?;
+        Ok(ids)
+    }
+
+    /// Upserts a document, its ID must be an unsigned integer or a UUID.
+    async fn upsert(
+        &self,
+        id: &DocumentId,
+        document: StorageDocument,
+    ) -> Result<(), VectorStoreError> {
+        self.upsert_documents(vec![(id.clone(), document)]).await
+    }
+
+    async fn get(&self, id: &DocumentId) -> Result<Option<StorageDocument>, VectorStoreError> {
+        let points = self
+            .client
+            .get_points(
+                GetPointsBuilder::new(DEFAULT_COLLECTION_NAME, vec![point_id(id)])
+                    .with_payload(true),
+            )
+            .await
+            .map_err(|err| VectorStoreError::DatastoreError(Box::new(err)))?
+            .result;
+
+        Ok(points.into_iter().next().map(|mut point| {
+            let text = point
+                .payload
+                .remove("document")
+                .and_then(|v| v.as_str().cloned())
+                .unwrap_or_default();
+            let metadata = match point.payload.remove("metadata").map(|v| v.into_json()) {
+                Some(Value::Object(metadata)) => metadata,
+                _ => Metadata::new(),
+            };
+            StorageDocument { text, metadata }
+        }))
+    }
 
-    async fn search(&self, query: &str, limit: usize, threshold: f32) -> TopNResults {
+    async fn delete(&self, id: &DocumentId) -> Result<(), VectorStoreError> {
+        self.client
+            .delete_points(
+                DeletePointsBuilder::new(DEFAULT_COLLECTION_NAME)
+                    .points(PointsIdsList {
+                        ids: vec![point_id(id)],
+                    })
+                    .wait(true),
+            )
+            .await
+            .map_err(|err| VectorStoreError::DatastoreError(Box::new(err)))?;
+
+        Ok(())
+    }
+
+    async fn search_filtered(
+        &self,
+        query: &str,
+        limit: usize,
+        threshold: f32,
+        filter: Option<&Filter>,
+    ) -> TopNResults {
         let query = Query::new_nearest(self.generate_query_vector(query).await?);
-        let params = QueryPointsBuilder::new(DEFAULT_COLLECTION_NAME)
+        let mut params = QueryPointsBuilder::new(DEFAULT_COLLECTION_NAME)
             .score_threshold(threshold)
             .with_payload(true)
             .query(query)
             .limit(limit as u64);
+        if let Some(filter) = filter {
+            params = params.filter(QdrantFilter::must([qdrant_condition(filter)?]));
+        }
 
         let points = self
             .client
@@ -121,8 +198,8 @@ impl<E: Embeddings> Storage for QdrantStorage<E> {
         let result = points
             .into_iter()
             .flat_map(|point| {
-                let id_usize =
-                    id_usize(point.id.ok_or_else(|| {
+                let id =
+                    document_id(point.id.ok_or_else(|| {
                         VectorStoreError::DatastoreError("Missing point ID".into())
                     })?)?;
                 let document = point
@@ -131,7 +208,7 @@ impl<E: Embeddings> Storage for QdrantStorage<E> {
                     .map(|v| v.as_str().cloned().unwrap_or_default())
                     .unwrap_or_default();
                 Ok::<(DocumentId, std::string::String, f32), VectorStoreError>((
-                    DocumentId(id_usize.to_string()),
+                    id,
                     document,
                     point.score,
                 ))
@@ -150,11 +227,140 @@ impl<E: Embeddings> Storage for QdrantStorage<E> {
     }
 }
 
-fn id_usize(point_id: PointId) -> Result<usize, VectorStoreError> {
+/// Converts a document into a point, with its text and metadata as payload.
+fn document_to_point(id: &DocumentId, data: EmbeddingsData, metadata: Metadata) -> PointStruct {
+    let vec: Vec<f32> = data.vec.iter().map(|&x| x as f32).collect();
+    let mut object = Map::new();
+    object.insert("document".to_string(), data.document.into());
+    object.insert("metadata".to_string(), Value::Object(metadata));
+    PointStruct::new(point_id(id), vec, object)
+}
+
+/// Converts a document ID into a numeric point ID when it is a number, or a UUID.
+fn point_id(id: &DocumentId) -> PointId {
+    match id.0.parse::<u64>() {
+        Ok(num) => num.into(),
+        Err(_) => id.0.clone().into(),
+    }
+}
+
+fn document_id(point_id: PointId) -> Result<DocumentId, VectorStoreError> {
     match point_id.point_id_options {
-        Some(PointIdOptions::Num(num)) => Ok(num as usize),
-        _ => Err(VectorStoreError::DatastoreError(
+        Some(PointIdOptions::Num(num)) => Ok(DocumentId(num.to_string())),
+        Some(PointIdOptions::Uuid(uuid)) => Ok(DocumentId(uuid)),
+        None => Err(VectorStoreError::DatastoreError(
             "Invalid point ID format".into(),
         )),
     }
 }
+
+/// Translates a filter into a condition on the metadata payload.
+fn qdrant_condition(filter: &Filter) -> Result<Condition, VectorStoreError> {
+    let conditions = |filters: &[Filter]| {
+        filters
+            .iter()
+            .map(qdrant_condition)
+            .collect::<Result<Vec<_>, _>>()
+    };
+    Ok(match filter {
+        Filter::Eq { key, value } => match_condition(key, value)?,
+        Filter::In { key, values } => {
+            let strings: Option<Vec<String>> = values
+                .iter()
+                .map(|value| value.as_str().map(str::to_string))
+                .collect();
+            let integers: Option<Vec<i64>> = values.iter().map(Value::as_i64).collect();
+            match (strings, integers) {
+                (Some(strings), _) if !strings.is_empty() => {
+                    Condition::matches(payload_key(key), strings)
+                }
+                (_, Some(integers)) if !integers.is_empty() => {
+                    Condition::matches(payload_key(key), integers)
+                }
+                _ => qdrant_condition(&Filter::Or(
+                    values
+                        .iter()
+                        .map(|value| Filter::eq(key.as_str(), value.clone()))
+                        .collect(),
+                ))?,
+            }
+        }
+        Filter::Range { key, range } => {
+            let range = range.bounded();
+            Condition::range(
+                payload_key(key),
+                QdrantRange {
+                    gt: range.gt,
+                    gte: range.gte,
+                    lt: range.lt,
+                    lte: range.lte,
+                },
+            )
+        }
+        Filter::And(filters) => QdrantFilter::must(conditions(filters)?).into(),
+        // Qdrant ignores an empty `should`, so match no point ID instead.
+        Filter::Or(filters) if filters.is_empty() => Condition::has_id(Vec::<PointId>::new()),
+        Filter::Or(filters) => QdrantFilter::should(conditions(filters)?).into(),
+    })
+}
+
+/// Matches a value exactly, or a float with an inclusive range.
+fn match_condition(key: &str, value: &Value) -> Result<Condition, VectorStoreError> {
+    match value {
+        Value::String(string) => Ok(Condition::matches(payload_key(key), string.clone())),
+        Value::Bool(boolean) => Ok(Condition::matches(payload_key(key), *boolean)),
+        Value::Number(number) => match number.as_i64() {
+            Some(integer) => Ok(Condition::matches(payload_key(key), integer)),
+            None => Ok(Condition::range(
+                payload_key(key),
+                QdrantRange {
+                    gte: number.as_f64(),
+                    lte: number.as_f64(),
+                    ..Default::default()
+                },
+            )),
+        },
+        _ => Err(VectorStoreError::SearchError(format!(
+            "Qdrant can't match `{key}` with `{value}`"
+        ))),
+    }
+}
+
+fn payload_key(key: &str) -> String {
+    format!("metadata.{key}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alith_core::store::Range;
+
+    #[test]
+    fn test_empty_filters_match_nothing() {
+        let nothing = Condition::has_id(Vec::<PointId>::new());
+        assert_eq!(qdrant_condition(&Filter::Or(vec![])).unwrap(), nothing);
+        let filter = Filter::In {
+            key: "tag".to_string(),
+            values: vec![],
+        };
+        assert_eq!(qdrant_condition(&filter).unwrap(), nothing);
+        assert_eq!(
+            qdrant_condition(&Filter::And(vec![Filter::Or(vec![])])).unwrap(),
+            QdrantFilter::must([nothing]).into()
+        );
+    }
+
+    #[test]
+    fn test_unbounded_ranges_match_numbers() {
+        assert_eq!(
+            qdrant_condition(&Filter::range("date", Range::default())).unwrap(),
+            Condition::range(
+                payload_key("date"),
+                QdrantRange {
+                    gte: Some(f64::MIN),
+                    ..Default::default()
+                }
+            )
+        );
+    }
+}
@@ -102,9 +102,10 @@ impl DelegateAgent {
             },
             tools,
         );
-        let history = unsafe {
-            std::mem::transmute::<Vec<Message>, Vec<alith::core::chat::Message>>(history)
-        };
+        let history = history
+            .into_iter()
+            .map(|m| alith::core::chat::Message::new(m.role, m.content))
+            .collect();
         agent.preamble = self.preamble.clone();
         let result = GLOBAL_RUNTIME.block_on(async {
             if !self.mcp_config_path.is_empty() {
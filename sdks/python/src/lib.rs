@@ -85,9 +85,13 @@ impl DelegateAgent {
                     .map_err(TaskError::MCPError)?;
             }
             self.agent
-                .chat(prompt, unsafe {
-                    std::mem::transmute::<Vec<Message>, Vec<alith::core::chat::Message>>(history)
-                })
+                .chat(
+                    prompt,
+                    history
+                        .into_iter()
+                        .map(|m| alith::core::chat::Message::new(m.role, m.content))
+                        .collect(),
+                )
                 .await
         });
         result.map_err(|e| PyErr::new::<PyException, _>(e.to_string()))